@@ -121,3 +121,31 @@ fn bench_smart_really_long_file_with_unclosed_quote(b: &mut Bencher) {
         b.iter(|| parinfer_rust::run_parinfer(options.as_ptr()));
     }
 }
+
+fn document_edit(mode: parinfer_rust::api::Mode, b: &mut Bencher) {
+    use parinfer_rust::api::{Change, Document, Options};
+
+    let line_no = 1004;
+    let line = REALLY_LONG_FILE.lines().nth(line_no).unwrap();
+    let x = line.chars().count();
+    let mut document = Document::new(mode, String::from(REALLY_LONG_FILE), Options::default());
+    document.process();
+    b.iter(|| {
+        document.edit(Change { x, line_no, old_text: String::from(""), new_text: String::from(" ") }).unwrap();
+        document.move_cursor(x + 1, line_no);
+        document.process();
+        document.edit(Change { x, line_no, old_text: String::from(" "), new_text: String::from("") }).unwrap();
+        document.move_cursor(x, line_no);
+        document.process();
+    });
+}
+
+#[bench]
+fn bench_smart_really_long_file_document_edit(b: &mut Bencher) {
+    document_edit(parinfer_rust::api::Mode::Smart, b);
+}
+
+#[bench]
+fn bench_paren_really_long_file_document_edit(b: &mut Bencher) {
+    document_edit(parinfer_rust::api::Mode::Paren, b);
+}
@@ -0,0 +1,17 @@
+/* tslint:disable */
+/* eslint-disable */
+export const memory: WebAssembly.Memory;
+export const free_document: (a: number) => void;
+export const indentMode: (a: number, b: number, c: number) => any;
+export const new_document: (a: number, b: number) => number;
+export const parenMode: (a: number, b: number, c: number) => any;
+export const run_parinfer: (a: number, b: number) => [number, number];
+export const smartMode: (a: number, b: number, c: number) => any;
+export const update_document: (a: number, b: number, c: number) => [number, number];
+export const __wbindgen_malloc: (a: number, b: number) => number;
+export const __wbindgen_realloc: (a: number, b: number, c: number, d: number) => number;
+export const __wbindgen_exn_store: (a: number) => void;
+export const __externref_table_alloc: () => number;
+export const __wbindgen_externrefs: WebAssembly.Table;
+export const __wbindgen_free: (a: number, b: number, c: number) => void;
+export const __wbindgen_start: () => void;
@@ -0,0 +1,129 @@
+/* tslint:disable */
+/* eslint-disable */
+
+export type Mode = "indent" | "paren" | "smart";
+
+export type PositionEncoding = "graphemes" | "utf16" | "utf8-bytes" | "codepoints";
+
+export interface Change {
+    x: number;
+    lineNo: number;
+    oldText: string;
+    newText: string;
+}
+
+export interface Cursor {
+    x: number;
+    lineNo: number;
+    selectionStartLine?: number | null;
+}
+
+export interface Options {
+    cursorX?: number | null;
+    cursorLine?: number | null;
+    prevCursorX?: number | null;
+    prevCursorLine?: number | null;
+    prevText?: string | null;
+    selectionStartLine?: number | null;
+    cursors?: Cursor[];
+    prevCursors?: Cursor[];
+    trackPositions?: [number, number][];
+    changes?: Change[];
+    partialResult?: boolean;
+    forceBalance?: boolean;
+    returnParens?: boolean;
+    collectErrors?: boolean;
+    returnEdits?: boolean;
+    commentChar?: string;
+    commentStrings?: string[];
+    stringDelimiters?: string[];
+    parenPairs?: string[];
+    tabWidth?: number;
+    preserveTabs?: boolean;
+    positionEncoding?: PositionEncoding;
+    lispVlineSymbols?: boolean;
+    lispBlockComments?: boolean;
+    guileBlockComments?: boolean;
+    schemeSexpComments?: boolean;
+    janetLongStrings?: boolean;
+}
+
+export interface ErrorExtra {
+    name: string;
+    lineNo: number;
+    x: number;
+}
+
+export interface ParinferError {
+    name: string;
+    message: string;
+    x: number;
+    lineNo: number;
+    inputX: number;
+    inputLineNo: number;
+    extra: ErrorExtra | null;
+}
+
+export interface TabStop {
+    ch: string;
+    x: number;
+    lineNo: number;
+    argX: number | null;
+}
+
+export interface ParenTrail {
+    lineNo: number;
+    startX: number;
+    endX: number;
+}
+
+export interface Closer {
+    lineNo: number;
+    x: number;
+    ch: string;
+    trail: ParenTrail | null;
+}
+
+export interface Paren {
+    lineNo: number;
+    ch: string;
+    x: number;
+    indentDelta: number;
+    maxChildIndent: number | null;
+    argX: number | null;
+    inputLineNo: number;
+    inputX: number;
+    closer: Closer | null;
+    children: Paren[];
+}
+
+export interface Answer {
+    text: string;
+    success: boolean;
+    error: ParinferError | null;
+    errors: ParinferError[];
+    cursorX: number | null;
+    cursorLine: number | null;
+    cursors: Cursor[];
+    trackedPositions: [number, number][];
+    edits: Change[];
+    tabStops: TabStop[];
+    parenTrails: ParenTrail[];
+    parens: Paren[];
+}
+
+
+
+export function free_document(handle: number): void;
+
+export function indentMode(text: string, options?: Options | null): Answer;
+
+export function new_document(input: string): number;
+
+export function parenMode(text: string, options?: Options | null): Answer;
+
+export function run_parinfer(input: string): string;
+
+export function smartMode(text: string, options?: Options | null): Answer;
+
+export function update_document(handle: number, input: string): string;
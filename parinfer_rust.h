@@ -0,0 +1,395 @@
+/*
+ * C interface to parinfer-rust.  Generated by cbindgen from
+ * src/c_wrapper.rs; run `make header` rather than editing it.
+ *
+ * Requests and answers are JSON, in the same format as the command-line
+ * `--input-format=json` and `--output-format=json`:
+ *
+ *   {"mode": "smart", "text": "(def x", "options": {"cursorX": 6, "cursorLine": 0}}
+ *
+ * parinfer_process() takes the text and options directly instead, for hosts
+ * which would rather not produce and parse JSON.
+ *
+ * Link with -lparinfer_rust.
+ */
+
+#ifndef PARINFER_RUST_H
+#define PARINFER_RUST_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// The version of the C API described by `parinfer_rust.h`.  It changes
+// only when existing functions change, not when new ones are added.
+#define PARINFER_API_VERSION 1
+
+// Modes for `parinfer_process`.
+#define PARINFER_MODE_INDENT 0
+
+#define PARINFER_MODE_PAREN 1
+
+#define PARINFER_MODE_SMART 2
+
+// What x positions count, for `ParinferOptions::position_encoding`.
+// Graphemes are display columns.
+#define PARINFER_POSITION_GRAPHEMES 0
+
+#define PARINFER_POSITION_UTF16 1
+
+#define PARINFER_POSITION_UTF8_BYTES 2
+
+#define PARINFER_POSITION_CODEPOINTS 3
+
+// A buffer whose text and cursor are remembered between runs.
+typedef struct ParinferDocument ParinferDocument;
+
+// The answer from `parinfer_process`, read with the `parinfer_answer_*`
+// functions and released with `parinfer_answer_free`.
+typedef struct ParinferAnswer ParinferAnswer;
+
+// A change to the text, in `ParinferOptions::changes` or an answer's
+// edits.  Its texts are UTF-8, as pointers and lengths in bytes.
+typedef struct ParinferChange {
+  int64_t x;
+  int64_t line_no;
+  const char *old_text;
+  size_t old_text_length;
+  const char *new_text;
+  size_t new_text_length;
+} ParinferChange;
+
+// The options for `parinfer_process`.  Positions and lines count from 0
+// and are `-1` when absent, and text is a pointer and a length in bytes
+// rather than a NUL-terminated string.  Fill it with
+// `parinfer_options_init` before setting the fields you need.
+typedef struct ParinferOptions {
+  int64_t cursor_x;
+  int64_t cursor_line;
+  int64_t prev_cursor_x;
+  int64_t prev_cursor_line;
+  int64_t selection_start_line;
+  // The text before the user's changes as UTF-8, or null.
+  const char *prev_text;
+  size_t prev_text_length;
+  // The user's changes, `change_count` of them, which smart mode uses
+  // instead of comparing the text with `prev_text`.
+  const struct ParinferChange *changes;
+  size_t change_count;
+  // `comment_string_count` NUL-terminated strings which start comments,
+  // or null to use `comment_char`.
+  const char *const *comment_strings;
+  size_t comment_string_count;
+  // `string_delimiter_count` NUL-terminated strings, or null for `"`.
+  const char *const *string_delimiters;
+  size_t string_delimiter_count;
+  // `paren_pair_count` NUL-terminated open and close characters, such
+  // as `"()"`, or null for `()`, `[]` and `{}`.
+  const char *const *paren_pairs;
+  size_t paren_pair_count;
+  // A Unicode code point.
+  uint32_t comment_char;
+  uint32_t tab_width;
+  uint32_t position_encoding;
+  bool force_balance;
+  bool partial_result;
+  // Whether the answer has parens, read with `parinfer_answer_paren`.
+  bool return_parens;
+  // Whether to carry on past errors, which are read with
+  // `parinfer_answer_collected_error`.
+  bool collect_errors;
+  // Whether the answer has edits, read with `parinfer_answer_edit`.
+  bool return_edits;
+  bool preserve_tabs;
+  bool lisp_vline_symbols;
+  bool lisp_block_comments;
+  bool guile_block_comments;
+  bool scheme_sexp_comments;
+  bool janet_long_strings;
+} ParinferOptions;
+
+// An error from a `ParinferAnswer`.  The strings are NUL-terminated and
+// last as long as the answer.
+typedef struct ParinferError {
+  // Such as `"unclosed-paren"`.
+  const char *name;
+  const char *message;
+  int64_t line_no;
+  int64_t x;
+  int64_t input_line_no;
+  int64_t input_x;
+} ParinferError;
+
+typedef struct ParinferTabStop {
+  // The open-paren, as a Unicode code point.
+  uint32_t ch;
+  int64_t x;
+  int64_t line_no;
+  // `-1` when there is no argument after the paren on its line.
+  int64_t arg_x;
+} ParinferTabStop;
+
+typedef struct ParinferParenTrail {
+  int64_t line_no;
+  int64_t start_x;
+  int64_t end_x;
+} ParinferParenTrail;
+
+// A paren from a `ParinferAnswer`.  They are listed with each one before
+// its children.
+typedef struct ParinferParen {
+  // The open-paren, as a Unicode code point.
+  uint32_t ch;
+  int64_t line_no;
+  int64_t x;
+  int64_t input_line_no;
+  int64_t input_x;
+  int64_t indent_delta;
+  // `-1` when it is not known.
+  int64_t max_child_indent;
+  // `-1` when there is no argument after the paren on its line.
+  int64_t arg_x;
+  // The close-paren, as a Unicode code point, or 0 when there is none.
+  uint32_t closer_ch;
+  // `-1` when there is no close-paren.
+  int64_t closer_line_no;
+  int64_t closer_x;
+  // The index of the paren this one is inside, or `-1` at the top level.
+  int64_t parent;
+} ParinferParen;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Runs a JSON request, returning the answer as JSON.  The answer belongs to
+// the library and is overwritten by the next call on the same thread;
+// `parinfer_run` returns one the caller owns instead.
+//
+// # Safety
+//
+// `json` must be a valid, NUL-terminated string.
+const char *run_parinfer(const char *json);
+
+// The library's version, such as `"0.4.3"`.
+const char *parinfer_version(void);
+
+// The version of the C API, `PARINFER_API_VERSION`.
+uint32_t parinfer_api_version(void);
+
+// Runs a JSON request, as `run_parinfer` does, but returns an answer the
+// caller owns and must release with `parinfer_free`.
+//
+// # Safety
+//
+// `json` must be null or a valid, NUL-terminated string.
+char *parinfer_run(const char *json);
+
+// Releases an answer returned by `parinfer_run`.
+//
+// # Safety
+//
+// `answer` must be null or come from `parinfer_run`, and must not be used
+// afterwards.
+void parinfer_free(char *answer);
+
+// Runs a JSON request, writing the NUL-terminated answer into the caller's
+// `buffer` of `size` bytes.  Returns the size the answer needs, including
+// the NUL.  When that is more than `size`, nothing but an empty string is
+// written, and the request can be run again with a larger buffer.
+//
+// # Safety
+//
+// `json` must be null or a valid, NUL-terminated string, and `buffer` must
+// point to at least `size` writable bytes.
+size_t parinfer_run_into(const char *json, char *buffer, size_t size);
+
+// Creates a document from a request in the same JSON format as
+// `run_parinfer` takes.  Returns null if the request can't be read.  The
+// document must be released with `parinfer_document_free`.
+//
+// # Safety
+//
+// `json` must be a valid, NUL-terminated string.
+struct ParinferDocument *parinfer_document_new(const char *json);
+
+// Applies a JSON update of the form
+// `{"changes": [...], "cursorX": 1, "cursorLine": 0}` to the document and
+// processes it.  Returns the answer as JSON, as `run_parinfer` does.
+//
+// # Safety
+//
+// `document` must come from `parinfer_document_new` and not have been
+// freed, and `json` must be a valid, NUL-terminated string.
+const char *parinfer_document_update(struct ParinferDocument *document, const char *json);
+
+// # Safety
+//
+// `document` must be null or come from `parinfer_document_new`, and must
+// not be used afterwards.
+void parinfer_document_free(struct ParinferDocument *document);
+
+// Fills `options` with the defaults: no cursor or previous text, `;`
+// comments and a tab width of 2.
+//
+// # Safety
+//
+// `options` must point to a writable `ParinferOptions`.
+void parinfer_options_init(struct ParinferOptions *options);
+
+// Runs `mode` on the `length` bytes of UTF-8 at `text`, without going
+// through JSON.  A null `options` means the defaults.  The answer is never
+// null; problems with the arguments are reported as its error.  It must be
+// released with `parinfer_answer_free`.
+//
+// # Safety
+//
+// `text` must point to `length` readable bytes, and `options` must be null
+// or point to a `ParinferOptions` whose pointers are null or point to as
+// many readable bytes or items as their lengths and counts say.
+struct ParinferAnswer *parinfer_process(uint32_t mode,
+                                        const char *text,
+                                        size_t length,
+                                        const struct ParinferOptions *options);
+
+// Whether parinfer could process the text.
+//
+// # Safety
+//
+// `answer` must come from `parinfer_process` and not have been freed, as
+// for all the `parinfer_answer_*` functions.
+bool parinfer_answer_success(const struct ParinferAnswer *answer);
+
+// The answer's text, which is not NUL-terminated, storing its length in
+// bytes in `length`.  It lasts as long as the answer.
+//
+// # Safety
+//
+// `answer` must be valid, and `length` must point to a writable `size_t`.
+const char *parinfer_answer_text(const struct ParinferAnswer *answer, size_t *length);
+
+// The cursor's new x position, or `-1` without a cursor.
+//
+// # Safety
+//
+// `answer` must be valid.
+int64_t parinfer_answer_cursor_x(const struct ParinferAnswer *answer);
+
+// The cursor's new line, or `-1` without a cursor.
+//
+// # Safety
+//
+// `answer` must be valid.
+int64_t parinfer_answer_cursor_line(const struct ParinferAnswer *answer);
+
+// Stores the answer's error in `error` and returns true, or returns false
+// if there was none.
+//
+// # Safety
+//
+// `answer` must be valid, and `error` must point to a writable
+// `ParinferError`.
+bool parinfer_answer_error(const struct ParinferAnswer *answer, struct ParinferError *error);
+
+// The number of tab stops in the answer.
+//
+// # Safety
+//
+// `answer` must be valid.
+size_t parinfer_answer_tab_stop_count(const struct ParinferAnswer *answer);
+
+// Stores tab stop `index` in `tab_stop` and returns true, or returns false
+// if there are not that many.
+//
+// # Safety
+//
+// `answer` must be valid, and `tab_stop` must point to a writable
+// `ParinferTabStop`.
+bool parinfer_answer_tab_stop(const struct ParinferAnswer *answer,
+                              size_t index,
+                              struct ParinferTabStop *tab_stop);
+
+// The number of paren trails in the answer.
+//
+// # Safety
+//
+// `answer` must be valid.
+size_t parinfer_answer_paren_trail_count(const struct ParinferAnswer *answer);
+
+// Stores paren trail `index` in `paren_trail` and returns true, or returns
+// false if there are not that many.
+//
+// # Safety
+//
+// `answer` must be valid, and `paren_trail` must point to a writable
+// `ParinferParenTrail`.
+bool parinfer_answer_paren_trail(const struct ParinferAnswer *answer,
+                                 size_t index,
+                                 struct ParinferParenTrail *paren_trail);
+
+// The number of errors collected with `collect_errors`.
+//
+// # Safety
+//
+// `answer` must be valid.
+size_t parinfer_answer_collected_error_count(const struct ParinferAnswer *answer);
+
+// Stores collected error `index` in `error` and returns true, or returns
+// false if there are not that many.
+//
+// # Safety
+//
+// `answer` must be valid, and `error` must point to a writable
+// `ParinferError`.
+bool parinfer_answer_collected_error(const struct ParinferAnswer *answer,
+                                     size_t index,
+                                     struct ParinferError *error);
+
+// The number of edits in the answer, with `return_edits`.
+//
+// # Safety
+//
+// `answer` must be valid.
+size_t parinfer_answer_edit_count(const struct ParinferAnswer *answer);
+
+// Stores edit `index` in `edit` and returns true, or returns false if
+// there are not that many.  Its texts last as long as the answer.
+//
+// # Safety
+//
+// `answer` must be valid, and `edit` must point to a writable
+// `ParinferChange`.
+bool parinfer_answer_edit(const struct ParinferAnswer *answer,
+                          size_t index,
+                          struct ParinferChange *edit);
+
+// The number of parens in the answer, with `return_parens`, counting the
+// ones inside others.
+//
+// # Safety
+//
+// `answer` must be valid.
+size_t parinfer_answer_paren_count(const struct ParinferAnswer *answer);
+
+// Stores paren `index` in `paren` and returns true, or returns false if
+// there are not that many.
+//
+// # Safety
+//
+// `answer` must be valid, and `paren` must point to a writable
+// `ParinferParen`.
+bool parinfer_answer_paren(const struct ParinferAnswer *answer,
+                           size_t index,
+                           struct ParinferParen *paren);
+
+// # Safety
+//
+// `answer` must be null or come from `parinfer_process`, and must not be
+// used afterwards.
+void parinfer_answer_free(struct ParinferAnswer *answer);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PARINFER_RUST_H */
@@ -7,12 +7,21 @@ endif
 if !exists('g:parinfer_force_balance')
   let g:parinfer_force_balance = 0
 endif
+if !exists('g:parinfer_preserve_tabs')
+  let g:parinfer_preserve_tabs = 0
+endif
 if !exists('g:parinfer_comment_char')
   let g:parinfer_comment_char = ";"
 endif
+if !exists('g:parinfer_comment_strings')
+  let g:parinfer_comment_strings = []
+endif
 if !exists('g:parinfer_string_delimiters')
   let g:parinfer_string_delimiters = ['"']
 endif
+if !exists('g:parinfer_paren_pairs')
+  let g:parinfer_paren_pairs = ['()', '[]', '{}']
+endif
 if !exists('g:parinfer_lisp_vline_symbols')
   let g:parinfer_lisp_vline_symbols = 0
 endif
@@ -71,6 +80,9 @@ au BufNewFile,BufRead *.janet let b:parinfer_comment_char = "#"
 " Quote settings
 au BufNewFile,BufRead *.yuck let b:parinfer_string_delimiters = ['"', "'", "`"]
 
+" Paren settings: Common Lisp brackets and braces aren't structural
+au BufNewFile,BufRead *.lsp,*.lisp,*.cl,*.L,sbclrc,.sbclrc let b:parinfer_paren_pairs = ['()']
+
 " Long strings settings
 au BufNewFile,BufRead *.janet let b:parinfer_janet_long_strings = 1
 
@@ -186,9 +198,15 @@ function! s:process_buffer() abort
   if !exists('b:parinfer_comment_char')
     let b:parinfer_comment_char = g:parinfer_comment_char
   endif
+  if !exists('b:parinfer_comment_strings')
+    let b:parinfer_comment_strings = g:parinfer_comment_strings
+  endif
   if !exists('b:parinfer_string_delimiters')
     let b:parinfer_string_delimiters = g:parinfer_string_delimiters
   endif
+  if !exists('b:parinfer_paren_pairs')
+    let b:parinfer_paren_pairs = g:parinfer_paren_pairs
+  endif
   if !exists('b:parinfer_lisp_vline_symbols')
     let b:parinfer_lisp_vline_symbols = g:parinfer_lisp_vline_symbols
   endif
@@ -211,7 +229,9 @@ function! s:process_buffer() abort
     let l:request = { "mode": g:parinfer_mode,
                     \ "text": l:orig_text,
                     \ "options": { "commentChar": b:parinfer_comment_char,
+                                 \ "commentStrings": b:parinfer_comment_strings,
                                  \ "stringDelimiters": b:parinfer_string_delimiters,
+                                 \ "parenPairs": b:parinfer_paren_pairs,
                                  \ "cursorX": l:cursor[2],
                                  \ "cursorLine": l:cursor[1],
                                  \ "forceBalance": g:parinfer_force_balance ? v:true : v:false,
@@ -223,6 +243,12 @@ function! s:process_buffer() abort
                                  \ "prevCursorX": w:parinfer_previous_cursor[2],
                                  \ "prevCursorLine": w:parinfer_previous_cursor[1],
                                  \ "prevText": b:parinfer_previous_text } }
+    " Otherwise tabs are expanded with tab stops every two columns, as in the
+    " original parinfer.
+    if g:parinfer_preserve_tabs
+      let l:request["options"]["preserveTabs"] = v:true
+      let l:request["options"]["tabWidth"] = &tabstop
+    endif
     if !exists('g:parinfer_dylib_path')
       let g:parinfer_dylib_path = s:guess_dylib_path()
     endif
@@ -0,0 +1,38 @@
+//! A typed Rust interface to parinfer.
+//!
+//! ```
+//! use parinfer_rust::api::{self, Options};
+//!
+//! let answer = api::indent_mode("(def x", &Options::default().with_cursor(3, 0));
+//! assert!(answer.success);
+//! assert_eq!(answer.text, "(def x)");
+//! ```
+
+use parinfer;
+
+pub use types::{Answer, Change, Closer, Column, Cursor, Delta, Error, ErrorExtra, ErrorName, LineNumber,
+                Mode, Options, OwnedAnswer, Paren, ParenTrail, PositionEncoding, Request, TabStop};
+pub use document::{Document, DocumentUpdate};
+
+/// Runs `mode` over `text`.  The answer owns its text, so it may outlive `text`.
+///
+/// If `options.prev_text` is given, `options.changes` is computed from it.
+pub fn run(mode: Mode, text: &str, options: &Options) -> OwnedAnswer {
+    parinfer::run_mode(mode, text, options).into_owned()
+}
+
+pub fn indent_mode(text: &str, options: &Options) -> OwnedAnswer {
+    run(Mode::Indent, text, options)
+}
+
+pub fn paren_mode(text: &str, options: &Options) -> OwnedAnswer {
+    run(Mode::Paren, text, options)
+}
+
+pub fn smart_mode(text: &str, options: &Options) -> OwnedAnswer {
+    run(Mode::Smart, text, options)
+}
+
+pub fn process(request: &Request) -> OwnedAnswer {
+    run(request.mode, &request.text, &request.options)
+}
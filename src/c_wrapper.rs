@@ -2,7 +2,10 @@ use types::*;
 use libc::c_char;
 use std::cell::RefCell;
 use std::ffi::{CStr, CString};
-use std::panic;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::thread;
+use document::Document;
 use super::*;
 
 /// On unix, Vim loads and unloads the library for every call. On Mac, and
@@ -140,11 +143,8 @@ unsafe fn unwrap_c_pointers(json: *const c_char) -> Result<CString, Error> {
 
 thread_local!(static BUFFER: RefCell<Option<CString>> = RefCell::new(None));
 
-#[cfg(not(target_arch = "wasm32"))]
-#[no_mangle]
-pub unsafe extern "C" fn run_parinfer(json: *const c_char) -> *const c_char {
-    reference_hack::initialize();
-    let output = match panic::catch_unwind(|| unwrap_c_pointers(json)) {
+fn output(result: thread::Result<Result<CString, Error>>) -> CString {
+    match result {
         Ok(Ok(result)) => result,
         Ok(Err(e)) => {
             let out = serde_json::to_string(&Answer::from(e)).unwrap();
@@ -154,10 +154,823 @@ pub unsafe extern "C" fn run_parinfer(json: *const c_char) -> *const c_char {
             let out = common_wrapper::panic_result();
             CString::new(out).unwrap()
         }
-    };
+    }
+}
 
+fn buffer_output(result: thread::Result<Result<CString, Error>>) -> *const c_char {
+    let output = output(result);
     BUFFER.with(|buffer| {
         buffer.replace(Some(output));
         buffer.borrow().as_ref().unwrap().as_ptr()
     })
 }
+
+unsafe fn run_json(json: *const c_char) -> CString {
+    if json.is_null() {
+        return output(Ok(Err(Error {
+            message: String::from("The request is null."),
+            ..Error::default()
+        })));
+    }
+    output(panic::catch_unwind(|| unwrap_c_pointers(json)))
+}
+
+/// Runs a JSON request, returning the answer as JSON.  The answer belongs to
+/// the library and is overwritten by the next call on the same thread;
+/// `parinfer_run` returns one the caller owns instead.
+///
+/// # Safety
+///
+/// `json` must be a valid, NUL-terminated string.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn run_parinfer(json: *const c_char) -> *const c_char {
+    reference_hack::initialize();
+    buffer_output(panic::catch_unwind(|| unwrap_c_pointers(json)))
+}
+
+/// The version of the C API described by `parinfer_rust.h`.  It changes
+/// only when existing functions change, not when new ones are added.
+pub const PARINFER_API_VERSION: u32 = 1;
+
+static VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "\0");
+
+/// The library's version, such as `"0.4.3"`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub extern "C" fn parinfer_version() -> *const c_char {
+    VERSION.as_ptr() as *const c_char
+}
+
+/// The version of the C API, `PARINFER_API_VERSION`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub extern "C" fn parinfer_api_version() -> u32 {
+    PARINFER_API_VERSION
+}
+
+/// Runs a JSON request, as `run_parinfer` does, but returns an answer the
+/// caller owns and must release with `parinfer_free`.
+///
+/// # Safety
+///
+/// `json` must be null or a valid, NUL-terminated string.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_run(json: *const c_char) -> *mut c_char {
+    reference_hack::initialize();
+    run_json(json).into_raw()
+}
+
+/// Releases an answer returned by `parinfer_run`.
+///
+/// # Safety
+///
+/// `answer` must be null or come from `parinfer_run`, and must not be used
+/// afterwards.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_free(answer: *mut c_char) {
+    if !answer.is_null() {
+        drop(CString::from_raw(answer));
+    }
+}
+
+/// Runs a JSON request, writing the NUL-terminated answer into the caller's
+/// `buffer` of `size` bytes.  Returns the size the answer needs, including
+/// the NUL.  When that is more than `size`, nothing but an empty string is
+/// written, and the request can be run again with a larger buffer.
+///
+/// # Safety
+///
+/// `json` must be null or a valid, NUL-terminated string, and `buffer` must
+/// point to at least `size` writable bytes.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_run_into(json: *const c_char, buffer: *mut c_char, size: usize) -> usize {
+    reference_hack::initialize();
+    let answer = run_json(json);
+    let bytes = answer.as_bytes_with_nul();
+    if bytes.len() <= size {
+        ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, buffer, bytes.len());
+    } else if size > 0 && !buffer.is_null() {
+        *buffer = 0;
+    }
+    bytes.len()
+}
+
+/// Creates a document from a request in the same JSON format as
+/// `run_parinfer` takes.  Returns null if the request can't be read.  The
+/// document must be released with `parinfer_document_free`.
+///
+/// # Safety
+///
+/// `json` must be a valid, NUL-terminated string.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_document_new(json: *const c_char) -> *mut Document {
+    reference_hack::initialize();
+    let result = panic::catch_unwind(|| -> Result<Document, Error> {
+        let json_str = CStr::from_ptr(json).to_str()?;
+        common_wrapper::new_document(json_str)
+    });
+    match result {
+        Ok(Ok(document)) => Box::into_raw(Box::new(document)),
+        _ => ptr::null_mut()
+    }
+}
+
+/// Applies a JSON update of the form
+/// `{"changes": [...], "cursorX": 1, "cursorLine": 0}` to the document and
+/// processes it.  Returns the answer as JSON, as `run_parinfer` does.
+///
+/// # Safety
+///
+/// `document` must come from `parinfer_document_new` and not have been
+/// freed, and `json` must be a valid, NUL-terminated string.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_document_update(document: *mut Document,
+                                                  json: *const c_char) -> *const c_char {
+    let document = AssertUnwindSafe(&mut *document);
+    buffer_output(panic::catch_unwind(move || -> Result<CString, Error> {
+        let AssertUnwindSafe(document) = document;
+        let json_str = CStr::from_ptr(json).to_str()?;
+        let response = common_wrapper::update_document(document, json_str)?;
+        Ok(CString::new(response)?)
+    }))
+}
+
+/// # Safety
+///
+/// `document` must be null or come from `parinfer_document_new`, and must
+/// not be used afterwards.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_document_free(document: *mut Document) {
+    if !document.is_null() {
+        drop(Box::from_raw(document));
+    }
+}
+
+/// Modes for `parinfer_process`.
+pub const PARINFER_MODE_INDENT: u32 = 0;
+pub const PARINFER_MODE_PAREN: u32 = 1;
+pub const PARINFER_MODE_SMART: u32 = 2;
+
+/// What x positions count, for `ParinferOptions::position_encoding`.
+/// Graphemes are display columns.
+pub const PARINFER_POSITION_GRAPHEMES: u32 = 0;
+pub const PARINFER_POSITION_UTF16: u32 = 1;
+pub const PARINFER_POSITION_UTF8_BYTES: u32 = 2;
+pub const PARINFER_POSITION_CODEPOINTS: u32 = 3;
+
+/// A change to the text, in `ParinferOptions::changes` or an answer's
+/// edits.  Its texts are UTF-8, as pointers and lengths in bytes.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ParinferChange {
+    pub x: i64,
+    pub line_no: i64,
+    pub old_text: *const c_char,
+    pub old_text_length: usize,
+    pub new_text: *const c_char,
+    pub new_text_length: usize,
+}
+
+/// The options for `parinfer_process`.  Positions and lines count from 0
+/// and are `-1` when absent, and text is a pointer and a length in bytes
+/// rather than a NUL-terminated string.  Fill it with
+/// `parinfer_options_init` before setting the fields you need.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ParinferOptions {
+    pub cursor_x: i64,
+    pub cursor_line: i64,
+    pub prev_cursor_x: i64,
+    pub prev_cursor_line: i64,
+    pub selection_start_line: i64,
+    /// The text before the user's changes as UTF-8, or null.
+    pub prev_text: *const c_char,
+    pub prev_text_length: usize,
+    /// The user's changes, `change_count` of them, which smart mode uses
+    /// instead of comparing the text with `prev_text`.
+    pub changes: *const ParinferChange,
+    pub change_count: usize,
+    /// `comment_string_count` NUL-terminated strings which start comments,
+    /// or null to use `comment_char`.
+    pub comment_strings: *const *const c_char,
+    pub comment_string_count: usize,
+    /// `string_delimiter_count` NUL-terminated strings, or null for `"`.
+    pub string_delimiters: *const *const c_char,
+    pub string_delimiter_count: usize,
+    /// `paren_pair_count` NUL-terminated open and close characters, such
+    /// as `"()"`, or null for `()`, `[]` and `{}`.
+    pub paren_pairs: *const *const c_char,
+    pub paren_pair_count: usize,
+    /// A Unicode code point.
+    pub comment_char: u32,
+    pub tab_width: u32,
+    pub position_encoding: u32,
+    pub force_balance: bool,
+    pub partial_result: bool,
+    /// Whether the answer has parens, read with `parinfer_answer_paren`.
+    pub return_parens: bool,
+    /// Whether to carry on past errors, which are read with
+    /// `parinfer_answer_collected_error`.
+    pub collect_errors: bool,
+    /// Whether the answer has edits, read with `parinfer_answer_edit`.
+    pub return_edits: bool,
+    pub preserve_tabs: bool,
+    pub lisp_vline_symbols: bool,
+    pub lisp_block_comments: bool,
+    pub guile_block_comments: bool,
+    pub scheme_sexp_comments: bool,
+    pub janet_long_strings: bool,
+}
+
+impl Default for ParinferOptions {
+    fn default() -> ParinferOptions {
+        let options = Options::default();
+        ParinferOptions {
+            cursor_x: -1,
+            cursor_line: -1,
+            prev_cursor_x: -1,
+            prev_cursor_line: -1,
+            selection_start_line: -1,
+            prev_text: ptr::null(),
+            prev_text_length: 0,
+            changes: ptr::null(),
+            change_count: 0,
+            comment_strings: ptr::null(),
+            comment_string_count: 0,
+            string_delimiters: ptr::null(),
+            string_delimiter_count: 0,
+            paren_pairs: ptr::null(),
+            paren_pair_count: 0,
+            comment_char: options.comment_char as u32,
+            tab_width: options.tab_width as u32,
+            position_encoding: PARINFER_POSITION_GRAPHEMES,
+            force_balance: false,
+            partial_result: false,
+            return_parens: false,
+            collect_errors: false,
+            return_edits: false,
+            preserve_tabs: false,
+            lisp_vline_symbols: false,
+            lisp_block_comments: false,
+            guile_block_comments: false,
+            scheme_sexp_comments: false,
+            janet_long_strings: false,
+        }
+    }
+}
+
+/// An error from a `ParinferAnswer`.  The strings are NUL-terminated and
+/// last as long as the answer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ParinferError {
+    /// Such as `"unclosed-paren"`.
+    pub name: *const c_char,
+    pub message: *const c_char,
+    pub line_no: i64,
+    pub x: i64,
+    pub input_line_no: i64,
+    pub input_x: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParinferTabStop {
+    /// The open-paren, as a Unicode code point.
+    pub ch: u32,
+    pub x: i64,
+    pub line_no: i64,
+    /// `-1` when there is no argument after the paren on its line.
+    pub arg_x: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParinferParenTrail {
+    pub line_no: i64,
+    pub start_x: i64,
+    pub end_x: i64,
+}
+
+/// A paren from a `ParinferAnswer`.  They are listed with each one before
+/// its children.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParinferParen {
+    /// The open-paren, as a Unicode code point.
+    pub ch: u32,
+    pub line_no: i64,
+    pub x: i64,
+    pub input_line_no: i64,
+    pub input_x: i64,
+    pub indent_delta: i64,
+    /// `-1` when it is not known.
+    pub max_child_indent: i64,
+    /// `-1` when there is no argument after the paren on its line.
+    pub arg_x: i64,
+    /// The close-paren, as a Unicode code point, or 0 when there is none.
+    pub closer_ch: u32,
+    /// `-1` when there is no close-paren.
+    pub closer_line_no: i64,
+    pub closer_x: i64,
+    /// The index of the paren this one is inside, or `-1` at the top level.
+    pub parent: i64,
+}
+
+/// The answer from `parinfer_process`, read with the `parinfer_answer_*`
+/// functions and released with `parinfer_answer_free`.
+pub struct ParinferAnswer {
+    answer: Answer<'static>,
+    // The error's name and message, NUL-terminated for `ParinferError`.
+    error: Option<(CString, CString)>,
+    // The same for each of the answer's `errors`.
+    errors: Vec<(CString, CString)>,
+    parens: Vec<ParinferParen>,
+}
+
+fn error_strings(error: &Error) -> (CString, CString) {
+    (CString::new(error.name.to_string()).unwrap_or_default(),
+     CString::new(error.message.replace('\0', "")).unwrap_or_default())
+}
+
+fn code_point(ch: &str) -> u32 {
+    ch.chars().next().map_or(0, |ch| ch as u32)
+}
+
+// Lists `parens` and their children, each before its children.
+fn flatten_parens(parens: &[Paren], parent: i64, result: &mut Vec<ParinferParen>) {
+    for paren in parens {
+        let index = result.len() as i64;
+        let closer = paren.closer.as_ref();
+        result.push(ParinferParen {
+            ch: code_point(&paren.ch),
+            line_no: paren.line_no as i64,
+            x: paren.x as i64,
+            input_line_no: paren.input_line_no as i64,
+            input_x: paren.input_x as i64,
+            indent_delta: paren.indent_delta,
+            max_child_indent: c_position(paren.max_child_indent),
+            arg_x: c_position(paren.arg_x),
+            closer_ch: closer.map_or(0, |closer| code_point(&closer.ch)),
+            closer_line_no: c_position(closer.map(|closer| closer.line_no)),
+            closer_x: c_position(closer.map(|closer| closer.x)),
+            parent,
+        });
+        flatten_parens(&paren.children, index, result);
+    }
+}
+
+impl From<Answer<'static>> for ParinferAnswer {
+    fn from(answer: Answer<'static>) -> ParinferAnswer {
+        let error = answer.error.as_ref().map(error_strings);
+        let errors = answer.errors.iter().map(error_strings).collect();
+        let mut parens = vec![];
+        flatten_parens(&answer.parens, -1, &mut parens);
+        ParinferAnswer { answer, error, errors, parens }
+    }
+}
+
+fn optional(value: i64) -> Option<usize> {
+    if value < 0 { None } else { Some(value as usize) }
+}
+
+fn c_position(value: Option<usize>) -> i64 {
+    value.map_or(-1, |value| value as i64)
+}
+
+fn invalid(message: &str) -> Error {
+    Error {
+        message: String::from(message),
+        ..Error::default()
+    }
+}
+
+unsafe fn c_text<'a>(text: *const c_char, length: usize) -> Result<&'a str, Error> {
+    if length == 0 {
+        return Ok("");
+    }
+    if text.is_null() {
+        return Err(invalid("The text is null."));
+    }
+    Ok(std::str::from_utf8(std::slice::from_raw_parts(text as *const u8, length))?)
+}
+
+unsafe fn c_changes(changes: *const ParinferChange, count: usize) -> Result<Vec<Change>, Error> {
+    if count == 0 {
+        return Ok(vec![]);
+    }
+    if changes.is_null() {
+        return Err(invalid("The changes are null."));
+    }
+    std::slice::from_raw_parts(changes, count).iter()
+        .map(|change| {
+            if change.x < 0 || change.line_no < 0 {
+                return Err(invalid("A change has no position."));
+            }
+            Ok(Change {
+                x: change.x as usize,
+                line_no: change.line_no as usize,
+                old_text: String::from(c_text(change.old_text, change.old_text_length)?),
+                new_text: String::from(c_text(change.new_text, change.new_text_length)?),
+            })
+        })
+        .collect()
+}
+
+// A null list means the default, given as `default`.
+unsafe fn c_strings(strings: *const *const c_char, count: usize,
+                    default: Vec<String>) -> Result<Vec<String>, Error> {
+    if strings.is_null() {
+        return Ok(default);
+    }
+    std::slice::from_raw_parts(strings, count).iter()
+        .map(|&string| {
+            if string.is_null() {
+                return Err(invalid("A string in a list is null."));
+            }
+            Ok(String::from(CStr::from_ptr(string).to_str()?))
+        })
+        .collect()
+}
+
+unsafe fn c_options(options: &ParinferOptions) -> Result<Options, Error> {
+    let prev_text = if options.prev_text.is_null() {
+        None
+    } else {
+        Some(String::from(c_text(options.prev_text, options.prev_text_length)?))
+    };
+    let defaults = Options::default();
+    Ok(Options {
+        cursor_x: optional(options.cursor_x),
+        cursor_line: optional(options.cursor_line),
+        prev_cursor_x: optional(options.prev_cursor_x),
+        prev_cursor_line: optional(options.prev_cursor_line),
+        prev_text,
+        selection_start_line: optional(options.selection_start_line),
+        changes: c_changes(options.changes, options.change_count)?,
+        force_balance: options.force_balance,
+        partial_result: options.partial_result,
+        return_parens: options.return_parens,
+        collect_errors: options.collect_errors,
+        return_edits: options.return_edits,
+        comment_char: std::char::from_u32(options.comment_char)
+            .ok_or_else(|| invalid("The comment character is not a Unicode code point."))?,
+        comment_strings: c_strings(options.comment_strings, options.comment_string_count,
+                                   defaults.comment_strings)?,
+        string_delimiters: c_strings(options.string_delimiters, options.string_delimiter_count,
+                                     defaults.string_delimiters)?,
+        paren_pairs: c_strings(options.paren_pairs, options.paren_pair_count, defaults.paren_pairs)?,
+        tab_width: options.tab_width as usize,
+        preserve_tabs: options.preserve_tabs,
+        position_encoding: match options.position_encoding {
+            PARINFER_POSITION_GRAPHEMES => PositionEncoding::Graphemes,
+            PARINFER_POSITION_UTF16 => PositionEncoding::Utf16,
+            PARINFER_POSITION_UTF8_BYTES => PositionEncoding::Utf8Bytes,
+            PARINFER_POSITION_CODEPOINTS => PositionEncoding::Codepoints,
+            _ => return Err(invalid("Unknown position encoding.")),
+        },
+        lisp_vline_symbols: options.lisp_vline_symbols,
+        lisp_block_comments: options.lisp_block_comments,
+        guile_block_comments: options.guile_block_comments,
+        scheme_sexp_comments: options.scheme_sexp_comments,
+        janet_long_strings: options.janet_long_strings,
+        ..defaults
+    })
+}
+
+unsafe fn process(mode: u32, text: *const c_char, length: usize,
+                  options: *const ParinferOptions) -> Result<Answer<'static>, Error> {
+    let mode = match mode {
+        PARINFER_MODE_INDENT => Mode::Indent,
+        PARINFER_MODE_PAREN => Mode::Paren,
+        PARINFER_MODE_SMART => Mode::Smart,
+        _ => return Err(invalid("Unknown mode.")),
+    };
+    let text = c_text(text, length)?;
+    let options = if options.is_null() {
+        Options::default()
+    } else {
+        c_options(&*options)?
+    };
+    Ok(parinfer::run_mode(mode, text, &options).into_owned())
+}
+
+/// Fills `options` with the defaults: no cursor or previous text, `;`
+/// comments and a tab width of 2.
+///
+/// # Safety
+///
+/// `options` must point to a writable `ParinferOptions`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_options_init(options: *mut ParinferOptions) {
+    if !options.is_null() {
+        ptr::write(options, ParinferOptions::default());
+    }
+}
+
+/// Runs `mode` on the `length` bytes of UTF-8 at `text`, without going
+/// through JSON.  A null `options` means the defaults.  The answer is never
+/// null; problems with the arguments are reported as its error.  It must be
+/// released with `parinfer_answer_free`.
+///
+/// # Safety
+///
+/// `text` must point to `length` readable bytes, and `options` must be null
+/// or point to a `ParinferOptions` whose pointers are null or point to as
+/// many readable bytes or items as their lengths and counts say.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_process(mode: u32, text: *const c_char, length: usize,
+                                          options: *const ParinferOptions) -> *mut ParinferAnswer {
+    reference_hack::initialize();
+    let answer = match panic::catch_unwind(|| process(mode, text, length, options)) {
+        Ok(Ok(answer)) => answer,
+        Ok(Err(e)) => Answer::from(e),
+        Err(_) => Answer::from(Error {
+            name: ErrorName::Panic,
+            message: String::from("plugin panicked!"),
+            ..Error::default()
+        }),
+    };
+    Box::into_raw(Box::new(ParinferAnswer::from(answer)))
+}
+
+/// Whether parinfer could process the text.
+///
+/// # Safety
+///
+/// `answer` must come from `parinfer_process` and not have been freed, as
+/// for all the `parinfer_answer_*` functions.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_success(answer: *const ParinferAnswer) -> bool {
+    (*answer).answer.success
+}
+
+/// The answer's text, which is not NUL-terminated, storing its length in
+/// bytes in `length`.  It lasts as long as the answer.
+///
+/// # Safety
+///
+/// `answer` must be valid, and `length` must point to a writable `size_t`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_text(answer: *const ParinferAnswer, length: *mut usize) -> *const c_char {
+    let answer = &*answer;
+    let text = &answer.answer.text;
+    *length = text.len();
+    text.as_ptr() as *const c_char
+}
+
+/// The cursor's new x position, or `-1` without a cursor.
+///
+/// # Safety
+///
+/// `answer` must be valid.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_cursor_x(answer: *const ParinferAnswer) -> i64 {
+    c_position((*answer).answer.cursor_x)
+}
+
+/// The cursor's new line, or `-1` without a cursor.
+///
+/// # Safety
+///
+/// `answer` must be valid.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_cursor_line(answer: *const ParinferAnswer) -> i64 {
+    c_position((*answer).answer.cursor_line)
+}
+
+/// Stores the answer's error in `error` and returns true, or returns false
+/// if there was none.
+///
+/// # Safety
+///
+/// `answer` must be valid, and `error` must point to a writable
+/// `ParinferError`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_error(answer: *const ParinferAnswer, error: *mut ParinferError) -> bool {
+    let answer = &*answer;
+    match (&answer.answer.error, &answer.error) {
+        (Some(e), Some((name, message))) => {
+            *error = ParinferError {
+                name: name.as_ptr(),
+                message: message.as_ptr(),
+                line_no: e.line_no as i64,
+                x: e.x as i64,
+                input_line_no: e.input_line_no as i64,
+                input_x: e.input_x as i64,
+            };
+            true
+        },
+        _ => false,
+    }
+}
+
+/// The number of tab stops in the answer.
+///
+/// # Safety
+///
+/// `answer` must be valid.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_tab_stop_count(answer: *const ParinferAnswer) -> usize {
+    (*answer).answer.tab_stops.len()
+}
+
+/// Stores tab stop `index` in `tab_stop` and returns true, or returns false
+/// if there are not that many.
+///
+/// # Safety
+///
+/// `answer` must be valid, and `tab_stop` must point to a writable
+/// `ParinferTabStop`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_tab_stop(answer: *const ParinferAnswer, index: usize,
+                                                  tab_stop: *mut ParinferTabStop) -> bool {
+    let answer = &*answer;
+    match answer.answer.tab_stops.get(index) {
+        Some(stop) => {
+            *tab_stop = ParinferTabStop {
+                ch: code_point(&stop.ch),
+                x: stop.x as i64,
+                line_no: stop.line_no as i64,
+                arg_x: c_position(stop.arg_x),
+            };
+            true
+        },
+        None => false,
+    }
+}
+
+/// The number of paren trails in the answer.
+///
+/// # Safety
+///
+/// `answer` must be valid.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_paren_trail_count(answer: *const ParinferAnswer) -> usize {
+    (*answer).answer.paren_trails.len()
+}
+
+/// Stores paren trail `index` in `paren_trail` and returns true, or returns
+/// false if there are not that many.
+///
+/// # Safety
+///
+/// `answer` must be valid, and `paren_trail` must point to a writable
+/// `ParinferParenTrail`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_paren_trail(answer: *const ParinferAnswer, index: usize,
+                                                     paren_trail: *mut ParinferParenTrail) -> bool {
+    let answer = &*answer;
+    match answer.answer.paren_trails.get(index) {
+        Some(trail) => {
+            *paren_trail = ParinferParenTrail {
+                line_no: trail.line_no as i64,
+                start_x: trail.start_x as i64,
+                end_x: trail.end_x as i64,
+            };
+            true
+        },
+        None => false,
+    }
+}
+
+/// The number of errors collected with `collect_errors`.
+///
+/// # Safety
+///
+/// `answer` must be valid.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_collected_error_count(answer: *const ParinferAnswer) -> usize {
+    (*answer).answer.errors.len()
+}
+
+/// Stores collected error `index` in `error` and returns true, or returns
+/// false if there are not that many.
+///
+/// # Safety
+///
+/// `answer` must be valid, and `error` must point to a writable
+/// `ParinferError`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_collected_error(answer: *const ParinferAnswer, index: usize,
+                                                         error: *mut ParinferError) -> bool {
+    let answer = &*answer;
+    match (answer.answer.errors.get(index), answer.errors.get(index)) {
+        (Some(e), Some((name, message))) => {
+            *error = ParinferError {
+                name: name.as_ptr(),
+                message: message.as_ptr(),
+                line_no: e.line_no as i64,
+                x: e.x as i64,
+                input_line_no: e.input_line_no as i64,
+                input_x: e.input_x as i64,
+            };
+            true
+        },
+        _ => false,
+    }
+}
+
+/// The number of edits in the answer, with `return_edits`.
+///
+/// # Safety
+///
+/// `answer` must be valid.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_edit_count(answer: *const ParinferAnswer) -> usize {
+    (*answer).answer.edits.len()
+}
+
+/// Stores edit `index` in `edit` and returns true, or returns false if
+/// there are not that many.  Its texts last as long as the answer.
+///
+/// # Safety
+///
+/// `answer` must be valid, and `edit` must point to a writable
+/// `ParinferChange`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_edit(answer: *const ParinferAnswer, index: usize,
+                                              edit: *mut ParinferChange) -> bool {
+    let answer = &*answer;
+    match answer.answer.edits.get(index) {
+        Some(change) => {
+            *edit = ParinferChange {
+                x: change.x as i64,
+                line_no: change.line_no as i64,
+                old_text: change.old_text.as_ptr() as *const c_char,
+                old_text_length: change.old_text.len(),
+                new_text: change.new_text.as_ptr() as *const c_char,
+                new_text_length: change.new_text.len(),
+            };
+            true
+        },
+        None => false,
+    }
+}
+
+/// The number of parens in the answer, with `return_parens`, counting the
+/// ones inside others.
+///
+/// # Safety
+///
+/// `answer` must be valid.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_paren_count(answer: *const ParinferAnswer) -> usize {
+    (*answer).parens.len()
+}
+
+/// Stores paren `index` in `paren` and returns true, or returns false if
+/// there are not that many.
+///
+/// # Safety
+///
+/// `answer` must be valid, and `paren` must point to a writable
+/// `ParinferParen`.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_paren(answer: *const ParinferAnswer, index: usize,
+                                               paren: *mut ParinferParen) -> bool {
+    let answer = &*answer;
+    match answer.parens.get(index) {
+        Some(found) => {
+            *paren = *found;
+            true
+        },
+        None => false,
+    }
+}
+
+/// # Safety
+///
+/// `answer` must be null or come from `parinfer_process`, and must not be
+/// used afterwards.
+#[cfg(not(target_arch = "wasm32"))]
+#[no_mangle]
+pub unsafe extern "C" fn parinfer_answer_free(answer: *mut ParinferAnswer) {
+    if !answer.is_null() {
+        drop(Box::from_raw(answer));
+    }
+}
@@ -1,75 +1,336 @@
 use super::types::*;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use parinfer::{chomp_cr, column_byte_index, tab_width, text_width};
+use positions;
 
-pub fn compute_text_changes<'a>(prev_text: &'a str, text: &'a str) -> Vec<Change> {
-    let mut x: Column = 0;
-    let mut line_no: LineNumber = 0;
-    let mut start_text: usize = 0;
-    let mut start_prev: usize = 0;
-    let mut end_text: usize = text.len();
-    let mut end_prev: usize = prev_text.len();
-    let mut different: bool = false;
-
-    for ((i, pc), (j, c)) in prev_text.char_indices().zip(text.char_indices()) {
-        if pc != c {
-            start_prev = i;
-            start_text = j;
-            different = true;
-            break;
+// A run of lines (or graphemes) which differ: `old` in the previous text
+// and `new` in the current one.
+struct Hunk {
+    old: Range<usize>,
+    new: Range<usize>,
+}
+
+// Past this many deletions and insertions, the texts are treated as
+// entirely different rather than searched further.
+const MAX_DIFF_EDITS: isize = 1000;
+
+// Myers' algorithm, finding the fewest lines (or graphemes) to delete and
+// insert to turn `a` into `b`.  Returns the runs of deleted and inserted
+// ones, in order.
+fn diff(a: &[&str], b: &[&str]) -> Vec<Hunk> {
+    let skip = a.iter().zip(b.iter()).take_while(|&(x, y)| x == y).count();
+    let (a, b) = (&a[skip..], &b[skip..]);
+    let keep = a.iter().rev().zip(b.iter().rev()).take_while(|&(x, y)| x == y).count();
+    let (a, b) = (&a[..a.len() - keep], &b[..b.len() - keep]);
+
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = n + m;
+    let index = |k: isize| (k + max + 1) as usize;
+    let mut v: Vec<isize> = vec![0; 2 * max as usize + 3];
+    // The furthest point reached on each diagonal -d..=d before step d.
+    let mut trace: Vec<Vec<isize>> = vec![];
+
+    let mut found = false;
+    'search: for d in 0..=max.min(MAX_DIFF_EDITS) {
+        trace.push(v[index(-d)..=index(d)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back through the search, collecting the lines which match.
+    let mut matches: Vec<(usize, usize)> = vec![];
+    if found {
+        let (mut x, mut y) = (n, m);
+        for (d, v) in trace.iter().enumerate().rev() {
+            let d = d as isize;
+            let at = |k: isize| v[(k + d) as usize];
+            let k = x - y;
+            let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+                k + 1
+            } else {
+                k - 1
+            };
+            let prev_x = if d == 0 { 0 } else { at(prev_k) };
+            let prev_y = prev_x - prev_k;
+            while x > prev_x && y > prev_y {
+                x -= 1;
+                y -= 1;
+                matches.push((x as usize, y as usize));
+            }
+            x = prev_x;
+            y = prev_y;
+        }
+        matches.reverse();
+    }
+
+    let mut hunks = vec![];
+    let (mut i, mut j) = (0, 0);
+    for (x, y) in matches.into_iter().chain(Some((a.len(), b.len()))) {
+        if i < x || j < y {
+            hunks.push(Hunk { old: skip + i..skip + x, new: skip + j..skip + y });
         }
-        if pc == '\n' {
-            x = 0;
-            line_no += 1;
+        i = x + 1;
+        j = y + 1;
+    }
+    hunks
+}
+
+// The length in bytes of the graphemes `a` and `b` start with, and then of
+// those they end with after that.
+pub fn common_affixes(a: &str, b: &str) -> (usize, usize) {
+    let prefix: usize = a.graphemes(true)
+        .zip(b.graphemes(true))
+        .take_while(|&(x, y)| x == y)
+        .map(|(x, _)| x.len())
+        .sum();
+    let (a_rest, b_rest) = (&a[prefix..], &b[prefix..]);
+    let suffix: usize = a_rest.graphemes(true).rev()
+        .zip(b_rest.graphemes(true).rev())
+        .take_while(|&(x, y)| x == y)
+        .map(|(x, _)| x.len())
+        .sum();
+    (prefix, suffix)
+}
+
+// The smallest change turning `old` into `new`, both of which start at
+// the beginning of `line_no`.
+fn minimal_change(old: &str, new: &str, line_no: LineNumber, tab_width: usize) -> Change {
+    let (prefix, suffix) = common_affixes(old, new);
+    let before = &new[..prefix];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    Change {
+        x: text_width(chomp_cr(&before[line_start..]), 0, tab_width),
+        line_no: line_no + before.matches('\n').count(),
+        old_text: String::from(&old[prefix..old.len() - suffix]),
+        new_text: String::from(&new[prefix..new.len() - suffix]),
+    }
+}
+
+/// Works out the edits which turn `prev_text` into `text`: one for each
+/// changed line, or for each run of lines which were inserted or removed.
+/// They come in order, so each is positioned as if those before it had
+/// already been made.
+pub fn compute_text_changes(prev_text: &str, text: &str, tab_width: usize) -> Vec<Change> {
+    let old_lines: Vec<&str> = prev_text.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = text.split_inclusive('\n').collect();
+
+    let mut changes = vec![];
+    for hunk in diff(&old_lines, &new_lines) {
+        if hunk.old.len() == hunk.new.len() {
+            // Lines were edited in place; diff each on its own.
+            for (old, new) in hunk.old.zip(hunk.new) {
+                changes.push(minimal_change(old_lines[old], new_lines[new], new, tab_width));
+            }
         } else {
-            x += 1;
+            let old = old_lines[hunk.old].concat();
+            let new = new_lines[hunk.new.clone()].concat();
+            changes.push(minimal_change(&old, &new, hunk.new.start, tab_width));
         }
     }
+    changes
+}
+
+/// Replaces the bytes in `range` of a text with `text`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Splice {
+    pub range: Range<usize>,
+    pub text: String,
+}
+
+// The byte offset of the start of each line, and of the end of the text.
+fn line_offsets(lines: &[&str]) -> Vec<usize> {
+    let mut offsets = Vec::with_capacity(lines.len() + 1);
+    let mut offset = 0;
+    offsets.push(0);
+    for line in lines {
+        offset += line.len();
+        offsets.push(offset);
+    }
+    offsets
+}
+
+/// The splices which turn `prev_text` into `text`, in order and all
+/// positioned in `prev_text`.  Lines edited in place get a splice for each
+/// run of changed graphemes, and lines which were inserted or removed get
+/// one for the whole run of them.
+#[allow(dead_code)]
+pub fn splices(prev_text: &str, text: &str) -> Vec<Splice> {
+    let old_lines: Vec<&str> = prev_text.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = text.split_inclusive('\n').collect();
+    let old_offsets = line_offsets(&old_lines);
 
-    for ((i, pc), (j, c)) in prev_text.char_indices().rev().zip(text.char_indices().rev()) {
-        if pc != c || i < start_prev || j < start_text {
-            end_prev = i + pc.len_utf8();
-            end_text = j + c.len_utf8();
-            break;
+    let mut splices = vec![];
+    for hunk in diff(&old_lines, &new_lines) {
+        if hunk.old.len() == hunk.new.len() {
+            for (old, new) in hunk.old.zip(hunk.new) {
+                let old_graphemes: Vec<(usize, &str)> = old_lines[old].grapheme_indices(true).collect();
+                let new_graphemes: Vec<&str> = new_lines[new].graphemes(true).collect();
+                let offset = |i: usize| old_offsets[old] + old_graphemes.get(i)
+                    .map(|&(offset, _)| offset)
+                    .unwrap_or(old_lines[old].len());
+                let old_graphemes_only: Vec<&str> = old_graphemes.iter().map(|&(_, g)| g).collect();
+                for edit in diff(&old_graphemes_only, &new_graphemes) {
+                    splices.push(Splice {
+                        range: offset(edit.old.start)..offset(edit.old.end),
+                        text: new_graphemes[edit.new].concat(),
+                    });
+                }
+            }
+        } else {
+            let start = old_offsets[hunk.old.start];
+            let old = old_lines[hunk.old].concat();
+            let new = new_lines[hunk.new].concat();
+            let (prefix, suffix) = common_affixes(&old, &new);
+            splices.push(Splice {
+                range: start + prefix..start + old.len() - suffix,
+                text: String::from(&new[prefix..new.len() - suffix]),
+            });
         }
     }
+    splices
+}
+
+fn invalid_change(change: &Change) -> Error {
+    Error {
+        name: ErrorName::InvalidChange,
+        message: format!("Change does not match the text at line {}, column {}.",
+                         change.line_no, change.x),
+        x: change.x,
+        line_no: change.line_no,
+        input_x: change.x,
+        input_line_no: change.line_no,
+        extra: None,
+    }
+}
 
-    if different {
-        vec![Change {
-            x,
-            line_no,
-            old_text: String::from(&prev_text[start_prev..end_prev]),
-            new_text: String::from(&text[start_text..end_text])
-        }]
+/// Applies `change` to `text`, checking that `change.old_text` is what is
+/// actually there.  Tabs reach the next multiple of `tab_width`.
+#[allow(dead_code)]
+pub fn apply_change(text: &str, change: &Change, options: &Options) -> Result<String, Error> {
+    let line_start = if change.line_no == 0 {
+        0
     } else {
-        vec![]
+        match text.match_indices('\n').nth(change.line_no - 1) {
+            Some((i, _)) => i + 1,
+            None => return Err(invalid_change(change)),
+        }
+    };
+    let line_end = text[line_start..].find('\n').map(|i| line_start + i).unwrap_or(text.len());
+    let line = chomp_cr(&text[line_start..line_end]);
+    // The change must start at a character, not in the middle of one.
+    let tab_width = tab_width(options);
+    let column = positions::to_column(line, change.x, options);
+    let x = column_byte_index(line, column, tab_width);
+    if text_width(&line[..x], 0, tab_width) != column || positions::from_column(line, column, options) != change.x {
+        return Err(invalid_change(change));
+    }
+
+    let start = line_start + x;
+    if !text[start..].starts_with(&change.old_text) {
+        return Err(invalid_change(change));
+    }
+    let end = start + change.old_text.len();
+
+    let mut result = String::with_capacity(text.len() - change.old_text.len() + change.new_text.len());
+    result.push_str(&text[..start]);
+    result.push_str(&change.new_text);
+    result.push_str(&text[end..]);
+    Ok(result)
+}
+
+// A `Change` at `x` on `line_no`, for tests.
+#[cfg(test)]
+pub fn change(x: Column, line_no: LineNumber, old_text: &str, new_text: &str) -> Change {
+    Change {
+        x,
+        line_no,
+        old_text: String::from(old_text),
+        new_text: String::from(new_text)
     }
 }
 
+#[cfg(test)]
+#[test]
+fn apply_change_works() {
+    let options = Options::default();
+    let tab_width_4 = Options::default().with_tab_width(4);
+    let utf16 = Options::default().with_position_encoding(PositionEncoding::Utf16);
+    assert_eq!("hexlo", apply_change("hello", &change(2, 0, "l", "x"), &options).unwrap());
+    assert_eq!("he\nxlo", apply_change("he\nllo", &change(0, 1, "l", "x"), &options).unwrap());
+    assert_eq!("hello\n", apply_change("hello", &change(5, 0, "", "\n"), &options).unwrap());
+    assert_eq!("hello", apply_change("he\nllo", &change(2, 0, "\n", ""), &options).unwrap());
+    assert_eq!("ｗｏx", apply_change("ｗｏｒ", &change(4, 0, "ｒ", "x"), &options).unwrap());
+    assert!(apply_change("hello", &change(2, 0, "x", "y"), &options).is_err());
+    assert!(apply_change("hello", &change(6, 0, "", "y"), &options).is_err());
+    assert!(apply_change("hello", &change(0, 1, "", "y"), &options).is_err());
+    assert_eq!("\tx(a)", apply_change("\t(a)", &change(4, 0, "", "x"), &tab_width_4).unwrap());
+    assert!(apply_change("\t(a)", &change(2, 0, "", "x"), &tab_width_4).is_err());
+    assert!(apply_change("ｗｏ", &change(1, 0, "", "x"), &options).is_err());
+    assert_eq!("😀x", apply_change("😀", &change(2, 0, "", "x"), &utf16).unwrap());
+    assert!(apply_change("😀", &change(1, 0, "", "x"), &utf16).is_err());
+}
+
 #[cfg(test)]
 #[test]
 fn compute_text_changes_works() {
-    assert!(compute_text_changes("hello", "hello").is_empty());
-    assert_eq!(vec![Change {
-        x: 2,
-        line_no: 0,
-        old_text: String::from("l"),
-        new_text: String::from("x")
-    }], compute_text_changes("hello", "hexlo"));
-    assert_eq!(vec![Change {
-        x: 0,
-        line_no: 1,
-        old_text: String::from("l"),
-        new_text: String::from("x")
-    }], compute_text_changes("he\nllo", "he\nxlo"));
-    assert_eq!(vec![Change {
-        x: 4,
-        line_no: 0,
-        old_text: String::from(""),
-        new_text: String::from("l")
-    }], compute_text_changes("hello", "helllo"));
-    assert_eq!(vec![Change {
-        x: 4,
-        line_no: 0,
-        old_text: String::from("l"),
-        new_text: String::from("")
-    }], compute_text_changes("helllo", "hello"));
+    assert!(compute_text_changes("hello", "hello", 2).is_empty());
+    assert_eq!(vec![change(2, 0, "l", "x")], compute_text_changes("hello", "hexlo", 2));
+    assert_eq!(vec![change(0, 1, "l", "x")], compute_text_changes("he\nllo", "he\nxlo", 2));
+    assert_eq!(vec![change(4, 0, "", "l")], compute_text_changes("hello", "helllo", 2));
+    assert_eq!(vec![change(4, 0, "l", "")], compute_text_changes("helllo", "hello", 2));
+}
+
+#[cfg(test)]
+#[test]
+fn compute_text_changes_finds_each_edit() {
+    assert_eq!(vec![change(1, 0, "a", "x"), change(1, 3, "d", "y")],
+               compute_text_changes("(a\n b\n c\n d)", "(x\n b\n c\n y)", 2));
+    assert_eq!(vec![change(0, 1, "", " x\n")],
+               compute_text_changes("(a\n b)", "(a\n x\n b)", 2));
+    assert_eq!(vec![change(0, 1, "x\n b", "y")],
+               compute_text_changes("(a\nx\n b\n c)", "(a\ny\n c)", 2));
+    assert_eq!(vec![change(5, 0, "a", "b")], compute_text_changes("ｗｏ a", "ｗｏ b", 2));
+
+    // Applying the changes in order gives the new text.
+    let cases = [
+        ("(defn foo\n  [a]\n  (bar a))\n\n(baz)", "(defn foo\n  [a b]\n  (bar a)\n  (qux b))\n(baz 1)"),
+        ("a\nb\nc\nd\ne", "e\nd\nc\nb\na"),
+        ("", "(foo)\n"),
+        ("(foo)\n", ""),
+    ];
+    for &(prev_text, text) in cases.iter() {
+        let mut result = String::from(prev_text);
+        for change in compute_text_changes(prev_text, text, 2) {
+            result = apply_change(&result, &change, &Options::default()).unwrap();
+        }
+        assert_eq!(result, text);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn splices_are_positioned_in_the_previous_text() {
+    let splice = |range: Range<usize>, text: &str| Splice { range, text: String::from(text) };
+    assert!(splices("(foo)\n", "(foo)\n").is_empty());
+    assert_eq!(vec![splice(4..4, ")"), splice(8..9, "")], splices("(foo bar))", "(foo) bar)"));
+    assert_eq!(vec![splice(3..3, " x\n")], splices("(a\n b)\n", "(a\n x\n b)\n"));
+    assert_eq!(vec![splice(3..6, "")], splices("(a\n x\n b)", "(a\n b)"));
+    assert_eq!(vec![splice(4..5, "b"), splice(12..12, ")")], splices("(é a\n  (c d\n", "(é b\n  (c d)\n"));
 }
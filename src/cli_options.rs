@@ -1,4 +1,6 @@
 use getopts;
+use kakoune;
+use kakoune::Region;
 use std::env;
 use std::io;
 use std::io::Read;
@@ -9,6 +11,7 @@ use types::*;
 pub enum InputType {
     Json,
     Kakoune,
+    KakouneFramed,
     Text
 }
 
@@ -67,9 +70,10 @@ const SCHEME_SEXP_COMMENTS : YesNoDefaultOption = YesNoDefaultOption {
 fn options() -> getopts::Options {
     let mut options = getopts::Options::new();
     options.optopt(  ""    , "comment-char"         , "(default: ';')", "CC");
+    options.optmulti(""    , "comment-string"       , "string which starts a comment, may be repeated (overrides --comment-char)", "STR");
     options.optopt(  ""    , "string-delimiters"    , "(default: '\"')", "DELIM");
     options.optflag("h"    , "help"                 , "show this help message");
-    options.optopt( ""     , "input-format"         , "'json', 'text' (default: 'text')", "FMT");
+    options.optopt( ""     , "input-format"         , "'json', 'kakoune', 'kakoune-framed', 'text' (default: 'text')", "FMT");
     GUILE_BLOCK_COMMENTS_OPTION.add(&mut options);
     JANET_LONG_STRINGS_OPTION.add(&mut options);
     options.optopt( "l"    , "language"             , "'clojure', 'janet', 'lisp', 'racket', 'guile', 'scheme' (default: 'clojure')", "LANG");
@@ -77,15 +81,20 @@ fn options() -> getopts::Options {
     LISP_VLINE_SYMBOLS_OPTION.add(&mut options);
     options.optopt( "m"    , "mode"                 , "parinfer mode (indent, paren, or smart) (default: smart)", "MODE");
     options.optopt( ""     , "output-format"        , "'json', 'kakoune', 'text' (default: 'text')", "FMT");
+    options.optflag(""     , "preserve-tabs"        , "keep tabs instead of expanding them to spaces");
+    options.optopt( ""     , "paren-pairs"          , "open and close characters of each paren, e.g. '()[]' (default: per language)", "PAIRS");
     SCHEME_SEXP_COMMENTS.add(&mut options);
+    options.optflag(""     , "server"               , "answer JSON-RPC requests on stdin until shutdown");
+    options.optopt( ""     , "tab-width"            , "columns between tab stops (default: 2)", "N");
     options
 }
 
 pub fn usage() -> String {
-    options().usage("Usage: parinfer-rust [options]")
+    options().usage("Usage: parinfer-rust [options]\n       parinfer-rust [options] lsp")
 }
 
 struct Defaults {
+    paren_pairs: &'static str,
     lisp_vline_symbols: bool,
     lisp_block_comments: bool,
     guile_block_comments: bool,
@@ -98,6 +107,7 @@ fn parse_language(language: Option<String>) -> Language {
         Some(ref s) if s == "clojure" => Language::Clojure,
         Some(ref s) if s == "janet"   => Language::Janet,
         Some(ref s) if s == "lisp"    => Language::Lisp,
+        Some(ref s) if s == "commonlisp" => Language::Lisp,
         Some(ref s) if s == "racket"  => Language::Racket,
         Some(ref s) if s == "guile"   => Language::Guile,
         Some(ref s) if s == "scheme"  => Language::Scheme,
@@ -110,6 +120,7 @@ fn parse_language(language: Option<String>) -> Language {
 fn language_defaults(language: Language) -> Defaults {
     match language {
         Language::Clojure => Defaults {
+            paren_pairs: "()[]{}",
             lisp_vline_symbols: false,
             lisp_block_comments: false,
             guile_block_comments: false,
@@ -117,6 +128,7 @@ fn language_defaults(language: Language) -> Defaults {
             janet_long_strings: false,
         },
         Language::Janet => Defaults {
+            paren_pairs: "()[]{}",
             lisp_vline_symbols: false,
             lisp_block_comments: false,
             guile_block_comments: false,
@@ -124,6 +136,7 @@ fn language_defaults(language: Language) -> Defaults {
             janet_long_strings: true,
         },
         Language::Lisp => Defaults {
+            paren_pairs: "()",
             lisp_vline_symbols: true,
             lisp_block_comments: true,
             guile_block_comments: false,
@@ -131,6 +144,7 @@ fn language_defaults(language: Language) -> Defaults {
             janet_long_strings: false
         },
         Language::Racket => Defaults {
+            paren_pairs: "()[]{}",
             lisp_vline_symbols: true,
             lisp_block_comments: true,
             guile_block_comments: false,
@@ -138,6 +152,7 @@ fn language_defaults(language: Language) -> Defaults {
             janet_long_strings: false
         },
         Language::Guile => Defaults {
+            paren_pairs: "()[]{}",
             lisp_vline_symbols: true,
             lisp_block_comments: true,
             guile_block_comments: true,
@@ -145,6 +160,7 @@ fn language_defaults(language: Language) -> Defaults {
             janet_long_strings: false
         },
         Language::Scheme => Defaults {
+            paren_pairs: "()[]{}",
             lisp_vline_symbols: true,
             lisp_block_comments: true,
             guile_block_comments: false,
@@ -154,6 +170,115 @@ fn language_defaults(language: Language) -> Defaults {
     }
 }
 
+// Kakoune counts lines and columns from one.  Unset or empty options give
+// `None`.
+fn kak_number(name: &str, value: &Option<String>) -> Result<Option<usize>, String> {
+    match *value {
+        None => Ok(None),
+        Some(ref s) if s.is_empty() => Ok(None),
+        Some(ref s) => match s.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Some(n - 1)),
+            _ => Err(format!("{} is not a line or column: '{}'", name, s))
+        }
+    }
+}
+
+// What Kakoune passes about the buffer, either in environment variables or
+// framed on stdin.
+#[derive(Default)]
+struct KakouneInput {
+    // The whole buffer, which has the saved cursor and previous text; or
+    // else the selections, which are processed on their own.
+    buffer: Option<String>,
+    selections: Vec<String>,
+    selections_desc: Vec<String>,
+    previous_text: Option<String>,
+    cursor_line: Option<String>,
+    cursor_char_column: Option<String>,
+    previous_cursor_line: Option<String>,
+    previous_cursor_char_column: Option<String>,
+}
+
+fn next_word(words: &mut dyn Iterator<Item = String>, name: &str) -> Result<String, String> {
+    words.next().ok_or_else(|| format!("no value for {}", name))
+}
+
+// A count of values followed by the values.
+fn next_words(words: &mut dyn Iterator<Item = String>, name: &str) -> Result<Vec<String>, String> {
+    let count = next_word(words, name)?;
+    let count = count.parse::<usize>()
+        .map_err(|_| format!("{} count is not a number: '{}'", name, count))?;
+    (0..count).map(|_| next_word(words, name)).collect()
+}
+
+impl KakouneInput {
+    fn from_env() -> Result<KakouneInput, String> {
+        let var = |name: &str| env::var(name).map_err(|_| format!("{} is not set.", name));
+        // Kakoune only sets the variables a script mentions, so a script
+        // passing `$kak_selection` rather than `$kak_quoted_selections` has
+        // selected the whole buffer, as the plugin used to.
+        let (buffer, selections, selections_desc) = match env::var("kak_quoted_selections") {
+            Ok(selections) => (
+                None,
+                kakoune::parse_quoted_list(&selections)?,
+                var("kak_selections_desc")?
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect()
+            ),
+            Err(_) => (Some(var("kak_selection")?), vec![], vec![]),
+        };
+        Ok(KakouneInput {
+            buffer,
+            selections,
+            selections_desc,
+            previous_text: env::var("kak_opt_parinfer_previous_text").ok(),
+            cursor_line: env::var("kak_opt_parinfer_cursor_line").ok(),
+            cursor_char_column: env::var("kak_opt_parinfer_cursor_char_column").ok(),
+            previous_cursor_line: env::var("kak_opt_parinfer_previous_cursor_line").ok(),
+            previous_cursor_char_column: env::var("kak_opt_parinfer_previous_cursor_char_column").ok(),
+        })
+    }
+
+    /// Reads shell-quoted words, as written by `echo -quoting shell`.  They
+    /// come in `name value` pairs, except that `selections` and
+    /// `selections_desc` are followed by a count and then that many values.
+    /// Either `buffer` or the selections must be given, not both.
+    fn from_frame(frame: &str) -> Result<KakouneInput, String> {
+        let mut input = KakouneInput::default();
+        let mut words = kakoune::parse_quoted_list(frame)?.into_iter();
+        while let Some(name) = words.next() {
+            match name.as_str() {
+                "buffer" => input.buffer = Some(next_word(&mut words, &name)?),
+                "selections" => input.selections = next_words(&mut words, &name)?,
+                "selections_desc" => input.selections_desc = next_words(&mut words, &name)?,
+                "previous_text" => input.previous_text = Some(next_word(&mut words, &name)?),
+                "cursor_line" => input.cursor_line = Some(next_word(&mut words, &name)?),
+                "cursor_char_column" => input.cursor_char_column = Some(next_word(&mut words, &name)?),
+                "previous_cursor_line" => input.previous_cursor_line = Some(next_word(&mut words, &name)?),
+                "previous_cursor_char_column" => {
+                    input.previous_cursor_char_column = Some(next_word(&mut words, &name)?)
+                },
+                _ => return Err(format!("unknown field in input: {}", name)),
+            }
+        }
+        if input.buffer.is_some() && !input.selections.is_empty() {
+            return Err(String::from("input has both a buffer and selections"));
+        }
+        Ok(input)
+    }
+}
+
+fn parse_paren_pairs(pairs: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = pairs.chars().collect();
+    chars.chunks(2)
+        .map(|pair| match *pair {
+            [_, _] => Ok(pair.iter().collect()),
+            _ => Err(format!("paren pairs must be given as open and close characters, e.g. '()[]': '{}'", pairs))
+        })
+        .collect()
+}
+
 impl Options {
     pub fn parse(args: &[String]) -> Result<Options, String> {
         options()
@@ -166,12 +291,20 @@ impl Options {
         self.matches.opt_present("h")
     }
 
-    fn mode(&self) -> &'static str {
+    pub fn want_lsp(&self) -> bool {
+        self.matches.free.first().map(String::as_str) == Some("lsp")
+    }
+
+    pub fn want_server(&self) -> bool {
+        self.matches.opt_present("server")
+    }
+
+    fn mode(&self) -> Mode {
         match self.matches.opt_str("m") {
-            None => "smart",
-            Some(ref s) if s == "i" || s == "indent" => "indent",
-            Some(ref s) if s == "p" || s == "paren"  => "paren",
-            Some(ref s) if s == "s" || s == "smart"  => "smart",
+            None => Mode::Smart,
+            Some(ref s) if s == "i" || s == "indent" => Mode::Indent,
+            Some(ref s) if s == "p" || s == "paren"  => Mode::Paren,
+            Some(ref s) if s == "s" || s == "smart"  => Mode::Smart,
             _ => panic!("invalid mode specified for `-m`")
         }
     }
@@ -182,6 +315,7 @@ impl Options {
             Some(ref s) if s == "text" => InputType::Text,
             Some(ref s) if s == "json" => InputType::Json,
             Some(ref s) if s == "kakoune" => InputType::Kakoune,
+            Some(ref s) if s == "kakoune-framed" => InputType::KakouneFramed,
             Some(ref s) => panic!("unknown input format `{}`", s)
         }
     }
@@ -204,6 +338,10 @@ impl Options {
         }
     }
 
+    fn comment_strings(&self) -> Vec<String> {
+        self.matches.opt_strs("comment-string")
+    }
+
     fn string_delimiters(&self) -> Vec<String> {
         let delims = self.matches.opt_strs("string-delimiters");
         if delims.is_empty() {
@@ -213,6 +351,18 @@ impl Options {
         }
     }
 
+    fn tab_width(&self) -> Result<usize, String> {
+        match self.matches.opt_str("tab-width") {
+            None => Ok(2),
+            Some(ref s) => s.parse().map_err(|_| format!("tab width must be a number: '{}'", s))
+        }
+    }
+
+    // The pairs given with `--paren-pairs`, or else `default`.
+    fn paren_pairs(&self, default: &str) -> Result<Vec<String>, String> {
+        parse_paren_pairs(&self.matches.opt_str("paren-pairs").unwrap_or_else(|| String::from(default)))
+    }
+
     fn invertible_flag(&self, name: &str) -> Option<bool> {
         if self.matches.opt_present(name) {
             Some(true)
@@ -246,79 +396,20 @@ impl Options {
     pub fn request(&self, input: &mut dyn Read) -> io::Result<Request> {
         match self.input_type() {
             InputType::Text => {
-                let Defaults {
-                    lisp_vline_symbols,
-                    lisp_block_comments,
-                    guile_block_comments,
-                    scheme_sexp_comments,
-                    janet_long_strings
-                } = language_defaults(parse_language(self.matches.opt_str("language")));
                 let mut text = String::new();
                 input.read_to_string(&mut text)?;
+                let options = self.language_options(None)
+                    .map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))?;
                 Ok(Request {
-                    mode: String::from(self.mode()),
+                    mode: self.mode(),
                     text,
-                    options: types::Options {
-                        changes: vec![],
-                        cursor_x: None,
-                        cursor_line: None,
-                        prev_text: None,
-                        prev_cursor_x: None,
-                        prev_cursor_line: None,
-                        force_balance: false,
-                        return_parens: false,
-                        comment_char: char::from(self.comment_char()),
-                        string_delimiters: self.string_delimiters(),
-                        partial_result: false,
-                        selection_start_line: None,
-                        lisp_vline_symbols: self.lisp_vline_symbols().unwrap_or(lisp_vline_symbols),
-                        lisp_block_comments: self.lisp_block_comments().unwrap_or(lisp_block_comments),
-                        guile_block_comments: self.guile_block_comments().unwrap_or(guile_block_comments),
-                        scheme_sexp_comments: self.scheme_sexp_comments().unwrap_or(scheme_sexp_comments),
-                        janet_long_strings: self.janet_long_strings().unwrap_or(janet_long_strings),
-                    }
+                    options,
                 })
             },
-            InputType::Kakoune => {
-                let Defaults {
-                    lisp_vline_symbols,
-                    lisp_block_comments,
-                    guile_block_comments,
-                    scheme_sexp_comments,
-                    janet_long_strings
-                } = language_defaults(parse_language(env::var("kak_opt_filetype").ok()));
-                Ok(Request {
-                    mode: String::from(self.mode()),
-                    text: env::var("kak_selection").unwrap(),
-                    options: types::Options {
-                        changes: vec![],
-                        cursor_x: env::var("kak_opt_parinfer_cursor_char_column")
-                            .map(|s| s.parse::<Column>().unwrap() - 1)
-                            .ok(),
-                        cursor_line: env::var("kak_opt_parinfer_cursor_line")
-                            .map(|s| s.parse::<LineNumber>().unwrap() - 1)
-                            .ok(),
-                        prev_text: env::var("kak_opt_parinfer_previous_text")
-                            .ok(),
-                        prev_cursor_x: env::var("kak_opt_parinfer_previous_cursor_char_column")
-                            .map(|s| s.parse::<Column>().unwrap() - 1)
-                            .ok(),
-                        prev_cursor_line: env::var("kak_opt_parinfer_previous_cursor_line")
-                            .map(|s| s.parse::<LineNumber>().unwrap() - 1)
-                            .ok(),
-                        force_balance: false,
-                        return_parens: false,
-                        comment_char: char::from(self.comment_char()),
-                        string_delimiters: self.string_delimiters(),
-                        partial_result: false,
-                        selection_start_line: None,
-                        lisp_vline_symbols,
-                        lisp_block_comments,
-                        guile_block_comments,
-                        scheme_sexp_comments,
-                        janet_long_strings,
-                    }
-                })
+            InputType::Kakoune | InputType::KakouneFramed => {
+                self.kakoune_input(input)
+                    .and_then(|kakoune_input| self.kakoune_request(&kakoune_input))
+                    .map_err(|message| io::Error::new(io::ErrorKind::InvalidInput, message))
             },
             InputType::Json => {
                 let mut text = String::new();
@@ -327,23 +418,147 @@ impl Options {
             },
         }
     }
+
+    /// The options given on the command line, with defaults for `--language`
+    /// or else `language`.
+    pub fn language_options(&self, language: Option<&str>) -> Result<types::Options, String> {
+        let language = self.matches.opt_str("language").or_else(|| language.map(String::from));
+        let Defaults {
+            paren_pairs,
+            lisp_vline_symbols,
+            lisp_block_comments,
+            guile_block_comments,
+            scheme_sexp_comments,
+            janet_long_strings
+        } = language_defaults(parse_language(language));
+        Ok(types::Options {
+            changes: vec![],
+            cursor_x: None,
+            cursor_line: None,
+            prev_text: None,
+            prev_cursor_x: None,
+            prev_cursor_line: None,
+            force_balance: false,
+            return_parens: false,
+            collect_errors: false,
+            return_edits: false,
+            comment_char: char::from(self.comment_char()),
+            comment_strings: self.comment_strings(),
+            string_delimiters: self.string_delimiters(),
+            paren_pairs: self.paren_pairs(paren_pairs)?,
+            tab_width: self.tab_width()?,
+            preserve_tabs: self.matches.opt_present("preserve-tabs"),
+            position_encoding: PositionEncoding::Graphemes,
+            partial_result: false,
+            selection_start_line: None,
+            cursors: vec![],
+            prev_cursors: vec![],
+            track_positions: vec![],
+            lisp_vline_symbols: self.lisp_vline_symbols().unwrap_or(lisp_vline_symbols),
+            lisp_block_comments: self.lisp_block_comments().unwrap_or(lisp_block_comments),
+            guile_block_comments: self.guile_block_comments().unwrap_or(guile_block_comments),
+            scheme_sexp_comments: self.scheme_sexp_comments().unwrap_or(scheme_sexp_comments),
+            janet_long_strings: self.janet_long_strings().unwrap_or(janet_long_strings),
+        })
+    }
+
+    /// The regions to process: one per Kakoune selection, or the whole input.
+    pub fn regions(&self, input: &mut dyn Read) -> Result<Vec<Region>, String> {
+        match self.input_type() {
+            InputType::Kakoune | InputType::KakouneFramed => {
+                let kakoune_input = self.kakoune_input(input)?;
+                let base = self.kakoune_request(&kakoune_input)?;
+                if let Some(text) = kakoune_input.buffer {
+                    return Ok(vec![Region::whole(Request { text, ..base })]);
+                }
+                let descs: Vec<&str> = kakoune_input.selections_desc.iter().map(String::as_str).collect();
+                kakoune::regions(base, kakoune_input.selections, &descs)
+            },
+            _ => {
+                let request = self.request(input).map_err(|e| e.to_string())?;
+                Ok(vec![Region::whole(request)])
+            },
+        }
+    }
+
+    fn kakoune_input(&self, input: &mut dyn Read) -> Result<KakouneInput, String> {
+        match self.input_type() {
+            InputType::KakouneFramed => {
+                let mut frame = String::new();
+                input.read_to_string(&mut frame).map_err(|e| e.to_string())?;
+                KakouneInput::from_frame(&frame)
+            },
+            _ => KakouneInput::from_env(),
+        }
+    }
+
+    // The options every Kakoune region shares; the text comes from each
+    // selection.
+    fn kakoune_request(&self, input: &KakouneInput) -> Result<Request, String> {
+        let Defaults {
+            paren_pairs,
+            lisp_vline_symbols,
+            lisp_block_comments,
+            guile_block_comments,
+            scheme_sexp_comments,
+            janet_long_strings
+        } = language_defaults(parse_language(env::var("kak_opt_filetype").ok()));
+        Ok(Request {
+            mode: self.mode(),
+            text: String::new(),
+            options: types::Options {
+                changes: vec![],
+                cursor_x: kak_number("cursor_char_column", &input.cursor_char_column)?,
+                cursor_line: kak_number("cursor_line", &input.cursor_line)?,
+                // Kakoune buffers are never empty, so an empty previous text
+                // means there wasn't one.
+                prev_text: input.previous_text.clone().filter(|text| !text.is_empty()),
+                prev_cursor_x: kak_number("previous_cursor_char_column", &input.previous_cursor_char_column)?,
+                prev_cursor_line: kak_number("previous_cursor_line", &input.previous_cursor_line)?,
+                force_balance: false,
+                return_parens: false,
+                collect_errors: false,
+                return_edits: false,
+                comment_char: char::from(self.comment_char()),
+                comment_strings: self.comment_strings(),
+                string_delimiters: self.string_delimiters(),
+                paren_pairs: self.paren_pairs(paren_pairs)?,
+                tab_width: self.tab_width()?,
+                preserve_tabs: self.matches.opt_present("preserve-tabs"),
+                // Kakoune counts columns in characters.
+                position_encoding: PositionEncoding::Codepoints,
+                partial_result: false,
+                selection_start_line: None,
+                cursors: vec![],
+                prev_cursors: vec![],
+                track_positions: vec![],
+                lisp_vline_symbols,
+                lisp_block_comments,
+                guile_block_comments,
+                scheme_sexp_comments,
+                janet_long_strings,
+            }
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn for_args(args: &[&str]) -> Request {
+    fn try_args(args: &[&str]) -> io::Result<Request> {
         let input = Vec::new();
         let string_args = args
             .iter()
             .map(|&s| String::from(s))
             .collect::<Vec<String>>();
-        let request = Options::parse(&string_args)
+        Options::parse(&string_args)
             .expect("unable to parse options")
             .request(&mut input.as_slice())
-            .expect("unable to make request");
-        request
+    }
+
+    fn for_args(args: &[&str]) -> Request {
+        try_args(args).expect("unable to make request")
     }
 
     #[test]
@@ -375,4 +590,66 @@ mod tests {
         assert_eq!(for_args(&["--lisp-block-comments"]).options.lisp_block_comments, true);
         assert_eq!(for_args(&["--language=lisp", "--no-lisp-block-comments"]).options.lisp_block_comments, false);
     }
+
+    #[test]
+    fn comment_strings() {
+        assert_eq!(for_args(&[]).options.comment_strings, Vec::<String>::new());
+        assert_eq!(for_args(&["--comment-string=--", "--comment-string=;"]).options.comment_strings,
+                   vec!["--", ";"]);
+    }
+
+    #[test]
+    fn tabs() {
+        assert_eq!(for_args(&[]).options.tab_width, 2);
+        assert!(!for_args(&[]).options.preserve_tabs);
+        assert_eq!(for_args(&["--tab-width=8"]).options.tab_width, 8);
+        assert!(for_args(&["--preserve-tabs"]).options.preserve_tabs);
+        assert!(try_args(&["--tab-width=x"]).is_err());
+    }
+
+    #[test]
+    fn paren_pairs() {
+        assert_eq!(for_args(&[]).options.paren_pairs, vec!["()", "[]", "{}"]);
+        assert_eq!(for_args(&["--language=lisp"]).options.paren_pairs, vec!["()"]);
+        assert_eq!(for_args(&["--paren-pairs=()<>"]).options.paren_pairs, vec!["()", "<>"]);
+        assert_eq!(for_args(&["--language=lisp", "--paren-pairs=()[]"]).options.paren_pairs, vec!["()", "[]"]);
+        assert!(try_args(&["--paren-pairs=()("]).is_err());
+    }
+
+    fn framed_regions(frame: &str) -> Result<Vec<Region>, String> {
+        let args = vec![String::from("--input-format=kakoune-framed")];
+        Options::parse(&args)
+            .expect("unable to parse options")
+            .regions(&mut frame.as_bytes())
+    }
+
+    #[test]
+    fn kakoune_framed_input() {
+        let regions = framed_regions(
+            "cursor_line '2' cursor_char_column '3' previous_cursor_line '' \
+             previous_text '(foo\n  bar' buffer '(foo\n  '\\''bar)'\n"
+        ).expect("unable to read frame");
+        assert_eq!(regions.len(), 1);
+        assert!(regions[0].saved_cursor);
+        let request = &regions[0].request;
+        assert_eq!(request.text, "(foo\n  'bar)");
+        assert_eq!(request.options.prev_text, Some(String::from("(foo\n  bar")));
+        assert_eq!((request.options.cursor_line, request.options.cursor_x), (Some(1), Some(2)));
+        assert_eq!(request.options.prev_cursor_line, None);
+
+        // A selection is only the whole buffer when it is passed as `buffer`.
+        let regions = framed_regions(
+            "cursor_line '2' cursor_char_column '3' previous_text '(foo\n  bar' \
+             selections '1' '(foo' selections_desc '1' '1.1,1.4'\n"
+        ).expect("unable to read frame");
+        assert!(!regions[0].saved_cursor);
+        assert_eq!(regions[0].request.options.prev_text, None);
+        assert_eq!((regions[0].request.options.cursor_line, regions[0].request.options.cursor_x), (Some(0), Some(3)));
+
+        assert!(framed_regions("buffer '(a' selections '1' '(a' selections_desc '1' '1.1,1.2'").is_err());
+
+        assert!(framed_regions("cursor_line 'x' selections '0' selections_desc '0'").is_err());
+        assert!(framed_regions("selections '2' '(a'").is_err());
+        assert!(framed_regions("selection '(a'").is_err());
+    }
 }
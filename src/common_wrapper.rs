@@ -1,6 +1,7 @@
 use super::*;
 use std::borrow::Cow;
 use types::*;
+use document::{Document, DocumentUpdate};
 
 pub fn internal_run(json_str: &str) -> Result<String, Error> {
     let request: Request = serde_json::from_str(json_str)?;
@@ -8,20 +9,37 @@ pub fn internal_run(json_str: &str) -> Result<String, Error> {
     Ok(serde_json::to_string(&Answer::from(answer))?)
 }
 
+pub fn new_document(json_str: &str) -> Result<Document, Error> {
+    let request: Request = serde_json::from_str(json_str)?;
+    Ok(Document::from(request))
+}
+
+pub fn update_document(document: &mut Document, json_str: &str) -> Result<String, Error> {
+    let update: DocumentUpdate = serde_json::from_str(json_str)?;
+    let answer = document.update(update)?;
+    Ok(serde_json::to_string(answer)?)
+}
+
 pub fn panic_result() -> String {
+    let error = Error {
+        name: ErrorName::Panic,
+        message: String::from("plugin panicked!"),
+        x: 0,
+        line_no: 0,
+        input_x: 0,
+        input_line_no: 0,
+        extra: None,
+    };
     let answer = Answer {
         text: Cow::from(""),
         success: false,
-        error: Some(Error {
-            name: ErrorName::Panic,
-            message: String::from("plugin panicked!"),
-            x: 0,
-            line_no: 0,
-            input_x: 0,
-            input_line_no: 0,
-        }),
+        errors: vec![error.clone()],
+        error: Some(error),
         cursor_x: None,
         cursor_line: None,
+        cursors: vec![],
+        tracked_positions: vec![],
+        edits: vec![],
         tab_stops: vec![],
         paren_trails: vec![],
         parens: vec![]
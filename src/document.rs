@@ -0,0 +1,150 @@
+//! An editing session which remembers the text and cursor between runs.
+//!
+//! Instead of sending the whole buffer (and the previous one) for every
+//! keystroke, an editor hands a `Document` the edits it made and where the
+//! cursor went.  The document keeps track of `changes` and the previous
+//! cursor position for smart mode, and only reprocesses the top-level forms
+//! around the edits.
+
+use changes;
+use parinfer;
+use types::*;
+
+/// Edits and a cursor move to apply to a `Document` before processing it.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentUpdate {
+    #[serde(default)]
+    pub changes: Vec<Change>,
+    pub cursor_x: Option<Column>,
+    pub cursor_line: Option<LineNumber>,
+    /// Replaces the other cursors, if given.
+    pub cursors: Option<Vec<Cursor>>,
+}
+
+/// A buffer whose text and cursor are remembered between runs.
+pub struct Document {
+    mode: Mode,
+    text: String,
+    // Holds the cursor, the cursor at the last run, and the changes made
+    // since the last run, along with the language settings.
+    options: Options,
+    answer: Option<OwnedAnswer>,
+    // Lets the next run skip the top-level forms that weren't edited.
+    cache: Option<parinfer::Cache>,
+}
+
+impl Document {
+    /// `options.prev_text` and `options.changes` are ignored; the document
+    /// tracks edits itself from here on.
+    pub fn new(mode: Mode, text: String, options: Options) -> Document {
+        let mut options = options;
+        options.prev_text = None;
+        options.changes = vec![];
+        options.prev_cursor_x = options.cursor_x;
+        options.prev_cursor_line = options.cursor_line;
+        options.prev_cursors = options.cursors.clone();
+        Document {
+            mode,
+            text,
+            options,
+            answer: None,
+            cache: None,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> (Option<Column>, Option<LineNumber>) {
+        (self.options.cursor_x, self.options.cursor_line)
+    }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// The answer from the last call to `process`, if any.
+    pub fn answer(&self) -> Option<&OwnedAnswer> {
+        self.answer.as_ref()
+    }
+
+    pub fn move_cursor(&mut self, x: Column, line_no: LineNumber) {
+        self.options.cursor_x = Some(x);
+        self.options.cursor_line = Some(line_no);
+    }
+
+    /// Sets the cursors besides the main one.
+    pub fn set_cursors(&mut self, cursors: Vec<Cursor>) {
+        self.options.cursors = cursors;
+    }
+
+    pub fn set_selection_start_line(&mut self, line_no: Option<LineNumber>) {
+        self.options.selection_start_line = line_no;
+    }
+
+    /// Applies an edit made by the user.  The text is left untouched if
+    /// `change.old_text` isn't found at the change's position.
+    pub fn edit(&mut self, change: Change) -> Result<(), Error> {
+        self.text = changes::apply_change(&self.text, &change, &self.options)?;
+        self.options.changes.push(change);
+        Ok(())
+    }
+
+    /// Runs parinfer over the edits made since the last run.  On success
+    /// the document takes on the corrected text and cursor.
+    pub fn process(&mut self) -> &OwnedAnswer {
+        let (answer, cache) = parinfer::run_mode_cached(self.mode, &self.text, &self.options,
+                                                        self.cache.as_ref());
+        let answer = answer.into_owned();
+        self.cache = cache;
+        self.options.changes.clear();
+        if answer.success {
+            self.text = answer.text.to_string();
+            self.options.cursor_x = answer.cursor_x;
+            self.options.cursor_line = answer.cursor_line;
+            self.options.cursors = answer.cursors.clone();
+            self.options.track_positions = answer.tracked_positions.clone();
+        }
+        self.options.prev_cursor_x = self.options.cursor_x;
+        self.options.prev_cursor_line = self.options.cursor_line;
+        self.options.prev_cursors = self.options.cursors.clone();
+        self.answer.insert(answer)
+    }
+
+    /// Applies `update`'s edits in order, moves the cursor and processes the
+    /// document.  If any edit doesn't apply, the document is left as it was.
+    pub fn update(&mut self, update: DocumentUpdate) -> Result<&OwnedAnswer, Error> {
+        let mut text = self.text.clone();
+        for change in &update.changes {
+            text = changes::apply_change(&text, change, &self.options)?;
+        }
+        self.text = text;
+        self.options.changes.extend(update.changes);
+        if update.cursor_x.is_some() {
+            self.options.cursor_x = update.cursor_x;
+        }
+        if update.cursor_line.is_some() {
+            self.options.cursor_line = update.cursor_line;
+        }
+        if let Some(cursors) = update.cursors {
+            self.options.cursors = cursors;
+        }
+        Ok(self.process())
+    }
+}
+
+impl From<Request> for Document {
+    fn from(request: Request) -> Document {
+        Document::new(request.mode, request.text, request.options)
+    }
+}
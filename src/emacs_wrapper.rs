@@ -1,12 +1,13 @@
-use super::parinfer::rc_process;
+use super::parinfer::process;
 use emacs::{Env, IntoLisp, Result, Value};
-use types::{Change, Error, Options, Request, SharedRequest, WrappedAnswer};
+use types::{Change, Closer, Cursor, Error, Options, OwnedAnswer, Paren, ParenTrail, PositionEncoding, Request,
+            TabStop};
+use document::Document;
 
 use std::{fs::OpenOptions,
           io::Write,
           convert::TryFrom,
-          cell::RefCell,
-          rc::Rc};
+          cell::RefCell};
 
 emacs::plugin_is_GPL_compatible!();
 
@@ -33,18 +34,19 @@ fn to_i64(value: Option<usize>) -> Option<i64> {
   }
 }
 
+// The error signaled when a plist request can't be understood.  Its data is
+// a message and the offending value.
+const INVALID_REQUEST: &str = "parinfer-rust-invalid-request";
+
 #[emacs::module(name = "parinfer-rust")]
-pub fn init(_: &Env) -> Result<()> {
+pub fn init(env: &Env) -> Result<()> {
+  env.call("define-error", (env.intern(INVALID_REQUEST)?, "Invalid parinfer-rust request"))?;
   Ok(())
 }
 
 ////////////////////////////////
 // Entry point
 ///////////////////////////////
-// Need to wrap Request into a specific lifetime for use as part of this struct are used in the Answer struct
-// this is talked about here https://github.com/ubolonton/emacs-module-rs/issues/21
-type AliasedRequest<'a> = &'a SharedRequest;
-
 //  https://github.com/shaunlebron/parinfer/tree/master/lib#api
 // text is the full text input.
 // options is an object with the following properties:
@@ -92,10 +94,8 @@ type AliasedRequest<'a> = &'a SharedRequest;
 /// ```elisp,no_run
 /// (parinfer-rust-execute request)
 /// ```
-fn execute(request: AliasedRequest) -> Result<WrappedAnswer> {
-  let answer = rc_process(&request);
-  let wrapped_answer = unsafe{WrappedAnswer::new(request.clone(), answer)};
-  Ok(wrapped_answer)
+fn execute(request: &Request) -> Result<OwnedAnswer> {
+  Ok(process(request).into_owned())
 }
 ////////////////////////////////
 // options
@@ -112,23 +112,8 @@ fn execute(request: AliasedRequest) -> Result<WrappedAnswer> {
 /// ```
 fn make_option() -> Result<Options> {
   Ok(Options {
-    cursor_x: None,
-    cursor_line: None,
-    prev_cursor_x: None,
-    prev_cursor_line: None,
-    prev_text: None,
-    selection_start_line: None,
-    changes: Vec::new(),
-    partial_result: false,
-    force_balance: false,
-    return_parens: false,
-    comment_char: ';',
     string_delimiters: Vec::new(),
-    lisp_vline_symbols: false,
-    lisp_block_comments: false,
-    guile_block_comments: false,
-    scheme_sexp_comments: false,
-    janet_long_strings: false,
+    ..Options::default()
   })
 }
 
@@ -154,17 +139,8 @@ fn new_options(
     prev_cursor_line: old_options.cursor_line,
     selection_start_line: to_usize(selection_start_line),
     changes: changes.clone(),
-    prev_text: None,
-    partial_result: false,
-    force_balance: false,
-    return_parens: false,
-    comment_char: ';',
     string_delimiters: Vec::new(),
-    lisp_vline_symbols: false,
-    lisp_block_comments: false,
-    guile_block_comments: false,
-    scheme_sexp_comments: false,
-    janet_long_strings: false,
+    ..Options::default()
   })
 }
 
@@ -235,7 +211,7 @@ fn print_changes<'a>(env: &'a Env, changes: &mut Vec<Change>) -> Result<Value<'a
 ////////////////////////////////
 // Request
 ////////////////////////////////
-#[defun(mod_in_name = false)]
+#[defun(user_ptr, mod_in_name = false)]
 
 /// Creates a Request from the given mode, current buffer text, and the set of Options
 ///
@@ -245,13 +221,13 @@ fn print_changes<'a>(env: &'a Env, changes: &mut Vec<Change>) -> Result<Value<'a
 /// (parinfer-make-request "paren" (buffer-substring-no-properties) options)
 /// ```
 //
-fn make_request(mode: String, text: String, options: &mut Options) -> Result<SharedRequest> {
+fn make_request(mode: String, text: String, options: &mut Options) -> Result<Request> {
   let request = Request {
-    mode,
+    mode: mode.parse()?,
     text,
     options: options.clone(),
   };
-  Ok(Rc::new(request))
+  Ok(request)
 }
 
 /// Creates a Request from the given mode, current buffer text, and the set of Options
@@ -263,15 +239,392 @@ fn make_request(mode: String, text: String, options: &mut Options) -> Result<Sha
 /// ```
 //
 #[defun(mod_in_name = false)]
-fn print_request(request: AliasedRequest) -> Result<String> {
+fn print_request(request: &Request) -> Result<String> {
   Ok(format!("{:?}", &request).to_string())
 }
+
+////////////////////////////////
+// Plist requests
+////////////////////////////////
+/// Signals `parinfer-rust-invalid-request` with `message` and `value`
+fn invalid<'a, T>(env: &'a Env, message: String, value: Value<'a>) -> Result<T> {
+  let data = env.list((message.as_str(), value))?;
+  // `signal` never returns; the error it comes back with carries the
+  // pending signal out of the defun.
+  match env.call("signal", (env.intern(INVALID_REQUEST)?, data)) {
+    Err(error) => Err(error),
+    Ok(_) => unreachable!("signal returned normally"),
+  }
+}
+
+fn is<'a>(env: &'a Env, predicate: &str, value: Value<'a>) -> Result<bool> {
+  Ok(env.call(predicate, (value,))?.is_not_nil())
+}
+
+fn list_to_vec<'a>(env: &'a Env, key: &str, list: Value<'a>) -> Result<Vec<Value<'a>>> {
+  if !is(env, "listp", list)? {
+    return invalid(env, format!(":{} must be a list", key), list);
+  }
+  let mut items = Vec::new();
+  let mut rest = list;
+  while rest.is_not_nil() {
+    items.push(rest.car()?);
+    rest = rest.cdr()?;
+  }
+  Ok(items)
+}
+
+/// Calls `f` with the name of each key of a plist, without its colon, and
+/// the key and its value
+fn for_each_in_plist<'a, F>(env: &'a Env, what: &str, plist: Value<'a>, mut f: F) -> Result<()>
+where
+  F: FnMut(&str, Value<'a>, Value<'a>) -> Result<()>,
+{
+  if !is(env, "listp", plist)? {
+    return invalid(env, format!("{} must be a plist", what), plist);
+  }
+  let mut rest = plist;
+  while rest.is_not_nil() {
+    let key: Value = rest.car()?;
+    if !is(env, "keywordp", key)? {
+      return invalid(env, format!("{} keys must be keywords", what), key);
+    }
+    let tail: Value = rest.cdr()?;
+    if !tail.is_not_nil() {
+      return invalid(env, format!("{} has no value for a key", what), key);
+    }
+    let name: String = env.call("symbol-name", (key,))?.into_rust()?;
+    f(&name[1..], key, tail.car()?)?;
+    rest = tail.cdr()?;
+  }
+  Ok(())
+}
+
+fn natural<'a>(env: &'a Env, key: &str, value: Value<'a>) -> Result<usize> {
+  if !is(env, "natnump", value)? {
+    return invalid(env, format!(":{} must be a non-negative integer", key), value);
+  }
+  Ok(usize::try_from(value.into_rust::<i64>()?)?)
+}
+
+fn optional_natural<'a>(env: &'a Env, key: &str, value: Value<'a>) -> Result<Option<usize>> {
+  if value.is_not_nil() {
+    Ok(Some(natural(env, key, value)?))
+  } else {
+    Ok(None)
+  }
+}
+
+fn string<'a>(env: &'a Env, key: &str, value: Value<'a>) -> Result<String> {
+  if !is(env, "stringp", value)? {
+    return invalid(env, format!(":{} must be a string", key), value);
+  }
+  value.into_rust()
+}
+
+fn strings<'a>(env: &'a Env, key: &str, value: Value<'a>) -> Result<Vec<String>> {
+  list_to_vec(env, key, value)?
+    .into_iter()
+    .map(|item| string(env, key, item))
+    .collect()
+}
+
+/// Reads a character, given either as one, e.g. ?\;, or as a string of one
+fn character<'a>(env: &'a Env, key: &str, value: Value<'a>) -> Result<char> {
+  if is(env, "characterp", value)? {
+    if let Some(ch) = std::char::from_u32(value.into_rust::<i64>()? as u32) {
+      return Ok(ch);
+    }
+  } else if is(env, "stringp", value)? {
+    let text: String = value.into_rust()?;
+    let mut chars = text.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+      return Ok(ch);
+    }
+  }
+  invalid(env, format!(":{} must be a character or a string of one character", key), value)
+}
+
+/// Reads a change such as (:line_no 0 :x 6 :old_text "" :new_text " 1")
+fn change_from_plist<'a>(env: &'a Env, plist: Value<'a>) -> Result<Change> {
+  let (mut x, mut line_no, mut old_text, mut new_text) = (None, None, None, None);
+  for_each_in_plist(env, "A change", plist, |name, key, value| {
+    match name {
+      "x" => x = Some(natural(env, name, value)?),
+      "line_no" => line_no = Some(natural(env, name, value)?),
+      "old_text" => old_text = Some(string(env, name, value)?),
+      "new_text" => new_text = Some(string(env, name, value)?),
+      _ => return invalid(env, format!("Unknown change key :{}", name), key),
+    }
+    Ok(())
+  })?;
+  match (x, line_no, old_text, new_text) {
+    (Some(x), Some(line_no), Some(old_text), Some(new_text)) => Ok(Change { x, line_no, old_text, new_text }),
+    _ => invalid(env, String::from("A change needs :line_no, :x, :old_text and :new_text"), plist),
+  }
+}
+
+/// Reads a cursor such as (:line_no 2 :x 4 :selection_start_line 1)
+fn cursor_from_plist<'a>(env: &'a Env, plist: Value<'a>) -> Result<Cursor> {
+  let (mut x, mut line_no, mut selection_start_line) = (None, None, None);
+  for_each_in_plist(env, "A cursor", plist, |name, key, value| {
+    match name {
+      "x" => x = Some(natural(env, name, value)?),
+      "line_no" => line_no = Some(natural(env, name, value)?),
+      "selection_start_line" => selection_start_line = optional_natural(env, name, value)?,
+      _ => return invalid(env, format!("Unknown cursor key :{}", name), key),
+    }
+    Ok(())
+  })?;
+  match (x, line_no) {
+    (Some(x), Some(line_no)) => Ok(Cursor { x, line_no, selection_start_line }),
+    _ => invalid(env, String::from("A cursor needs :line_no and :x"), plist),
+  }
+}
+
+fn cursors<'a>(env: &'a Env, key: &str, value: Value<'a>) -> Result<Vec<Cursor>> {
+  list_to_vec(env, key, value)?
+    .into_iter()
+    .map(|cursor| cursor_from_plist(env, cursor))
+    .collect()
+}
+
+/// Reads positions given as (LINE . X) conses
+fn positions<'a>(env: &'a Env, key: &str, value: Value<'a>) -> Result<Vec<(usize, usize)>> {
+  list_to_vec(env, key, value)?
+    .into_iter()
+    .map(|position| {
+      if !is(env, "consp", position)? {
+        return invalid(env, format!(":{} must hold (LINE . X) conses", key), position);
+      }
+      Ok((natural(env, key, position.car()?)?, natural(env, key, position.cdr()?)?))
+    })
+    .collect()
+}
+
+fn options_from_plist<'a>(env: &'a Env, plist: Value<'a>) -> Result<Options> {
+  let mut options = Options::default();
+  for_each_in_plist(env, "Options", plist, |name, key, value| {
+    match name {
+      "cursor_x" => options.cursor_x = optional_natural(env, name, value)?,
+      "cursor_line" => options.cursor_line = optional_natural(env, name, value)?,
+      "prev_cursor_x" => options.prev_cursor_x = optional_natural(env, name, value)?,
+      "prev_cursor_line" => options.prev_cursor_line = optional_natural(env, name, value)?,
+      "prev_text" => options.prev_text = if value.is_not_nil() { Some(string(env, name, value)?) } else { None },
+      "selection_start_line" => options.selection_start_line = optional_natural(env, name, value)?,
+      "cursors" => options.cursors = cursors(env, name, value)?,
+      "prev_cursors" => options.prev_cursors = cursors(env, name, value)?,
+      "track_positions" => options.track_positions = positions(env, name, value)?,
+      "changes" => {
+        options.changes = list_to_vec(env, name, value)?
+          .into_iter()
+          .map(|change| change_from_plist(env, change))
+          .collect::<Result<Vec<Change>>>()?
+      }
+      "partial_result" => options.partial_result = value.is_not_nil(),
+      "force_balance" => options.force_balance = value.is_not_nil(),
+      "return_parens" => options.return_parens = value.is_not_nil(),
+      "collect_errors" => options.collect_errors = value.is_not_nil(),
+      "return_edits" => options.return_edits = value.is_not_nil(),
+      "comment_char" => options.comment_char = character(env, name, value)?,
+      "comment_strings" => options.comment_strings = strings(env, name, value)?,
+      "string_delimiters" => options.string_delimiters = strings(env, name, value)?,
+      "paren_pairs" => options.paren_pairs = strings(env, name, value)?,
+      "tab_width" => options.tab_width = natural(env, name, value)?,
+      "preserve_tabs" => options.preserve_tabs = value.is_not_nil(),
+      "position_encoding" => {
+        options.position_encoding = match string(env, name, value)?.as_ref() {
+          "graphemes" => PositionEncoding::Graphemes,
+          "utf16" => PositionEncoding::Utf16,
+          "utf8-bytes" => PositionEncoding::Utf8Bytes,
+          "codepoints" => PositionEncoding::Codepoints,
+          _ => return invalid(env,
+                              String::from(":position_encoding must be \"graphemes\", \"utf16\", \"utf8-bytes\" or \"codepoints\""),
+                              value),
+        }
+      }
+      "lisp_vline_symbols" => options.lisp_vline_symbols = value.is_not_nil(),
+      "lisp_block_comments" => options.lisp_block_comments = value.is_not_nil(),
+      "guile_block_comments" => options.guile_block_comments = value.is_not_nil(),
+      "scheme_sexp_comments" => options.scheme_sexp_comments = value.is_not_nil(),
+      "janet_long_strings" => options.janet_long_strings = value.is_not_nil(),
+      _ => return invalid(env, format!("Unknown option :{}", name), key),
+    }
+    Ok(())
+  })?;
+  Ok(options)
+}
+
+#[defun(user_ptr, mod_in_name = false)]
+/// Runs the parinfer algorithm on TEXT in MODE ("indent", "paren" or "smart")
+/// with OPTIONS given as a plist, without building Options, Changes and a
+/// Request first
+///
+/// The keys are those of the JSON options in snake_case, e.g. :cursor_x, and
+/// :changes is a list of plists with :line_no, :x, :old_text and :new_text.
+/// Options which are left out take their defaults.  Unknown keys and values
+/// of the wrong type signal `parinfer-rust-invalid-request`.
+///
+/// # Examples
+///
+/// ```elisp,no_run
+/// (parinfer-rust-execute-plist "smart" "(def x" '(:cursor_x 6 :cursor_line 0
+///                                                  :changes ((:line_no 0 :x 5 :old_text "" :new_text "x"))))
+/// ```
+fn execute_plist(env: &Env, mode: String, text: String, options: Value<'_>) -> Result<OwnedAnswer> {
+  let mode = match mode.parse() {
+    Ok(mode) => mode,
+    Err(_) => return invalid(env, String::from("The mode must be \"indent\", \"paren\" or \"smart\""),
+                             mode.into_lisp(env)?),
+  };
+  let options = options_from_plist(env, options)?;
+  Ok(process(&Request { mode, text, options }).into_owned())
+}
+////////////////////////////////
+// Document
+////////////////////////////////
+#[defun(user_ptr, mod_in_name = false)]
+/// Creates a Document, which remembers the buffer text and cursor between runs
+/// so that only the edits need to be passed in afterwards
+///
+/// # Examples
+///
+/// ```elisp,no_run
+/// (parinfer-rust-make-document "smart" (buffer-substring-no-properties (point-min) (point-max)) options)
+/// ```
+fn make_document(mode: String, text: String, options: &Options) -> Result<Document> {
+  Ok(Document::new(mode.parse()?, text, options.clone()))
+}
+
+#[defun(mod_in_name = false)]
+/// Applies a change to the Document's text; signals an error if the change's
+/// old text isn't in the document
+///
+/// # Examples
+///
+/// ```elisp,no_run
+/// (parinfer-rust-document-edit document change)
+/// ```
+fn document_edit(document: &mut Document, change: &Change) -> Result<()> {
+  Ok(document.edit(change.clone())?)
+}
+
+#[defun(mod_in_name = false)]
+/// Moves the Document's cursor
+///
+/// # Examples
+///
+/// ```elisp,no_run
+/// (parinfer-rust-document-move-cursor document 4 0)
+/// ```
+fn document_move_cursor(document: &mut Document, cursor_x: i64, cursor_line: i64) -> Result<()> {
+  document.move_cursor(usize::try_from(cursor_x)?, usize::try_from(cursor_line)?);
+  Ok(())
+}
+
+#[defun(mod_in_name = false)]
+/// Switches the mode used the next time the Document is processed
+///
+/// # Examples
+///
+/// ```elisp,no_run
+/// (parinfer-rust-document-set-mode document "paren")
+/// ```
+fn document_set_mode(document: &mut Document, mode: String) -> Result<()> {
+  document.set_mode(mode.parse()?);
+  Ok(())
+}
+
+#[defun(user_ptr, mod_in_name = false)]
+/// Runs parinfer over the edits made since the Document was last processed
+///
+/// # Examples
+///
+/// ```elisp,no_run
+/// (parinfer-rust-document-process document)
+/// ```
+fn document_process(document: &mut Document) -> Result<OwnedAnswer> {
+  Ok(document.process().clone())
+}
+
+#[defun(mod_in_name = false)]
+/// Returns the Document's current text
+///
+/// # Examples
+///
+/// ```elisp,no_run
+/// (parinfer-rust-document-text document)
+/// ```
+fn document_text(document: &Document) -> Result<String> {
+  Ok(document.text().to_string())
+}
+
 ////////////////////////////////
 // Answer
 ////////////////////////////////
+// A plist for a tab stop, e.g. (:ch "(" :x 0 :line_no 0 :arg_x 5)
+fn tab_stop_to_lisp<'a>(env: &'a Env, tab_stop: &TabStop) -> Result<Value<'a>> {
+  env.list(&[
+    env.intern(":ch")?, tab_stop.ch.to_string().into_lisp(env)?,
+    env.intern(":x")?, to_i64(Some(tab_stop.x)).into_lisp(env)?,
+    env.intern(":line_no")?, to_i64(Some(tab_stop.line_no)).into_lisp(env)?,
+    env.intern(":arg_x")?, to_i64(tab_stop.arg_x).into_lisp(env)?,
+  ])
+}
+
+// A plist for a paren trail, e.g. (:line_no 0 :start_x 6 :end_x 8)
+fn paren_trail_to_lisp<'a>(env: &'a Env, trail: &ParenTrail) -> Result<Value<'a>> {
+  env.list(&[
+    env.intern(":line_no")?, to_i64(Some(trail.line_no)).into_lisp(env)?,
+    env.intern(":start_x")?, to_i64(Some(trail.start_x)).into_lisp(env)?,
+    env.intern(":end_x")?, to_i64(Some(trail.end_x)).into_lisp(env)?,
+  ])
+}
+
+fn closer_to_lisp<'a>(env: &'a Env, closer: &Closer) -> Result<Value<'a>> {
+  env.list(&[
+    env.intern(":line_no")?, to_i64(Some(closer.line_no)).into_lisp(env)?,
+    env.intern(":x")?, to_i64(Some(closer.x)).into_lisp(env)?,
+    env.intern(":ch")?, closer.ch.to_string().into_lisp(env)?,
+    env.intern(":trail")?, match &closer.trail {
+      Some(trail) => paren_trail_to_lisp(env, trail)?,
+      None => ().into_lisp(env)?,
+    },
+  ])
+}
+
+// A plist for an open-paren, with its :closer and its :children as a list
+// of the same
+fn paren_to_lisp<'a>(env: &'a Env, paren: &Paren) -> Result<Value<'a>> {
+  let children = paren.children.iter()
+    .map(|child| paren_to_lisp(env, child))
+    .collect::<Result<Vec<Value>>>()?;
+  env.list(&[
+    env.intern(":line_no")?, to_i64(Some(paren.line_no)).into_lisp(env)?,
+    env.intern(":ch")?, paren.ch.to_string().into_lisp(env)?,
+    env.intern(":x")?, to_i64(Some(paren.x)).into_lisp(env)?,
+    env.intern(":indent_delta")?, paren.indent_delta.into_lisp(env)?,
+    env.intern(":max_child_indent")?, to_i64(paren.max_child_indent).into_lisp(env)?,
+    env.intern(":arg_x")?, to_i64(paren.arg_x).into_lisp(env)?,
+    env.intern(":input_line_no")?, to_i64(Some(paren.input_line_no)).into_lisp(env)?,
+    env.intern(":input_x")?, to_i64(Some(paren.input_x)).into_lisp(env)?,
+    env.intern(":closer")?, match &paren.closer {
+      Some(closer) => closer_to_lisp(env, closer)?,
+      None => ().into_lisp(env)?,
+    },
+    env.intern(":children")?, env.list(&children[..])?,
+  ])
+}
+
 #[defun(mod_in_name = false)]
 /// Gives a hashmap like interface to extracting values from the Answer type
-/// Accepted keys are 'text', 'success', 'cursor_x', 'cursor_line', and 'error'
+/// Accepted keys are 'text', 'success', 'cursor_x', 'cursor_line', 'error', 'errors',
+/// 'tab_stops', 'paren_trails' and 'parens'
+///
+/// 'tab_stops', 'paren_trails' and 'parens' are lists of plists, e.g.
+/// (:line_no 0 :start_x 6 :end_x 8) for a paren trail.  Each paren has a
+/// :closer plist and a list of :children parens.  Parens are only returned
+/// when the request's options ask for them.
 ///
 /// # Examples
 ///
@@ -280,17 +633,14 @@ fn print_request(request: AliasedRequest) -> Result<String> {
 /// ```
 fn get_in_answer<'a>(
   env: &'a Env,
-  answer: &WrappedAnswer,
+  unwrapped_answer: &OwnedAnswer,
   key: Option<String>,
 ) -> Result<Value<'a>> {
-  let unwrapped_answer = answer.inner();
   let query = match key {
     Some(key) => key,
     None => return env.message("Missing 'key'"),
   };
 
-  // I only care about some nested structures at the moment, errors,
-  // so leave tab_stops, paren_trails, and parens as unsupported
   match query.as_ref() {
     "text" => unwrapped_answer.text.to_string().into_lisp(env),
     "success" => unwrapped_answer.success.into_lisp(env),
@@ -300,9 +650,30 @@ fn get_in_answer<'a>(
       Some(error) => Ok(RefCell::new(error).into_lisp(env)?),
       None => ().into_lisp(env),
     }
-    // "tab_stops"
-    // "paren_trails"
-    // "parens"
+    "errors" => {
+      let errors = unwrapped_answer.errors.iter()
+        .map(|error| RefCell::new(error.clone()).into_lisp(env))
+        .collect::<Result<Vec<Value>>>()?;
+      env.list(&errors[..])
+    }
+    "tab_stops" => {
+      let tab_stops = unwrapped_answer.tab_stops.iter()
+        .map(|tab_stop| tab_stop_to_lisp(env, tab_stop))
+        .collect::<Result<Vec<Value>>>()?;
+      env.list(&tab_stops[..])
+    }
+    "paren_trails" => {
+      let trails = unwrapped_answer.paren_trails.iter()
+        .map(|trail| paren_trail_to_lisp(env, trail))
+        .collect::<Result<Vec<Value>>>()?;
+      env.list(&trails[..])
+    }
+    "parens" => {
+      let parens = unwrapped_answer.parens.iter()
+        .map(|paren| paren_to_lisp(env, paren))
+        .collect::<Result<Vec<Value>>>()?;
+      env.list(&parens[..])
+    }
     _ => {
       env.message(format!("Key '{}' unsupported", query))?;
       ().into_lisp(env)},
@@ -317,8 +688,8 @@ fn get_in_answer<'a>(
 /// ```elisp,no_run
 /// (parinfer-rust-print-answer answer)
 /// ```
-fn print_answer(answer: &WrappedAnswer) -> Result<String> {
-  Ok(format!("{:?}", answer.inner()).to_string())
+fn print_answer(answer: &OwnedAnswer) -> Result<String> {
+  Ok(format!("{:?}", answer).to_string())
 }
 
 #[defun(mod_in_name = false)]
@@ -329,8 +700,7 @@ fn print_answer(answer: &WrappedAnswer) -> Result<String> {
 /// ```elisp,no_run
 /// (parinfer-rust-debug "/tmp/parinfer.txt" options answer)
 /// ```
-fn debug(env: &Env, filename: String, options: &Options, wrapped_answer: &WrappedAnswer) -> Result<()> {
-  let answer = wrapped_answer.inner();
+fn debug(env: &Env, filename: String, options: &Options, answer: &OwnedAnswer) -> Result<()> {
   let file = match OpenOptions::new().append(true).create(true).open(&filename) {
     Ok(file) => file,
     Err(_) => {
@@ -355,7 +725,7 @@ fn debug(env: &Env, filename: String, options: &Options, wrapped_answer: &Wrappe
 ////////////////////////////////
 #[defun(mod_in_name = false)]
 /// Gives a hashmap like interface to extracting values from the error type
-/// Accepted keys are 'text', 'success', 'cursor_x', 'cursor_line', and 'error'
+/// Accepted keys are 'name', 'message', 'x', 'line_no', 'input_x', 'input_line_no', and 'extra'
 ///
 /// # Examples
 ///
@@ -375,6 +745,15 @@ fn get_in_error<'a>(env: &'a Env, error: &Error, key: Option<String>)-> Result<V
     "line_no" => to_i64(Some(error.line_no)).into_lisp(env),
     "input_x" => to_i64(Some(error.input_x)).into_lisp(env),
     "input_line_no" => to_i64(Some(error.input_line_no)).into_lisp(env),
+    // A plist locating the related open-paren, e.g. (:name "unmatched-open-paren" :line_no 0 :x 5)
+    "extra" => match &error.extra {
+      Some(extra) => env.list(&[
+        env.intern(":name")?, extra.name.to_string().into_lisp(env)?,
+        env.intern(":line_no")?, to_i64(Some(extra.line_no)).into_lisp(env)?,
+        env.intern(":x")?, to_i64(Some(extra.x)).into_lisp(env)?,
+      ]),
+      None => ().into_lisp(env),
+    },
     _ => {
       env.message(format!("Key '{}' unsupported", query))?; // Can return an error
       ().into_lisp(env)
@@ -1,6 +1,7 @@
-use parinfer::chomp_cr;
+use changes;
+use parinfer;
 use types::*;
-use std::env;
+use unicode_segmentation::UnicodeSegmentation;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Coord {
@@ -32,109 +33,238 @@ impl Selection {
     }
 }
 
+/// Replaces the text in `selection` with `text`.
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Insertion {
-    pub cursor: Coord,
+pub struct Fix {
+    pub selection: Selection,
     pub text: String
 }
 
-impl Insertion {
-    fn new(cursor_line: LineNumber, cursor_column: Column, text: &str) -> Insertion {
-        Insertion {
-            cursor: Coord {
-                line: cursor_line,
-                column: cursor_column
-            },
-            text: text.to_string()
+// Kakoune coordinates are one-based, with columns counted in bytes.
+fn coord(text: &str, offset: usize) -> Coord {
+    let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    Coord {
+        line: text[..offset].matches('\n').count() + 1,
+        column: offset - line_start + 1
+    }
+}
+
+// Whether the selections for splices `a` and `b` would overlap once each
+// takes the character after it, or before it at the end of the text.
+fn overlap(from: &str, a: &changes::Splice, b: &changes::Splice) -> bool {
+    let a_end = a.range.end + from[a.range.end..].graphemes(true).next().map(|next| next.len()).unwrap_or(0);
+    let b_start = if b.range.end < from.len() {
+        b.range.start
+    } else {
+        b.range.start - from[..b.range.start].graphemes(true).next_back().map(|prev| prev.len()).unwrap_or(0)
+    };
+    b_start < a_end
+}
+
+// Joins splices whose selections would overlap.  Joining can carry a splice
+// to the end of the text, so it is checked against the ones before it again.
+fn merge_close_splices(from: &str, splices: Vec<changes::Splice>) -> Vec<changes::Splice> {
+    let mut merged: Vec<changes::Splice> = vec![];
+    for splice in splices {
+        merged.push(splice);
+        while merged.len() > 1 && overlap(from, &merged[merged.len() - 2], &merged[merged.len() - 1]) {
+            let splice = merged.pop().unwrap();
+            let last = merged.last_mut().unwrap();
+            last.text.push_str(&from[last.range.end..splice.range.start]);
+            last.text.push_str(&splice.text);
+            last.range.end = splice.range.end;
         }
     }
+    merged
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct Fixes {
-    pub deletions: Vec<Selection>,
-    pub insertions: Vec<Insertion>
+/// The smallest replacements which turn `from` into `to`.
+///
+/// Kakoune selections can't be empty, so each fix also selects the
+/// character after the changed text and puts it back afterwards.
+pub fn fixes(from: &str, to: &str) -> Vec<Fix> {
+    let mut result = vec![];
+    for splice in merge_close_splices(from, changes::splices(from, to)) {
+        let (start, end, text) = match from[splice.range.end..].graphemes(true).next() {
+            Some(next) => (splice.range.start, splice.range.end + next.len(), splice.text + next),
+            None => match from[..splice.range.start].graphemes(true).next_back() {
+                Some(prev) => (splice.range.start - prev.len(), splice.range.end, String::from(prev) + &splice.text),
+                None if !splice.range.is_empty() => (splice.range.start, splice.range.end, splice.text),
+                None => continue,
+            },
+        };
+        // The selection ends at the start of its last character.
+        let last = from[..end].chars().next_back().map(|ch| end - ch.len_utf8()).unwrap_or(start);
+        let (anchor, cursor) = (coord(from, start), coord(from, last));
+        result.push(Fix {
+            selection: Selection::new(anchor.line, anchor.column, cursor.line, cursor.column),
+            text
+        });
+    }
+    result
 }
 
-pub fn fixes<'a>(from: &'a str, to: &'a str) -> Fixes {
-    let mut result = Fixes {
-        insertions: vec![],
-        deletions: vec![]
-    };
+/// A selection which parinfer processes on its own, starting at `start` in
+/// the buffer.
+pub struct Region {
+    pub start: Coord,
+    pub request: Request,
+    /// Whether the request's cursor is the one saved in the
+    /// `parinfer_cursor_*` options, which are updated if it moves.
+    pub saved_cursor: bool
+}
+
+impl Region {
+    /// A region holding the whole buffer.
+    pub fn whole(request: Request) -> Region {
+        Region {
+            start: Coord { line: 1, column: 1 },
+            request,
+            saved_cursor: true
+        }
+    }
 
-    let mut line: LineNumber = 1;
-    for (a_line, b_line) in from.split('\n').map(chomp_cr).zip(to.split('\n').map(chomp_cr)) {
-        if a_line != b_line {
-            result.deletions.push(Selection::new(line, 1, line, a_line.chars().count()));
-            if b_line != "" {
-                result.insertions.push(Insertion::new(line, 1, b_line));
+    // Moves a fix from the region's text to the buffer.
+    fn place(&self, mut fix: Fix) -> Fix {
+        for coord in &mut [&mut fix.selection.anchor, &mut fix.selection.cursor] {
+            if coord.line == 1 {
+                coord.column += self.start.column - 1;
             }
+            coord.line += self.start.line - 1;
         }
-        line += 1;
+        fix
     }
+}
 
-    result
+/// Splits a list quoted for the shell, such as `$kak_quoted_selections`.
+pub fn parse_quoted_list(s: &str) -> Result<Vec<String>, String> {
+    let mut result = vec![];
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.peek() == Some(&' ') || chars.peek() == Some(&'\n') {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            return Ok(result);
+        }
+        let mut item = String::new();
+        while let Some(ch) = chars.next() {
+            match ch {
+                ' ' | '\n' => break,
+                '\'' => loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(ch) => item.push(ch),
+                        None => return Err(format!("unterminated quote in selections: {}", s)),
+                    }
+                },
+                '\\' => match chars.next() {
+                    Some(ch) => item.push(ch),
+                    None => return Err(format!("trailing backslash in selections: {}", s)),
+                },
+                ch => item.push(ch),
+            }
+        }
+        result.push(item);
+    }
+}
+
+fn parse_coord(s: &str) -> Option<Coord> {
+    let mut parts = s.splitn(2, '.');
+    let line = parts.next()?.parse().ok()?;
+    let column = parts.next()?.parse().ok()?;
+    if line == 0 || column == 0 {
+        return None;
+    }
+    Some(Coord { line, column })
+}
+
+/// Parses a selection description, such as `1.1,2.5`.
+pub fn parse_selection_desc(s: &str) -> Result<Selection, String> {
+    let mut parts = s.splitn(2, ',');
+    match (parts.next().and_then(parse_coord), parts.next().and_then(parse_coord)) {
+        (Some(anchor), Some(cursor)) => Ok(Selection { anchor, cursor }),
+        _ => Err(format!("invalid selection description: {}", s))
+    }
+}
+
+// The cursor of `selection`, as a line and a count of characters along it
+// from the start of the selection's `text`.
+fn selection_cursor(text: &str, start: &Coord, selection: &Selection) -> Result<(LineNumber, Column), String> {
+    let line_no = selection.cursor.line - start.line;
+    let line_start = if line_no == 0 { start.column } else { 1 };
+    let x = text.split('\n')
+        .nth(line_no)
+        .and_then(|line| line.get(..selection.cursor.column.checked_sub(line_start)?))
+        .map(|before| before.chars().count());
+    match x {
+        Some(x) => Ok((line_no, x)),
+        None => Err(String::from("the selections do not match their descriptions"))
+    }
+}
+
+/// Splits `base` into a region for each of the `selections`, described by
+/// `descs`.  Each region's cursor is its selection's, and none of them has a
+/// previous text, which is only known for the whole buffer.
+pub fn regions(base: Request, selections: Vec<String>, descs: &[&str]) -> Result<Vec<Region>, String> {
+    if selections.len() != descs.len() {
+        return Err(String::from("the selections do not match their descriptions"));
+    }
+    let mut result = vec![];
+    for (text, desc) in selections.into_iter().zip(descs.iter()) {
+        let selection = parse_selection_desc(desc)?;
+        let start = if (selection.cursor.line, selection.cursor.column) < (selection.anchor.line, selection.anchor.column) {
+            selection.cursor.clone()
+        } else {
+            selection.anchor.clone()
+        };
+        let (cursor_line, cursor_x) = selection_cursor(&text, &start, &selection)?;
+        let mut options = base.options.clone();
+        options.cursor_line = Some(cursor_line);
+        options.cursor_x = Some(cursor_x);
+        options.prev_text = None;
+        options.prev_cursor_line = None;
+        options.prev_cursor_x = None;
+        result.push(Region {
+            start,
+            request: Request { mode: base.mode, text, options },
+            saved_cursor: false
+        });
+    }
+    Ok(result)
 }
 
 fn escape(s: &str) -> String {
     s.replace("'", "''")
 }
 
-fn delete_script(fixes: &Fixes) -> String {
-    if fixes.deletions.is_empty() {
-        String::new()
-    } else {
-        format!(
-            "select {} {}\nexec '\\<a-d>'\n",
-            env::var("kak_opt_parinfer_select_switches").unwrap(),
-            fixes
-                .deletions
-                .iter()
-                .map(|d| {
-                    format!(
-                        "{}.{},{}.{}",
-                        d.anchor.line,
-                        d.anchor.column,
-                        d.cursor.line,
-                        d.cursor.column
-                    )
-                })
-                .fold(String::new(), |acc, s| acc + " " + &s)
-        )
-    }
-}
-
-fn insert_script(fixes: &Fixes) -> String {
-    if fixes.insertions.is_empty() {
-        String::new()
-    } else {
-        format!(
-            "select {} {}
-             set-register '\"' {}
-             exec '\\P'",
-            env::var("kak_opt_parinfer_select_switches").unwrap(),
-            fixes
-                .insertions
-                .iter()
-                .map(|i| {
-                    format!(
-                        "{}.{},{}.{}",
-                        i.cursor.line,
-                        i.cursor.column,
-                        i.cursor.line,
-                        i.cursor.column
-                    )
-                })
-                .fold(String::new(), |acc, s| acc + " " + &s),
-            fixes
-                .insertions
-                .iter()
-                .map(|i| {
-                    format!("'{}'", escape(&i.text))
-                })
-                .fold(String::new(), |acc, s| acc + " " + &s)
-        )
+// Replaces every fix's selection at once, so that Kakoune keeps track of
+// where the others are.
+fn fix_script(fixes: &[Fix]) -> String {
+    if fixes.is_empty() {
+        return String::new();
     }
+    let selections: Vec<String> = fixes.iter()
+        .map(|f| {
+            format!(
+                "{}.{},{}.{}",
+                f.selection.anchor.line,
+                f.selection.anchor.column,
+                f.selection.cursor.line,
+                f.selection.cursor.column
+            )
+        })
+        .collect();
+    let texts: Vec<String> = fixes.iter()
+        .map(|f| format!("'{}'", escape(&f.text)))
+        .collect();
+    format!(
+        "select {}
+         set-register '\"' {}
+         exec '\\R'",
+        selections.join(" "),
+        texts.join(" ")
+    )
 }
 
 fn cursor_script(request: &Request, answer: &Answer) -> String {
@@ -147,58 +277,146 @@ fn cursor_script(request: &Request, answer: &Answer) -> String {
     }
 }
 
-pub fn kakoune_output(request: &Request, answer: Answer) -> (String, i32) {
-    if answer.success {
-        let fixes = fixes(&request.text, &answer.text);
-        let script = format!("{}\n{}\n{}", delete_script(&fixes), insert_script(&fixes),
-                             cursor_script(&request, &answer));
+fn fail(message: &str) -> String {
+    format!("fail '{}'\n", escape(message))
+}
 
-        ( script, 0 )
-    } else {
-        let error_msg = match answer.error {
-            None => String::from("unknown error."),
-            Some(e) => e.message
-        };
+pub fn kakoune_output(regions: Result<Vec<Region>, String>) -> (String, i32) {
+    let regions = match regions {
+        Ok(regions) => regions,
+        Err(message) => return ( fail(&format!("parinfer-rust: {}", message)), 0 )
+    };
 
-        ( format!("fail '{}'\n", escape(&error_msg)), 0 )
+    let mut fixes = vec![];
+    let mut cursor = String::new();
+    for region in &regions {
+        let answer = parinfer::process(&region.request);
+        if !answer.success {
+            let error_msg = match answer.error {
+                None => String::from("unknown error."),
+                Some(e) => e.message
+            };
+            return ( fail(&error_msg), 0 );
+        }
+        fixes.extend(self::fixes(&region.request.text, &answer.text).into_iter().map(|fix| region.place(fix)));
+        if region.saved_cursor {
+            cursor = cursor_script(&region.request, &answer);
+        }
     }
+
+    ( format!("{}\n{}", fix_script(&fixes), cursor), 0 )
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn fix(anchor_line: LineNumber, anchor_column: Column, cursor_line: LineNumber,
+           cursor_column: Column, text: &str) -> Fix {
+        Fix {
+            selection: Selection::new(anchor_line, anchor_column, cursor_line, cursor_column),
+            text: text.to_string()
+        }
+    }
+
     #[test]
     pub fn fixes_works() {
+        assert_eq!(fixes("abc\n", "abc\n"), vec![], "it can handle no changes");
         assert_eq!(
-            fixes("abc", "abc"),
-            Fixes {
-                deletions: vec![],
-                insertions: vec![]
-            },
-            "it can handle no changes"
+            fixes("abcd\n", "axcy\n"),
+            vec![fix(1,2,1,3,"xc"), fix(1,4,1,5,"y\n")],
+            "it only replaces the changed letters"
         );
         assert_eq!(
-            fixes("abcd", "axcy"),
-            Fixes {
-                deletions: vec![
-                    Selection::new(1,1,1,4),
-                ],
-                insertions: vec![
-                    Insertion::new(1,1,"axcy"),
-                ]
-            },
-            "it can produce a replacement for a single changed letter"
+            fixes("(foo bar))\n(baz)\n", "(foo) bar)\n(baz)\n"),
+            vec![fix(1,5,1,5,") "), fix(1,9,1,10,")")],
+            "it can insert and delete on the same line"
         );
         assert_eq!(
-            fixes("hello, worxxyz", ""),
-            Fixes {
-                deletions: vec![
-                    Selection::new(1,1,1,14)
-                ],
-                insertions: vec![]
-            },
-            "it can produce a longer deletion"
+            fixes("(a\n b)\n", "(a\n x\n b)\n"),
+            vec![fix(2,1,2,1," x\n ")],
+            "it can insert a line"
+        );
+        assert_eq!(
+            fixes("(a\n x\n b)\n", "(a\n b)\n"),
+            vec![fix(2,1,3,1," ")],
+            "it can delete a line"
+        );
+        assert_eq!(
+            fixes("(é a\n", "(é b\n"),
+            vec![fix(1,5,1,6,"b\n")],
+            "it counts columns in bytes"
+        );
+        assert_eq!(
+            fixes("(a\n", "(a)"),
+            vec![fix(1,2,1,3,"a)")],
+            "it can change the end of the text"
+        );
+        assert_eq!(
+            fixes("ab", "xby"),
+            vec![fix(1,1,1,2,"xby")],
+            "it doesn't overlap changes a character apart"
+        );
+    }
+
+    #[test]
+    pub fn parse_quoted_list_works() {
+        assert_eq!(parse_quoted_list(""), Ok(vec![]));
+        assert_eq!(parse_quoted_list("'(foo)' 'bar\n'"), Ok(vec![String::from("(foo)"), String::from("bar\n")]));
+        assert_eq!(parse_quoted_list("'it'\\''s'"), Ok(vec![String::from("it's")]));
+        assert!(parse_quoted_list("'foo").is_err());
+    }
+
+    #[test]
+    pub fn parse_selection_desc_works() {
+        assert_eq!(parse_selection_desc("1.2,3.4"), Ok(Selection::new(1, 2, 3, 4)));
+        assert!(parse_selection_desc("1.2").is_err());
+        assert!(parse_selection_desc("0.1,1.1").is_err());
+        assert!(parse_selection_desc("a.b,c.d").is_err());
+    }
+
+    fn base() -> Request {
+        Request {
+            mode: Mode::Indent,
+            text: String::new(),
+            options: Options::default()
+                .with_cursor(0, 4)
+                .with_prev_text("(foo")
+        }
+    }
+
+    #[test]
+    pub fn regions_take_the_cursor_from_each_selection() {
+        let split = regions(base(), vec![String::from("(a"), String::from("é (b\n  c")], &["2.3,2.4", "3.5,4.3"]).unwrap();
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].start, Coord { line: 2, column: 3 });
+        assert_eq!((split[0].request.options.cursor_line, split[0].request.options.cursor_x), (Some(0), Some(1)));
+        assert_eq!(split[1].start, Coord { line: 3, column: 5 });
+        assert_eq!((split[1].request.options.cursor_line, split[1].request.options.cursor_x), (Some(1), Some(2)));
+        assert!(split.iter().all(|region| region.request.options.prev_text.is_none() && !region.saved_cursor));
+
+        // A single selection from the start of the buffer is still only
+        // that selection.
+        let single = regions(base(), vec![String::from("(foo)")], &["1.5,1.1"]).unwrap();
+        assert!(!single[0].saved_cursor);
+        assert_eq!(single[0].request.options.prev_text, None);
+        assert_eq!((single[0].request.options.cursor_line, single[0].request.options.cursor_x), (Some(0), Some(0)));
+
+        assert!(regions(base(), vec![String::from("(a")], &[]).is_err());
+        assert!(regions(base(), vec![String::from("(a")], &["2.1,2.9"]).is_err());
+    }
+
+    #[test]
+    pub fn kakoune_output_fixes_each_region() {
+        let base = || Request { mode: Mode::Indent, text: String::new(), options: Options::default() };
+        let split = regions(base(), vec![String::from("(a"), String::from("(b")], &["1.1,1.2", "2.3,2.4"]);
+        assert_eq!(
+            kakoune_output(split).0,
+            "select 1.2,1.2 2.4,2.4\n         set-register '\"' 'a)' 'b)'\n         exec '\\R'\n"
+        );
+        assert_eq!(
+            kakoune_output(Err(String::from("kak_selections_desc is not set."))).0,
+            "fail 'parinfer-rust: kak_selections_desc is not set.'\n"
         );
     }
 }
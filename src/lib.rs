@@ -10,6 +10,10 @@ extern crate unicode_width;
 mod parinfer;
 mod types;
 mod changes;
+mod document;
+mod positions;
+
+pub mod api;
 
 #[macro_use]
 #[cfg(feature = "emacs")]
@@ -25,6 +29,22 @@ mod c_wrapper;
 #[cfg(not(target_arch = "wasm32"))]
 pub use c_wrapper::run_parinfer;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use c_wrapper::{parinfer_document_new, parinfer_document_update, parinfer_document_free};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use c_wrapper::{PARINFER_API_VERSION, parinfer_version, parinfer_api_version, parinfer_run, parinfer_free,
+                    parinfer_run_into};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use c_wrapper::{ParinferOptions, ParinferAnswer, ParinferError, ParinferTabStop, ParinferParenTrail,
+                    PARINFER_MODE_INDENT, PARINFER_MODE_PAREN, PARINFER_MODE_SMART, PARINFER_POSITION_GRAPHEMES,
+                    PARINFER_POSITION_UTF16, PARINFER_POSITION_UTF8_BYTES, PARINFER_POSITION_CODEPOINTS,
+                    parinfer_options_init, parinfer_process, parinfer_answer_success, parinfer_answer_text,
+                    parinfer_answer_cursor_x, parinfer_answer_cursor_line, parinfer_answer_error,
+                    parinfer_answer_tab_stop_count, parinfer_answer_tab_stop, parinfer_answer_paren_trail_count,
+                    parinfer_answer_paren_trail, parinfer_answer_free};
+
 #[cfg(not(target_arch = "wasm32"))]
 pub use c_wrapper::INITIALIZED;
 
@@ -39,11 +59,16 @@ pub use emacs_wrapper::init;
 // WebAssembly-specific stuff
 
 #[cfg(target_arch = "wasm32")]
-#[macro_use]
-extern crate stdweb;
+extern crate wasm_bindgen;
 
 #[cfg(target_arch = "wasm32")]
-use stdweb::js_export;
+extern crate serde_wasm_bindgen;
+
+#[cfg(all(test, target_arch = "wasm32"))]
+extern crate wasm_bindgen_test;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
 
 mod common_wrapper;
 
@@ -51,10 +76,49 @@ mod common_wrapper;
 mod wasm_wrapper;
 
 #[cfg(target_arch = "wasm32")]
-#[js_export]
+pub use wasm_wrapper::{JsAnswer, JsOptions};
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
 pub fn run_parinfer(input: String) -> String {
     wasm_wrapper::run_parinfer(input)
 }
 
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = indentMode)]
+pub fn indent_mode(text: String, options: Option<JsOptions>) -> JsAnswer {
+    wasm_wrapper::run_mode(types::Mode::Indent, text, options)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = parenMode)]
+pub fn paren_mode(text: String, options: Option<JsOptions>) -> JsAnswer {
+    wasm_wrapper::run_mode(types::Mode::Paren, text, options)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_name = smartMode)]
+pub fn smart_mode(text: String, options: Option<JsOptions>) -> JsAnswer {
+    wasm_wrapper::run_mode(types::Mode::Smart, text, options)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn new_document(input: String) -> u32 {
+    wasm_wrapper::new_document(input)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn update_document(handle: u32, input: String) -> String {
+    wasm_wrapper::update_document(handle, input)
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn free_document(handle: u32) {
+    wasm_wrapper::free_document(handle)
+}
+
 #[cfg(windows)]
 extern crate winapi;
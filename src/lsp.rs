@@ -0,0 +1,521 @@
+//! A Language Server Protocol frontend, run with `parinfer-rust lsp`.
+//!
+//! Smart mode runs on `textDocument/onTypeFormatting`, using the changes
+//! the client has sent since the last time, and paren mode runs on
+//! `textDocument/formatting` and `textDocument/rangeFormatting`.  Errors are
+//! published as diagnostics whenever a document changes.  Positions count
+//! UTF-16 code units, as LSP clients expect.
+
+use parinfer;
+use serde_json;
+use serde_json::Value;
+use server::{read_message, response, write_message, Framing, ResponseError, INVALID_PARAMS, METHOD_NOT_FOUND,
+             PARSE_ERROR};
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Write};
+use types::*;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+struct Position {
+    line: LineNumber,
+    character: usize,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+struct Range {
+    start: Position,
+    end: Position,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TextEdit {
+    range: Range,
+    new_text: String,
+}
+
+#[derive(Serialize)]
+struct Diagnostic {
+    range: Range,
+    severity: u8,
+    code: ErrorName,
+    source: &'static str,
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct Message {
+    id: Option<Value>,
+    method: Option<String>,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct TextDocumentIdentifier {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentItem {
+    uri: String,
+    language_id: Option<String>,
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidOpenParams {
+    text_document: TextDocumentItem,
+}
+
+#[derive(Deserialize)]
+struct ContentChange {
+    range: Option<Range>,
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidChangeParams {
+    text_document: TextDocumentIdentifier,
+    content_changes: Vec<ContentChange>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DocumentParams {
+    text_document: TextDocumentIdentifier,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RangeFormattingParams {
+    text_document: TextDocumentIdentifier,
+    range: Range,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnTypeFormattingParams {
+    text_document: TextDocumentIdentifier,
+    position: Position,
+}
+
+// The byte offset of `position` in `text`, clamped to the end of its line.
+fn offset(text: &str, position: Position) -> usize {
+    let line_start = if position.line == 0 {
+        0
+    } else {
+        match text.match_indices('\n').nth(position.line - 1) {
+            Some((i, _)) => i + 1,
+            None => return text.len(),
+        }
+    };
+    let line = text[line_start..].split('\n').next().unwrap_or("");
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut units = 0;
+    for (i, ch) in line.char_indices() {
+        if units >= position.character {
+            return line_start + i;
+        }
+        units += ch.len_utf16();
+    }
+    line_start + line.len()
+}
+
+fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    Position {
+        line: before.matches('\n').count(),
+        character: before[line_start..].encode_utf16().count(),
+    }
+}
+
+struct Document {
+    text: String,
+    options: Options,
+    // What the user changed since parinfer last ran.
+    changes: Vec<Change>,
+    cursor: Option<Position>,
+    // The text once the client has applied parinfer's last edits.
+    expected_text: Option<String>,
+}
+
+impl Document {
+    fn apply(&mut self, change: ContentChange) {
+        let (start, end) = match change.range {
+            Some(range) => {
+                let start = offset(&self.text, range.start);
+                (start, offset(&self.text, range.end).max(start))
+            },
+            None => (0, self.text.len()),
+        };
+        let start_position = position(&self.text, start);
+        self.changes.push(Change {
+            x: start_position.character,
+            line_no: start_position.line,
+            old_text: String::from(&self.text[start..end]),
+            new_text: change.text.clone(),
+        });
+        self.text.replace_range(start..end, &change.text);
+        if self.expected_text.as_ref() == Some(&self.text) {
+            self.changes.clear();
+            self.expected_text = None;
+        }
+    }
+
+    fn run(&self, mode: Mode, cursor: Option<Position>) -> Answer<'_> {
+        let mut options = self.options.clone();
+        options.position_encoding = PositionEncoding::Utf16;
+        options.collect_errors = true;
+        options.return_edits = true;
+        options.cursor_line = cursor.map(|cursor| cursor.line);
+        options.cursor_x = cursor.map(|cursor| cursor.character);
+        if mode == Mode::Smart {
+            options.changes = self.changes.clone();
+            options.prev_cursor_line = self.cursor.map(|cursor| cursor.line);
+            options.prev_cursor_x = self.cursor.map(|cursor| cursor.character);
+        }
+        parinfer::run_mode(mode, &self.text, &options)
+    }
+
+    fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.run(Mode::Smart, None).errors.into_iter().map(|error| {
+            let start = Position { line: error.line_no, character: error.x };
+            Diagnostic {
+                range: Range { start, end: Position { character: error.x + 1, ..start } },
+                severity: 1,
+                code: error.name,
+                source: "parinfer-rust",
+                message: error.message,
+            }
+        }).collect()
+    }
+
+    // Turns the edits in `answer` into LSP edits on lines `first` to `last`,
+    // and expects the text to become the answer's.  A failed answer leaves
+    // the changes and cursor for the next run.
+    fn edits(&mut self, answer: Answer, first: LineNumber, last: LineNumber) -> Vec<TextEdit> {
+        if !answer.success {
+            return vec![];
+        }
+        let edits: Vec<TextEdit> = answer.edits.iter()
+            .filter(|edit| first <= edit.line_no && edit.line_no <= last)
+            .map(|edit| {
+                let start = Position { line: edit.line_no, character: edit.x };
+                let end = offset(&self.text, start) + edit.old_text.len();
+                TextEdit {
+                    range: Range { start, end: position(&self.text, end) },
+                    new_text: edit.new_text.clone(),
+                }
+            })
+            .collect();
+        let all = edits.len() == answer.edits.len();
+        if let (Some(line), Some(character)) = (answer.cursor_line, answer.cursor_x) {
+            self.cursor = Some(Position { line, character });
+        }
+        self.changes.clear();
+        self.expected_text = if edits.is_empty() || !all { None } else { Some(answer.text.into_owned()) };
+        edits
+    }
+}
+
+struct Lsp<'l> {
+    documents: HashMap<String, Document>,
+    language_options: &'l dyn Fn(Option<&str>) -> Options,
+    shutdown: bool,
+    outgoing: Vec<String>,
+}
+
+fn params<T: ::serde::de::DeserializeOwned>(params: Value) -> Result<T, ResponseError> {
+    serde_json::from_value(params).map_err(|e| ResponseError {
+        code: INVALID_PARAMS,
+        message: e.to_string(),
+    })
+}
+
+fn capabilities() -> Value {
+    json!({
+        "capabilities": {
+            "positionEncoding": "utf-16",
+            "textDocumentSync": { "openClose": true, "change": 2 },
+            "documentFormattingProvider": true,
+            "documentRangeFormattingProvider": true,
+            "documentOnTypeFormattingProvider": {
+                "firstTriggerCharacter": "\n",
+                "moreTriggerCharacter": ["(", ")", "[", "]", "{", "}", "\"", ";", " "]
+            }
+        },
+        "serverInfo": { "name": "parinfer-rust", "version": env!("CARGO_PKG_VERSION") }
+    })
+}
+
+impl<'l> Lsp<'l> {
+    fn document(&mut self, uri: &str) -> Result<&mut Document, ResponseError> {
+        self.documents.get_mut(uri).ok_or_else(|| ResponseError {
+            code: INVALID_PARAMS,
+            message: format!("unknown document: {}", uri),
+        })
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) {
+        let diagnostics = self.documents.get(uri).map(Document::diagnostics).unwrap_or_default();
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics }
+        });
+        self.outgoing.push(notification.to_string());
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, ResponseError> {
+        let edits = match method {
+            "initialize" => return Ok(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                return Ok(Value::Null);
+            },
+            "textDocument/formatting" => {
+                let params: DocumentParams = self::params(params)?;
+                let document = self.document(&params.text_document.uri)?;
+                let answer = document.run(Mode::Paren, None).into_owned();
+                document.edits(answer, 0, usize::MAX)
+            },
+            "textDocument/rangeFormatting" => {
+                let params: RangeFormattingParams = self::params(params)?;
+                let document = self.document(&params.text_document.uri)?;
+                let answer = document.run(Mode::Paren, None).into_owned();
+                document.edits(answer, params.range.start.line, params.range.end.line)
+            },
+            "textDocument/onTypeFormatting" => {
+                let params: OnTypeFormattingParams = self::params(params)?;
+                let document = self.document(&params.text_document.uri)?;
+                let answer = document.run(Mode::Smart, Some(params.position)).into_owned();
+                document.edits(answer, 0, usize::MAX)
+            },
+            _ => return Err(ResponseError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method: {}", method),
+            }),
+        };
+        Ok(serde_json::to_value(edits).expect("unable to produce JSON"))
+    }
+
+    fn notification(&mut self, method: &str, params: Value) -> Result<(), ResponseError> {
+        match method {
+            "textDocument/didOpen" => {
+                let params: DidOpenParams = self::params(params)?;
+                let item = params.text_document;
+                let document = Document {
+                    text: item.text,
+                    options: (self.language_options)(item.language_id.as_deref()),
+                    changes: vec![],
+                    cursor: None,
+                    expected_text: None,
+                };
+                self.documents.insert(item.uri.clone(), document);
+                self.publish_diagnostics(&item.uri);
+            },
+            "textDocument/didChange" => {
+                let params: DidChangeParams = self::params(params)?;
+                let uri = params.text_document.uri;
+                let document = self.document(&uri)?;
+                for change in params.content_changes {
+                    document.apply(change);
+                }
+                self.publish_diagnostics(&uri);
+            },
+            "textDocument/didClose" => {
+                let params: DocumentParams = self::params(params)?;
+                self.documents.remove(&params.text_document.uri);
+                self.publish_diagnostics(&params.text_document.uri);
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+}
+
+/// Speaks LSP over `input` and `output` until the client sends `exit`,
+/// returning the exit code.  `language_options` gives the options for a
+/// document's `languageId`.
+pub fn serve(input: &mut dyn BufRead, output: &mut dyn Write,
+             language_options: &dyn Fn(Option<&str>) -> Options) -> io::Result<i32> {
+    let mut lsp = Lsp {
+        documents: HashMap::new(),
+        language_options,
+        shutdown: false,
+        outgoing: vec![],
+    };
+    while let Some((_, message)) = read_message(input)? {
+        let message = match message {
+            Ok(message) => message,
+            Err(error) => {
+                write_message(output, Framing::ContentLength, &response(Value::Null, Err(error)))?;
+                continue;
+            },
+        };
+        match serde_json::from_str::<Message>(&message) {
+            Ok(Message { method: Some(ref method), .. }) if method == "exit" => {
+                return Ok(if lsp.shutdown { 0 } else { 1 });
+            },
+            Ok(Message { id: Some(id), method: Some(method), params }) => {
+                let outcome = lsp.request(&method, params);
+                lsp.outgoing.push(response(id, outcome));
+            },
+            // Notifications have no response, even when they fail.
+            Ok(Message { id: None, method: Some(method), params }) => {
+                let _ = lsp.notification(&method, params);
+            },
+            // A response to a request we never make.
+            Ok(Message { method: None, .. }) => {},
+            Err(e) => {
+                let error = ResponseError { code: PARSE_ERROR, message: e.to_string() };
+                lsp.outgoing.push(response(Value::Null, Err(error)));
+            },
+        }
+        for message in lsp.outgoing.drain(..) {
+            write_message(output, Framing::ContentLength, &message)?;
+        }
+    }
+    Ok(if lsp.shutdown { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn frame(messages: &[Value]) -> String {
+        messages.iter()
+            .map(|message| {
+                let message = message.to_string();
+                format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+            })
+            .collect()
+    }
+
+    fn run(messages: &[Value]) -> (i32, Vec<Value>) {
+        let input = frame(messages);
+        let mut output = vec![];
+        let code = serve(&mut input.as_bytes(), &mut output, &|_| Options::default()).expect("unable to serve");
+        let mut output = &output[..];
+        let mut replies = vec![];
+        while let Some((framing, message)) = read_message(&mut output).expect("unable to read output") {
+            assert_eq!(framing, Framing::ContentLength);
+            replies.push(serde_json::from_str(&message.expect("unreadable reply")).expect("reply is not JSON"));
+        }
+        (code, replies)
+    }
+
+    fn open(text: &str) -> Value {
+        json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": {"uri": "file:///a.clj", "languageId": "clojure", "version": 1, "text": text}
+        }})
+    }
+
+    fn reply(replies: &[Value], id: u64) -> &Value {
+        replies.iter().find(|reply| reply["id"] == id).expect("no reply")
+    }
+
+    #[test]
+    pub fn offsets_count_utf16() {
+        let text = "(😀 a\r\n  b)";
+        assert_eq!(offset(text, Position { line: 0, character: 3 }), 5);
+        assert_eq!(offset(text, Position { line: 0, character: 99 }), 7);
+        assert_eq!(offset(text, Position { line: 1, character: 2 }), 11);
+        assert_eq!(offset(text, Position { line: 5, character: 0 }), text.len());
+        assert_eq!(position(text, 5), Position { line: 0, character: 3 });
+        assert_eq!(position(text, 11), Position { line: 1, character: 2 });
+    }
+
+    #[test]
+    pub fn initializes_and_exits() {
+        let (code, replies) = run(&[
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ]);
+        assert_eq!(code, 0);
+        assert_eq!(replies.len(), 2);
+        assert_eq!(reply(&replies, 1)["result"]["capabilities"]["textDocumentSync"]["change"], 2);
+        assert_eq!(reply(&replies, 2)["result"], Value::Null);
+        assert_eq!(run(&[json!({"jsonrpc": "2.0", "method": "exit"})]).0, 1);
+    }
+
+    #[test]
+    pub fn publishes_errors_as_diagnostics() {
+        let (_, replies) = run(&[open("(foo \"bar")]);
+        assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+        let diagnostic = &replies[0]["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["code"], "unclosed-quote");
+        assert_eq!(diagnostic["range"]["start"], json!({"line": 0, "character": 5}));
+    }
+
+    #[test]
+    pub fn formats_on_type_with_the_changes_since_last_time() {
+        let (_, replies) = run(&[
+            open("(foo\n  (bar))"),
+            // Indenting the first line moves the rest with it.
+            json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+                "textDocument": {"uri": "file:///a.clj", "version": 2},
+                "contentChanges": [{"range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 0}}, "text": "  "}]
+            }}),
+            json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/onTypeFormatting", "params": {
+                "textDocument": {"uri": "file:///a.clj"}, "position": {"line": 0, "character": 2}, "ch": " ", "options": {}
+            }}),
+        ]);
+        assert_eq!(reply(&replies, 1)["result"], json!([
+            {"range": {"start": {"line": 1, "character": 2}, "end": {"line": 1, "character": 2}}, "newText": "  "}
+        ]));
+    }
+
+    #[test]
+    pub fn keeps_the_changes_while_the_text_has_errors() {
+        let change = |version: u64, x: usize, text: &str| json!({"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {
+            "textDocument": {"uri": "file:///a.clj", "version": version},
+            "contentChanges": [{"range": {"start": {"line": 0, "character": x}, "end": {"line": 0, "character": x}}, "text": text}]
+        }});
+        let typed = |id: u64, x: usize| json!({"jsonrpc": "2.0", "id": id, "method": "textDocument/onTypeFormatting", "params": {
+            "textDocument": {"uri": "file:///a.clj"}, "position": {"line": 0, "character": x}, "ch": "\"", "options": {}
+        }});
+        let (_, replies) = run(&[
+            open("(foo\n  (bar))"),
+            change(2, 0, "  "),
+            // Typing through a string leaves it unclosed for a moment.
+            change(3, 6, "\""),
+            typed(1, 7),
+            change(4, 7, "\""),
+            typed(2, 8),
+        ]);
+        assert_eq!(reply(&replies, 1)["result"], json!([]));
+        // The indented first line still moves the rest with it.
+        assert_eq!(reply(&replies, 2)["result"], json!([
+            {"range": {"start": {"line": 1, "character": 2}, "end": {"line": 1, "character": 2}}, "newText": "  "}
+        ]));
+    }
+
+    #[test]
+    pub fn formats_with_paren_mode() {
+        let format = |id: u64, method: &str| json!({"jsonrpc": "2.0", "id": id, "method": method, "params": {
+            "textDocument": {"uri": "file:///a.clj"}, "options": {},
+            "range": {"start": {"line": 2, "character": 0}, "end": {"line": 2, "character": 3}}
+        }});
+        let (_, replies) = run(&[
+            open("(a\nb\n(c\nd))"),
+            format(1, "textDocument/formatting"),
+            format(2, "textDocument/rangeFormatting"),
+            format(3, "textDocument/nope"),
+        ]);
+        let edit = |line: LineNumber, new_text: &str| json!({
+            "range": {"start": {"line": line, "character": 0}, "end": {"line": line, "character": 0}}, "newText": new_text
+        });
+        assert_eq!(reply(&replies, 1)["result"], json!([edit(3, "  "), edit(2, " "), edit(1, " ")]));
+        assert_eq!(reply(&replies, 2)["result"], json!([edit(2, " ")]));
+        assert_eq!(reply(&replies, 3)["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
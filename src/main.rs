@@ -1,6 +1,7 @@
 extern crate getopts;
 
 extern crate serde;
+#[macro_use]
 extern crate serde_json;
 
 #[macro_use]
@@ -13,11 +14,14 @@ extern crate unicode_width;
 mod changes;
 mod cli_options;
 mod kakoune;
+mod lsp;
 mod parinfer;
+mod positions;
+mod server;
 mod types;
 
 use cli_options::OutputType;
-use kakoune::kakoune_output;
+use kakoune::{kakoune_output, Region};
 use std::env;
 use std::io;
 use std::io::Write;
@@ -47,17 +51,61 @@ fn text_output(_request: &Request, answer: Answer) -> (String, i32) {
     }
 }
 
+// Processes each region, joining the outputs.  Input or options which
+// can't be read are reported as an answer with a JSON error, as the
+// WebAssembly build does for bad options.
+fn each_output(regions: Result<Vec<Region>, String>,
+               output: fn(&Request, Answer) -> (String, i32)) -> (String, i32) {
+    match regions {
+        Ok(regions) => regions.iter().fold((String::new(), 0), |(text, error_code), region| {
+            let (region_text, region_error_code) = output(&region.request, parinfer::process(&region.request));
+            ( text + &region_text, error_code.max(region_error_code) )
+        }),
+        Err(message) => {
+            let request = Request { mode: Mode::Smart, text: String::new(), options: Options::default() };
+            output(&request, Answer::from(Error {
+                name: ErrorName::JsonEncodingError,
+                message,
+                ..Error::default()
+            }))
+        }
+    }
+}
+
 pub fn main() {
     let opts = parse_args();
     if opts.want_help() {
         print!("{}", cli_options::usage());
+    } else if opts.want_lsp() {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        // Only the command line's values can be invalid, so once they have
+        // been checked every language's options can be made.
+        if let Err(message) = opts.language_options(None) {
+            eprintln!("parinfer-rust: {}", message);
+            std::process::exit(1);
+        }
+        let language_options = |language_id: Option<&str>| opts.language_options(language_id).unwrap_or_default();
+        match lsp::serve(&mut stdin.lock(), &mut stdout.lock(), &language_options) {
+            Ok(error_code) => std::process::exit(error_code),
+            Err(e) => {
+                eprintln!("parinfer-rust: {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if opts.want_server() {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        if let Err(e) = server::serve(&mut stdin.lock(), &mut stdout.lock()) {
+            eprintln!("parinfer-rust: {}", e);
+            std::process::exit(1);
+        }
     } else {
-        let request = opts.request(&mut io::stdin()).expect("unable to parse options");
-        let answer = parinfer::process(&request);
+        let regions = opts.regions(&mut io::stdin());
         let (output, error_code) = match opts.output_type() {
-            OutputType::Json => json_output(&request, answer),
-            OutputType::Kakoune => kakoune_output(&request, answer),
-            OutputType::Text => text_output(&request, answer)
+            OutputType::Json => each_output(regions, json_output),
+            OutputType::Kakoune => kakoune_output(regions),
+            OutputType::Text => each_output(regions, text_output)
         };
         io::stdout().write(output.as_bytes()).expect("unable to write output");
         std::process::exit(error_code);
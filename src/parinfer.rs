@@ -1,16 +1,21 @@
 use super::std;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::borrow::Cow;
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
+use types;
 use types::*;
 use changes;
+#[cfg(test)]
+use changes::change;
+use positions;
 
 // {{{1 Constants / Predicates
 
 const BACKSLASH: &'static str = "\\";
 const BLANK_SPACE: &'static str = " ";
-const DOUBLE_SPACE: &'static str = "  ";
+// Expanded tabs are sliced from here, so it bounds the tab width.
+const SPACES: &'static str = "                ";
 const VERTICAL_LINE: &'static str = "|";
 const BANG: &'static str = "!";
 const NUMBER_SIGN: &'static str = "#";
@@ -18,23 +23,45 @@ const NEWLINE: &'static str = "\n";
 const TAB: &'static str = "\t";
 const GRAVE: &'static str = "`";
 
-fn match_paren(paren: &str) -> Option<&'static str> {
-    match paren {
-        "{" => Some("}"),
-        "}" => Some("{"),
-        "[" => Some("]"),
-        "]" => Some("["),
-        "(" => Some(")"),
-        ")" => Some("("),
-        _ => None,
-    }
+type ParenPair = (String, String);
+
+// Pairs which aren't exactly two distinct characters are ignored.
+fn parse_paren_pairs(pairs: &[String]) -> Vec<ParenPair> {
+    pairs.iter()
+        .filter_map(|pair| {
+            let chars: Vec<&str> = pair.graphemes(true).collect();
+            match chars[..] {
+                [open, close] if open != close => Some((open.to_string(), close.to_string())),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn match_paren<'p>(pairs: &'p [ParenPair], paren: &str) -> Option<&'p str> {
+    pairs.iter()
+        .find_map(|(open, close)| {
+            if open == paren {
+                Some(&close[..])
+            } else if close == paren {
+                Some(&open[..])
+            } else {
+                None
+            }
+        })
 }
 
 #[cfg(test)]
 #[test]
 fn match_paren_works() {
-    assert_eq!(match_paren("}"), Some("{"));
-    assert_eq!(match_paren("x"), None);
+    let pairs = parse_paren_pairs(&Options::default().paren_pairs);
+    assert_eq!(match_paren(&pairs, "}"), Some("{"));
+    assert_eq!(match_paren(&pairs, "x"), None);
+
+    let pairs = parse_paren_pairs(&["()".to_string(), "<>".to_string(), "||".to_string()]);
+    assert_eq!(match_paren(&pairs, "<"), Some(">"));
+    assert_eq!(match_paren(&pairs, "["), None);
+    assert_eq!(match_paren(&pairs, "|"), None);
 }
 
 // {{{1 Options Structure
@@ -42,6 +69,7 @@ fn match_paren_works() {
 struct TransformedChange {
     old_end_x: Column,
     new_end_x: Column,
+    old_end_line_no: LineNumber,
     lookup_line_no: LineNumber,
     lookup_x: Column,
 }
@@ -54,11 +82,11 @@ pub fn chomp_cr<'a>(text: &'a str) -> &'a str {
     }
 }
 
-fn split_lines<'a>(text: &'a str) -> Vec<&'a str> {
+pub fn split_lines<'a>(text: &'a str) -> Vec<&'a str> {
     text.split('\n').map(chomp_cr).collect()
 }
 
-fn transform_change<'a>(change: &'a Change) -> TransformedChange {
+fn transform_change<'a>(change: &'a Change, tab_width: usize) -> TransformedChange {
     let new_lines: Vec<&'a str> = change.new_text.split('\n').map(chomp_cr).collect();
     let old_lines: Vec<&'a str> = change.old_text.split('\n').map(chomp_cr).collect();
 
@@ -75,41 +103,104 @@ fn transform_change<'a>(change: &'a Change) -> TransformedChange {
     //       |[])
     //     ++^ newEndX, newEndLineNo
 
-    let last_old_line_len = UnicodeWidthStr::width(old_lines[old_lines.len() - 1]);
-    let last_new_line_len = UnicodeWidthStr::width(new_lines[new_lines.len() - 1]);
+    let old_start_x = if old_lines.len() == 1 { change.x } else { 0 };
+    let new_start_x = if new_lines.len() == 1 { change.x } else { 0 };
 
-    let old_end_x = (if old_lines.len() == 1 { change.x } else { 0 }) + last_old_line_len;
-    let new_end_x = (if new_lines.len() == 1 { change.x } else { 0 }) + last_new_line_len;
+    let old_end_x = old_start_x + text_width(old_lines[old_lines.len() - 1], old_start_x, tab_width);
+    let new_end_x = new_start_x + text_width(new_lines[new_lines.len() - 1], new_start_x, tab_width);
+    let old_end_line_no = change.line_no + (old_lines.len() - 1);
     let new_end_line_no = change.line_no + (new_lines.len() - 1);
 
     TransformedChange {
         old_end_x,
         new_end_x,
 
+        old_end_line_no,
         lookup_line_no: new_end_line_no,
         lookup_x: new_end_x,
     }
 }
 
-fn transform_changes<'a>(
-    changes: &Vec<Change>,
-) -> HashMap<(LineNumber, Column), TransformedChange> {
-    let mut lines: HashMap<(LineNumber, Column), TransformedChange> = HashMap::new();
+// Each change is positioned in the text as it was when the change was made,
+// so a change can move the ends of the ones made before it.  Returns where
+// each change ends up in the final text.
+fn transform_changes(changes: &[Change], tab_width: usize) -> Vec<TransformedChange> {
+    let mut transformed: Vec<TransformedChange> = Vec::with_capacity(changes.len());
     for change in changes {
-        let transformed_change = transform_change(change);
-        lines.insert(
-            (
-                transformed_change.lookup_line_no,
-                transformed_change.lookup_x,
-            ),
-            transformed_change,
-        );
+        let later = transform_change(change, tab_width);
+        let start = (change.line_no, change.x);
+        let old_end = (later.old_end_line_no, later.old_end_x);
+        for earlier in &mut transformed {
+            let end = (earlier.lookup_line_no, earlier.lookup_x);
+            if end < start {
+                continue;
+            }
+            if end < old_end {
+                // The end was replaced; it is now wherever the new text ends.
+                earlier.lookup_line_no = later.lookup_line_no;
+                earlier.lookup_x = later.lookup_x;
+            } else if earlier.lookup_line_no == later.old_end_line_no {
+                earlier.lookup_line_no = later.lookup_line_no;
+                earlier.lookup_x = earlier.lookup_x - later.old_end_x + later.new_end_x;
+            } else {
+                earlier.lookup_line_no = earlier.lookup_line_no - later.old_end_line_no + later.lookup_line_no;
+            }
+        }
+        transformed.push(later);
+    }
+    transformed
+}
+
+// The indentation delta from the changes ending at each position.  Several
+// changes can end in the same place, so their deltas are added together.
+fn change_deltas(changes: &[Change], tab_width: usize) -> HashMap<(LineNumber, Column), Delta> {
+    let mut deltas: HashMap<(LineNumber, Column), Delta> = HashMap::new();
+    for change in transform_changes(changes, tab_width) {
+        *deltas.entry((change.lookup_line_no, change.lookup_x)).or_insert(0) +=
+            change.new_end_x as Delta - change.old_end_x as Delta;
     }
-    lines
+    deltas
+}
+
+#[cfg(test)]
+#[test]
+fn changes_on_the_same_line_are_all_kept() {
+    // "(a b)" -> "(aa b)" -> "(aa bbb)"
+    let deltas = change_deltas(&[change(1, 0, "a", "aa"), change(4, 0, "b", "bbb")], 2);
+    assert_eq!(deltas.get(&(0, 3)), Some(&1));
+    assert_eq!(deltas.get(&(0, 7)), Some(&2));
+
+    // The same edits made right to left: the second moves the end of the first.
+    let deltas = change_deltas(&[change(3, 0, "b", "bbb"), change(1, 0, "a", "aa")], 2);
+    assert_eq!(deltas.get(&(0, 3)), Some(&1));
+    assert_eq!(deltas.get(&(0, 7)), Some(&2));
+
+    // A line inserted above moves a change down.
+    let deltas = change_deltas(&[change(0, 1, "x", "xy"), change(0, 0, "", "\n")], 2);
+    assert_eq!(deltas.get(&(2, 2)), Some(&1));
+
+    // Two insertions ending at the same place.
+    let deltas = change_deltas(&[change(1, 0, "", "a"), change(2, 0, "", "bb")], 2);
+    assert_eq!(deltas.get(&(0, 4)), Some(&3));
 }
 
 // {{{1 State Structure (was Result)
 
+type ParenKey = (LineNumber, Column);
+
+fn paren_key(paren: &Paren) -> ParenKey {
+    (paren.input_line_no, paren.input_x)
+}
+
+// The reference implementation builds the paren tree by sharing opener objects
+// between the paren stack and their parents' `children`.  We clone openers as they
+// move around, so instead we remember each opener's parent when it is opened and
+// the latest copy of the opener, then assemble the tree at the end.
+struct ParenRecord<'a> {
+    parent: Option<ParenKey>,
+    paren: Paren<'a>,
+}
+
 #[derive(Debug)]
 struct ParenTrailClamped<'a> {
     start_x: Option<Column>,
@@ -126,7 +217,17 @@ struct InternalParenTrail<'a> {
     clamped: ParenTrailClamped<'a>,
 }
 
-#[derive(PartialEq, Eq)]
+// An edit made to a line, kept for `return_edits`.  `at` is a byte offset
+// into the line as it is now, and `old_text` is what the input had there.
+#[derive(Debug)]
+struct LineEdit {
+    line_no: LineNumber,
+    at: usize,
+    old_text: String,
+    new_text: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Mode {
     Indent,
     Paren,
@@ -155,6 +256,14 @@ impl<'a> State<'a> {
     }
 }
 
+impl<'a> State<'a> {
+    // Where every cursor is, starting with the main one.
+    fn each_cursor<'b>(&'b self) -> impl Iterator<Item = (Option<Column>, Option<LineNumber>)> + 'b {
+        std::iter::once((self.cursor_x, self.cursor_line))
+            .chain(self.cursors.iter().map(|cursor| (Some(cursor.x), Some(cursor.line_no))))
+    }
+}
+
 #[derive(PartialEq, Eq)]
 enum In<'a> {
     Code,
@@ -203,10 +312,14 @@ struct State<'a> {
     orig_text: &'a str,
     orig_cursor_x: Option<Column>,
     orig_cursor_line: Option<LineNumber>,
+    orig_cursors: Vec<Cursor>,
+    orig_tracked_positions: Vec<(LineNumber, Column)>,
 
     input_lines: Vec<&'a str>,
     input_line_no: LineNumber,
     input_x: Column,
+    // The input line from the current character on, for looking ahead.
+    input_rest: &'a str,
 
     lines: Vec<Cow<'a, str>>,
     line_no: LineNumber,
@@ -222,16 +335,21 @@ struct State<'a> {
     paren_trails: Vec<ParenTrail>,
 
     return_parens: bool,
-    parens: Vec<Paren<'a>>,
+    return_edits: bool,
+    edits: Vec<LineEdit>,
+    parens: BTreeMap<ParenKey, ParenRecord<'a>>,
 
     cursor_x: Option<Column>,
     cursor_line: Option<LineNumber>,
     prev_cursor_x: Option<Column>,
     prev_cursor_line: Option<Column>,
+    cursors: Vec<Cursor>,
+    prev_cursors: Vec<Cursor>,
+    tracked_positions: Vec<(LineNumber, Column)>,
 
     selection_start_line: Option<LineNumber>,
 
-    changes: HashMap<(LineNumber, Column), TransformedChange>,
+    changes: HashMap<(LineNumber, Column), Delta>,
 
     context: In<'a>,
     comment_x: Option<Column>,
@@ -251,8 +369,11 @@ struct State<'a> {
     partial_result: bool,
     force_balance: bool,
 
-    comment_char: String,
+    comment_strings: Vec<String>,
     string_delimiters: Vec<String>,
+    paren_pairs: Vec<ParenPair>,
+    tab_width: usize,
+    preserve_tabs: bool,
 
     max_indent: Option<Column>,
     indent_delta: i64,
@@ -261,6 +382,10 @@ struct State<'a> {
 
     error: Option<Error>,
     error_pos_cache: HashMap<ErrorName, Error>,
+    collect_errors: bool,
+    errors: Vec<Error>,
+
+    incremental: Incremental<'a>,
 }
 
 fn initial_paren_trail<'a>() -> InternalParenTrail<'a> {
@@ -296,10 +421,13 @@ fn get_initial_result<'a>(
         orig_text: text,
         orig_cursor_x: options.cursor_x,
         orig_cursor_line: options.cursor_line,
+        orig_cursors: options.cursors.clone(),
+        orig_tracked_positions: options.track_positions.clone(),
 
         input_lines: split_lines(text),
         input_line_no: 0,
         input_x: 0,
+        input_rest: &text[0..0],
 
         lines: vec![],
         line_no: usize::max_value(),
@@ -313,17 +441,22 @@ fn get_initial_result<'a>(
         paren_trail: initial_paren_trail(),
         paren_trails: vec![],
 
-        return_parens: false,
-        parens: vec![],
+        return_parens: options.return_parens,
+        return_edits: options.return_edits,
+        edits: vec![],
+        parens: BTreeMap::new(),
 
         cursor_x: options.cursor_x,
         cursor_line: options.cursor_line,
         prev_cursor_x: options.prev_cursor_x,
         prev_cursor_line: options.prev_cursor_line,
+        cursors: options.cursors.clone(),
+        prev_cursors: options.prev_cursors.clone(),
+        tracked_positions: options.track_positions.clone(),
 
-        selection_start_line: None,
+        selection_start_line: options.selection_start_line,
 
-        changes: transform_changes(&options.changes),
+        changes: change_deltas(&options.changes, tab_width(options)),
 
         context: In::Code,
         comment_x: None,
@@ -340,11 +473,14 @@ fn get_initial_result<'a>(
         tracking_indent: false,
         skip_char: false,
         success: false,
-        partial_result: false,
-        force_balance: false,
+        partial_result: options.partial_result,
+        force_balance: options.force_balance,
 
-        comment_char: options.comment_char.to_string(),
+        comment_strings: comment_strings(options),
         string_delimiters: options.string_delimiters.clone(),
+        paren_pairs: parse_paren_pairs(&options.paren_pairs),
+        tab_width: tab_width(options),
+        preserve_tabs: options.preserve_tabs,
 
         max_indent: None,
         indent_delta: 0,
@@ -353,6 +489,20 @@ fn get_initial_result<'a>(
 
         error: None,
         error_pos_cache: HashMap::new(),
+        collect_errors: options.collect_errors,
+        errors: vec![],
+
+        incremental: Incremental {
+            previous: None,
+            previous_lines: vec![],
+            affected: affected_lines(options),
+            checkpoints: vec![],
+            resynced: None,
+            prefix_parens: vec![],
+            suffix_parens: vec![],
+            prefix_edits: vec![],
+            suffix_edits: vec![],
+        },
     }
 }
 
@@ -371,6 +521,8 @@ fn error_message(error: ErrorName) -> &'static str {
         ErrorName::LeadingCloseParen => "Line cannot lead with a close-paren.",
         ErrorName::Utf8EncodingError => "UTF8 encoded incorrectly.",
         ErrorName::JsonEncodingError => "JSON encoded incorrectly.",
+        ErrorName::InvalidChange => "Change does not match the text.",
+        ErrorName::UnknownDocument => "No such document.",
         ErrorName::Panic => "Internal error (please report!)",
 
         ErrorName::Restart => "Restart requested (you shouldn't see this).",
@@ -385,10 +537,19 @@ fn cache_error_pos(result: &mut State, name: ErrorName) {
         x: result.x,
         input_line_no: result.input_line_no,
         input_x: result.input_x,
+        extra: None,
     };
     result.error_pos_cache.insert(name, error);
 }
 
+fn opener_extra(result: &State, opener: &Paren) -> ErrorExtra {
+    ErrorExtra {
+        name: ErrorName::UnmatchedOpenParen,
+        line_no: if result.partial_result { opener.line_no } else { opener.input_line_no },
+        x: if result.partial_result { opener.x } else { opener.input_x },
+    }
+}
+
 fn error(result: &mut State, name: ErrorName) -> Result<()> {
     let (line_no, x) = match (result.partial_result, result.error_pos_cache.get(&name)) {
         (true, Some(cache)) => (cache.line_no, cache.x),
@@ -404,8 +565,23 @@ fn error(result: &mut State, name: ErrorName) -> Result<()> {
         message: String::from(error_message(name)),
         input_line_no: result.input_line_no,
         input_x: result.input_x,
+        extra: None,
     };
 
+    if name == ErrorName::UnmatchedCloseParen {
+        // extra error info for locating the open-paren that it should've matched
+        e.extra = match result.error_pos_cache.get(&ErrorName::UnmatchedOpenParen) {
+            Some(cache) => Some(ErrorExtra {
+                name: ErrorName::UnmatchedOpenParen,
+                line_no: if result.partial_result { cache.line_no } else { cache.input_line_no },
+                x: if result.partial_result { cache.x } else { cache.input_x },
+            }),
+            None => peek(&result.paren_stack, 0).map(|opener| opener_extra(result, opener)),
+        };
+    } else if name == ErrorName::LeadingCloseParen {
+        e.extra = result.error_pos_cache.get(&name).and_then(|cache| cache.extra.clone());
+    }
+
     if name == ErrorName::UnclosedParen {
         if let Some(opener) = peek(&result.paren_stack, 0) {
             e.line_no = if result.partial_result {
@@ -421,19 +597,70 @@ fn error(result: &mut State, name: ErrorName) -> Result<()> {
         }
     }
 
+    if result.collect_errors && name != ErrorName::Restart {
+        // Close-parens are left alone or dropped, and a comment with an odd
+        // number of quotes has already ended, so scanning can carry on past
+        // them.  Other errors leave no paren trail or string state to carry
+        // on from, so they stop processing here too.
+        if name == ErrorName::UnmatchedCloseParen
+            || name == ErrorName::LeadingCloseParen
+            || name == ErrorName::QuoteDanger
+        {
+            result.errors.push(e);
+            return Ok(());
+        }
+        result.errors.push(e.clone());
+    }
+
     Err(e)
 }
 
 // {{{1 String Operations
 
-fn column_byte_index(s: &str, x: usize) -> usize {
+pub fn tab_width(options: &Options) -> usize {
+    clamp(options.tab_width, Some(1), Some(SPACES.len()))
+}
+
+// A tab reaches the next tab stop, so its width depends on where it starts.
+fn grapheme_width(ch: &str, x: Column, tab_width: usize) -> usize {
+    if ch == TAB {
+        tab_width - x % tab_width
+    } else {
+        UnicodeWidthStr::width(ch)
+    }
+}
+
+/// The graphemes of `s` with the column each starts at, when `s` itself
+/// starts at column `x`, and their byte index.
+pub fn columns(s: &str, x: Column, tab_width: usize) -> impl Iterator<Item = (Column, usize, &str)> {
     s.grapheme_indices(true)
-        .scan(0, |column, (idx, ch)| {
+        .scan(x, move |column, (idx, ch)| {
             let start_column = *column;
-            *column = *column + UnicodeWidthStr::width(ch);
-            Some((start_column, (idx, ch)))
+            *column += grapheme_width(ch, start_column, tab_width);
+            Some((start_column, idx, ch))
         })
-        .filter_map(|(n, (idx, _))| if n == x { Some(idx) } else { None })
+}
+
+/// The width of `s` when it starts at column `x`.
+pub fn text_width(s: &str, x: Column, tab_width: usize) -> usize {
+    s.graphemes(true)
+        .fold(x, |column, ch| column + grapheme_width(ch, column, tab_width)) - x
+}
+
+#[cfg(test)]
+#[test]
+fn text_width_works() {
+    assert_eq!(text_width("abc", 0, 2), 3);
+    assert_eq!(text_width("ｗｏ", 0, 2), 4);
+    assert_eq!(text_width("\t", 0, 4), 4);
+    assert_eq!(text_width("\t", 1, 4), 3);
+    assert_eq!(text_width("a\tb", 0, 4), 5);
+    assert_eq!(text_width("\t\t", 2, 8), 14);
+}
+
+pub fn column_byte_index(s: &str, x: usize, tab_width: usize) -> usize {
+    columns(s, 0, tab_width)
+        .filter_map(|(n, idx, _)| if n == x { Some(idx) } else { None })
         .nth(0) 
         .unwrap_or_else(|| s.len())
 }
@@ -441,32 +668,35 @@ fn column_byte_index(s: &str, x: usize) -> usize {
 #[cfg(test)]
 #[test]
 fn column_byte_index_works() {
-    assert_eq!(column_byte_index("abc", 1), 1);
-    assert_eq!(column_byte_index("abc", 3), 3);
-    assert_eq!(column_byte_index("åbc", 3), 4);
-    assert_eq!(column_byte_index("åbc", 1), 2);
-    assert_eq!(column_byte_index("ｗｏ", 4), 6);
-    assert_eq!(column_byte_index("ｗｏ", 2), 3);
-    assert_eq!(column_byte_index("ｗｏ", 0), 0);
-}
-
-fn replace_within_string(orig: &str, start: usize, end: usize, replace: &str) -> String {
-    let start_i = column_byte_index(orig, start);
-    let end_i = column_byte_index(orig, end);
+    assert_eq!(column_byte_index("abc", 1, 2), 1);
+    assert_eq!(column_byte_index("abc", 3, 2), 3);
+    assert_eq!(column_byte_index("åbc", 3, 2), 4);
+    assert_eq!(column_byte_index("åbc", 1, 2), 2);
+    assert_eq!(column_byte_index("ｗｏ", 4, 2), 6);
+    assert_eq!(column_byte_index("ｗｏ", 2, 2), 3);
+    assert_eq!(column_byte_index("ｗｏ", 0, 2), 0);
+    assert_eq!(column_byte_index("\t\tx", 8, 4), 2);
+    assert_eq!(column_byte_index("a\tx", 4, 4), 2);
+}
+
+fn replace_within_string(orig: &str, start: usize, end: usize, replace: &str, tab_width: usize) -> String {
+    let start_i = column_byte_index(orig, start, tab_width);
+    let end_i = column_byte_index(orig, end, tab_width);
     String::from(&orig[0..start_i]) + replace + &orig[end_i..]
 }
 
 #[cfg(test)]
 #[test]
 fn replace_within_string_works() {
-    assert_eq!(replace_within_string("aaa", 0, 2, ""), "a");
-    assert_eq!(replace_within_string("aaa", 0, 1, "b"), "baa");
-    assert_eq!(replace_within_string("aaa", 0, 2, "b"), "ba");
-    assert_eq!(replace_within_string("ééé", 0, 2, ""), "é");
-    assert_eq!(replace_within_string("ééé", 0, 1, "b"), "béé");
-    assert_eq!(replace_within_string("ééé", 1, 2, "b"), "ébé");
-    assert_eq!(replace_within_string("ééé", 0, 2, "b"), "bé");
-    assert_eq!(replace_within_string("ééé", 3, 3, "b"), "éééb");
+    assert_eq!(replace_within_string("aaa", 0, 2, "", 2), "a");
+    assert_eq!(replace_within_string("aaa", 0, 1, "b", 2), "baa");
+    assert_eq!(replace_within_string("aaa", 0, 2, "b", 2), "ba");
+    assert_eq!(replace_within_string("ééé", 0, 2, "", 2), "é");
+    assert_eq!(replace_within_string("ééé", 0, 1, "b", 2), "béé");
+    assert_eq!(replace_within_string("ééé", 1, 2, "b", 2), "ébé");
+    assert_eq!(replace_within_string("ééé", 0, 2, "b", 2), "bé");
+    assert_eq!(replace_within_string("ééé", 3, 3, "b", 2), "éééb");
+    assert_eq!(replace_within_string("\t(a)", 4, 4, " ", 4), "\t (a)");
 }
 
 fn repeat_string(text: &str, n: usize) -> String {
@@ -500,11 +730,11 @@ fn get_line_ending_works() {
 
 // {{{1 Line operations
 
-fn is_cursor_affected<'a>(result: &State<'a>, start: Column, end: Column) -> bool {
-    match result.cursor_x {
-        Some(x) if x == start && x == end => x == 0,
-        Some(x) => x >= end,
-        None => false,
+fn is_cursor_affected(cursor_x: Column, start: Column, end: Column) -> bool {
+    if cursor_x == start && cursor_x == end {
+        cursor_x == 0
+    } else {
+        cursor_x >= end
     }
 }
 
@@ -516,14 +746,75 @@ fn shift_cursor_on_edit<'a>(
     replace: &str,
 ) {
     let old_length = end - start;
-    let new_length = UnicodeWidthStr::width(replace);
+    let new_length = text_width(replace, start, result.tab_width);
     let dx = new_length as Delta - old_length as Delta;
 
+    if dx == 0 {
+        return;
+    }
     if let (Some(cursor_x), Some(cursor_line)) = (result.cursor_x, result.cursor_line) {
-        if dx != 0 && cursor_line == line_no && is_cursor_affected(result, start, end) {
+        if cursor_line == line_no && is_cursor_affected(cursor_x, start, end) {
             result.cursor_x = Some(((cursor_x as Delta) + dx) as usize);
         }
     }
+    for cursor in &mut result.cursors {
+        if cursor.line_no == line_no && is_cursor_affected(cursor.x, start, end) {
+            cursor.x = ((cursor.x as Delta) + dx) as usize;
+        }
+    }
+    for &mut (position_line_no, ref mut x) in &mut result.tracked_positions {
+        if position_line_no == line_no && is_cursor_affected(*x, start, end) {
+            *x = ((*x as Delta) + dx) as usize;
+        }
+    }
+}
+
+// Records the replacement of columns `start` to `end` of `line_no` for
+// `return_edits`.  Like the cursors in `shift_cursor_on_edit`, edits further
+// along the line move with it, and edits it touches are folded into it.
+fn record_edit<'a>(result: &mut State<'a>, line_no: LineNumber, start: Column, end: Column, replace: &str) {
+    if !result.return_edits {
+        return;
+    }
+
+    let line = &result.lines[line_no];
+    let start = column_byte_index(line, start, result.tab_width);
+    let end = column_byte_index(line, end, result.tab_width);
+    let dx = replace.len() as Delta - (end - start) as Delta;
+
+    let (mut lo, mut hi) = (start, end);
+    let mut touched = vec![];
+    for mut edit in std::mem::take(&mut result.edits) {
+        let edit_end = edit.at + edit.new_text.len();
+        if edit.line_no != line_no || edit_end < start || end < edit.at {
+            if edit.line_no == line_no && edit.at > end {
+                edit.at = (edit.at as Delta + dx) as usize;
+            }
+            result.edits.push(edit);
+        } else {
+            lo = std::cmp::min(lo, edit.at);
+            hi = std::cmp::max(hi, edit_end);
+            touched.push(edit);
+        }
+    }
+
+    touched.sort_by_key(|edit| edit.at);
+    let mut old_text = String::new();
+    let mut at = lo;
+    for edit in &touched {
+        old_text.push_str(&line[at..edit.at]);
+        old_text.push_str(&edit.old_text);
+        at = edit.at + edit.new_text.len();
+    }
+    old_text.push_str(&line[at..hi]);
+    let new_text = String::from(&line[lo..start]) + replace + &line[end..hi];
+
+    result.edits.push(LineEdit {
+        line_no,
+        at: lo,
+        old_text,
+        new_text,
+    });
 }
 
 fn replace_within_line<'a>(
@@ -533,8 +824,10 @@ fn replace_within_line<'a>(
     end: Column,
     replace: &str,
 ) {
+    record_edit(result, line_no, start, end, replace);
+
     let line = result.lines[line_no].clone();
-    let new_line = replace_within_string(&line, start, end, replace);
+    let new_line = replace_within_string(&line, start, end, replace, result.tab_width);
     result.lines[line_no] = Cow::from(new_line);
 
     shift_cursor_on_edit(result, line_no, start, end, replace);
@@ -567,11 +860,11 @@ fn init_line<'a>(result: &mut State<'a>) {
 
 fn commit_char<'a>(result: &mut State<'a>, orig_ch: &'a str) {
     let ch = result.ch;
-    let ch_width = UnicodeWidthStr::width(ch);
+    let ch_width = grapheme_width(ch, result.x, result.tab_width);
     if orig_ch != ch {
         let line_no = result.line_no;
         let x = result.x;
-        let orig_ch_width = UnicodeWidthStr::width(orig_ch);
+        let orig_ch_width = grapheme_width(orig_ch, x, result.tab_width);
         replace_within_line(result, line_no, x, x + orig_ch_width, ch);
         result.indent_delta -= orig_ch_width as Delta - ch_width as Delta;
     }
@@ -629,20 +922,44 @@ fn peek_works() {
 
 // {{{1 Questions about characters
 
-fn is_close_paren(paren: &str) -> bool {
-    match paren {
-        "}" | "]" | ")" => true,
-        _ => false,
-    }
+// Longest first, so that the longest comment string is the one matched.
+fn comment_strings(options: &Options) -> Vec<String> {
+    let mut strings: Vec<String> = if options.comment_strings.is_empty() {
+        vec![options.comment_char.to_string()]
+    } else {
+        options.comment_strings.iter().filter(|s| !s.is_empty()).cloned().collect()
+    };
+    strings.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    strings
 }
 
-fn is_valid_close_paren<'a>(paren_stack: &Vec<Paren<'a>>, ch: &'a str) -> bool {
-    if paren_stack.is_empty() {
-        return false;
-    }
-    if let Some(paren) = peek(paren_stack, 0) {
-        if let Some(close) = match_paren(ch) {
-            if paren.ch == close {
+#[cfg(test)]
+#[test]
+fn comment_strings_are_longest_first() {
+    assert_eq!(comment_strings(&Options::default()), vec![";"]);
+    let options = Options::default().with_comment_strings(vec!["#".to_string(), "".to_string(), "#!".to_string()]);
+    assert_eq!(comment_strings(&options), vec!["#!", "#"]);
+}
+
+fn comment_string_at<'a>(result: &State<'a>) -> Option<&'a str> {
+    let rest = result.input_rest;
+    result.comment_strings.iter()
+        .find(|comment| rest.starts_with(&comment[..]))
+        .map(|comment| &rest[..comment.len()])
+}
+
+fn is_open_paren(pairs: &[ParenPair], paren: &str) -> bool {
+    pairs.iter().any(|(open, _)| open == paren)
+}
+
+fn is_close_paren(pairs: &[ParenPair], paren: &str) -> bool {
+    pairs.iter().any(|(_, close)| close == paren)
+}
+
+fn is_valid_close_paren<'a>(result: &State<'a>, ch: &'a str) -> bool {
+    if let Some(paren) = peek(&result.paren_stack, 0) {
+        if let Some(open) = match_paren(&result.paren_pairs, ch) {
+            if paren.ch == open {
                 return true;
             }
         }
@@ -651,12 +968,14 @@ fn is_valid_close_paren<'a>(paren_stack: &Vec<Paren<'a>>, ch: &'a str) -> bool {
 }
 
 fn is_whitespace<'a>(result: &State<'a>) -> bool {
-    !result.is_escaped() && (result.ch == BLANK_SPACE || result.ch == DOUBLE_SPACE)
+    // Tabs in code have been expanded to spaces unless they're preserved.
+    !result.is_escaped()
+        && (result.ch == TAB || (!result.ch.is_empty() && result.ch.bytes().all(|b| b == b' ')))
 }
 
 fn is_closable<'a>(result: &State<'a>) -> bool {
     let ch = result.ch;
-    let closer = is_close_paren(ch) && !result.is_escaped();
+    let closer = is_close_paren(&result.paren_pairs, ch) && !result.is_escaped();
     return result.is_in_code() && !is_whitespace(result) && ch != "" && !closer;
 }
 
@@ -668,33 +987,39 @@ fn check_cursor_holding<'a>(result: &State<'a>) -> Result<bool> {
     let hold_min_x = peek(&result.paren_stack, 1).map(|p| p.x + 1).unwrap_or(0);
     let hold_max_x = opener.x;
 
-    let holding = result.cursor_line == Some(opener.line_no)
-        && result.cursor_x.map(|x| hold_min_x <= x).unwrap_or(false)
-        && result.cursor_x.map(|x| x <= hold_max_x).unwrap_or(false);
-    let should_check_prev = result.changes.is_empty() && result.prev_cursor_line != None;
-    if should_check_prev {
-        let prev_holding = result.prev_cursor_line == Some(opener.line_no)
-            && result
-                .prev_cursor_x
-                .map(|x| hold_min_x <= x)
-                .unwrap_or(false)
-            && result
-                .prev_cursor_x
-                .map(|x| x <= hold_max_x)
-                .unwrap_or(false);
-        if prev_holding && !holding {
-            return Err(Error {
-                name: ErrorName::Restart,
-                x: 0,
-                input_line_no: 0,
-                input_x: 0,
-                line_no: 0,
-                message: String::new(),
-            });
+    let holds = |cursor_x: Option<Column>, cursor_line: Option<LineNumber>| {
+        cursor_line == Some(opener.line_no)
+            && cursor_x.map(|x| hold_min_x <= x).unwrap_or(false)
+            && cursor_x.map(|x| x <= hold_max_x).unwrap_or(false)
+    };
+    let holding = holds(result.cursor_x, result.cursor_line);
+    let others_holding: Vec<bool> = result.cursors.iter()
+        .map(|cursor| holds(Some(cursor.x), Some(cursor.line_no)))
+        .collect();
+
+    // A cursor which moved off the opener lets go of it.
+    let mut released = false;
+    if result.changes.is_empty() {
+        if result.prev_cursor_line != None {
+            released |= holds(result.prev_cursor_x, result.prev_cursor_line) && !holding;
+        }
+        for (prev, &holding) in result.prev_cursors.iter().zip(others_holding.iter()) {
+            released |= holds(Some(prev.x), Some(prev.line_no)) && !holding;
         }
     }
+    if released {
+        return Err(Error {
+            name: ErrorName::Restart,
+            x: 0,
+            input_line_no: 0,
+            input_x: 0,
+            line_no: 0,
+            message: String::new(),
+            extra: None,
+        });
+    }
 
-    Ok(holding)
+    Ok(holding || others_holding.contains(&true))
 }
 
 fn track_arg_tab_stop<'a>(result: &mut State<'a>, state: TrackingArgTabStop) {
@@ -720,7 +1045,7 @@ fn in_code_on_open_paren<'a>(result: &mut State<'a>) {
 
         line_no: result.line_no,
         x: result.x,
-        ch: result.ch,
+        ch: Cow::from(result.ch),
         indent_delta: result.indent_delta,
         max_child_indent: None,
 
@@ -731,11 +1056,8 @@ fn in_code_on_open_paren<'a>(result: &mut State<'a>) {
     };
 
     if result.return_parens {
-        if let Some(parent) = result.paren_stack.last_mut() {
-            parent.children.push(opener.clone());
-        } else {
-            result.parens.push(opener.clone());
-        }
+        let parent = result.paren_stack.last().map(paren_key);
+        result.parens.insert(paren_key(&opener), ParenRecord { parent, paren: opener.clone() });
     }
     result.paren_stack.push(opener);
     result.tracking_arg_tab_stop = TrackingArgTabStop::Space;
@@ -744,7 +1066,7 @@ fn in_code_on_open_paren<'a>(result: &mut State<'a>) {
 fn in_code_on_matched_close_paren<'a>(result: &mut State<'a>) -> Result<()> {
     let mut opener = (*peek(&result.paren_stack, 0).unwrap()).clone();
     if result.return_parens {
-        set_closer(&mut opener, result.line_no, result.x, result.ch);
+        set_closer(&mut opener, result.line_no, result.x, Cow::from(result.ch));
     }
 
     result.paren_trail.end_x = Some(result.x + 1);
@@ -792,6 +1114,8 @@ fn in_code_on_unmatched_close_paren<'a>(result: &mut State<'a>) -> Result<()> {
                         .error_pos_cache
                         .get_mut(&ErrorName::UnmatchedOpenParen)
                     {
+                        err.line_no = opener.line_no;
+                        err.x = opener.x;
                         err.input_line_no = opener.input_line_no;
                         err.input_x = opener.input_x;
                     }
@@ -805,7 +1129,7 @@ fn in_code_on_unmatched_close_paren<'a>(result: &mut State<'a>) -> Result<()> {
 }
 
 fn in_code_on_close_paren<'a>(result: &mut State<'a>) -> Result<()> {
-    if is_valid_close_paren(&result.paren_stack, result.ch) {
+    if is_valid_close_paren(result, result.ch) {
         in_code_on_matched_close_paren(result)?;
     } else {
         in_code_on_unmatched_close_paren(result)?;
@@ -815,7 +1139,10 @@ fn in_code_on_close_paren<'a>(result: &mut State<'a>) -> Result<()> {
 }
 
 fn in_code_on_tab<'a>(result: &mut State<'a>) {
-    result.ch = DOUBLE_SPACE;
+    if !result.preserve_tabs {
+        let width = grapheme_width(TAB, result.x, result.tab_width);
+        result.ch = &SPACES[..width];
+    }
 }
 
 fn in_code_on_comment_char<'a>(result: &mut State<'a>) {
@@ -942,10 +1269,10 @@ fn on_context<'a>(result: &mut State<'a>) -> Result<()> {
     match result.context {
         In::Code => {
             match ch {
-                _ if ch == result.comment_char => in_code_on_comment_char(result),
+                _ if comment_string_at(result).is_some() => in_code_on_comment_char(result),
                 _ if result.string_delimiters.contains(&ch.to_string()) => in_code_on_quote(result),
-                "(" | "[" | "{" => in_code_on_open_paren(result),
-                ")" | "]" | "}" => in_code_on_close_paren(result)?,
+                _ if is_open_paren(&result.paren_pairs, ch) => in_code_on_open_paren(result),
+                _ if is_close_paren(&result.paren_pairs, ch) => in_code_on_close_paren(result)?,
                 VERTICAL_LINE if result.lisp_vline_symbols_enabled => in_code_on_quote(result),
                 NUMBER_SIGN if result.lisp_reader_syntax_enabled => in_code_on_nsign(result),
                 GRAVE if result.janet_long_strings_enabled => in_code_on_grave(result),
@@ -1049,7 +1376,7 @@ fn on_char<'a>(result: &mut State<'a>) -> Result<()> {
     if is_closable(result) {
         let line_no = result.line_no;
         let x = result.x;
-        reset_paren_trail(result, line_no, x + UnicodeWidthStr::width(ch));
+        reset_paren_trail(result, line_no, x + grapheme_width(ch, x, result.tab_width));
     }
 
     let state = result.tracking_arg_tab_stop;
@@ -1098,8 +1425,8 @@ fn is_cursor_in_comment<'a>(
 
 fn handle_change_delta<'a>(result: &mut State<'a>) {
     if !result.changes.is_empty() && (result.smart || result.mode == Mode::Paren) {
-        if let Some(change) = result.changes.get(&(result.input_line_no, result.input_x)) {
-            result.indent_delta += change.new_end_x as Delta - change.old_end_x as Delta;
+        if let Some(delta) = result.changes.get(&(result.input_line_no, result.input_x)) {
+            result.indent_delta += delta;
         }
     }
 }
@@ -1107,13 +1434,16 @@ fn handle_change_delta<'a>(result: &mut State<'a>) {
 // {{{1 Paren Trail functions
 
 fn reset_paren_trail<'a>(result: &mut State<'a>, line_no: LineNumber, x: Column) {
+    let openers = std::mem::take(&mut result.paren_trail.openers);
+    let clamped_openers = std::mem::take(&mut result.paren_trail.clamped.openers);
+    remember_parens(result, openers);
+    remember_parens(result, clamped_openers);
+
     result.paren_trail.line_no = Some(line_no);
     result.paren_trail.start_x = Some(x);
     result.paren_trail.end_x = Some(x);
-    result.paren_trail.openers = vec![];
     result.paren_trail.clamped.start_x = None;
     result.paren_trail.clamped.end_x = None;
-    result.paren_trail.clamped.openers = vec![];
 }
 
 fn is_cursor_clamping_paren_trail<'a>(
@@ -1131,28 +1461,25 @@ fn is_cursor_clamping_paren_trail<'a>(
 
 // INDENT MODE: allow the cursor to clamp the paren trail
 fn clamp_paren_trail_to_cursor<'a>(result: &mut State<'a>) {
-    let clamping = is_cursor_clamping_paren_trail(result, result.cursor_x, result.cursor_line);
-    if clamping {
+    // The rightmost cursor holds every paren before it.
+    let clamping_x = result.each_cursor()
+        .filter(|&(cursor_x, cursor_line)| is_cursor_clamping_paren_trail(result, cursor_x, cursor_line))
+        .filter_map(|(cursor_x, _)| cursor_x)
+        .max();
+    if let Some(cursor_x) = clamping_x {
         let start_x = result.paren_trail.start_x.unwrap();
         let end_x = result.paren_trail.end_x.unwrap();
 
-        let new_start_x = std::cmp::max(start_x, result.cursor_x.unwrap());
-        let new_end_x = std::cmp::max(end_x, result.cursor_x.unwrap());
+        let new_start_x = std::cmp::max(start_x, cursor_x);
+        let new_end_x = std::cmp::max(end_x, cursor_x);
 
         let line = &result.lines[result.line_no];
         let mut remove_count = 0;
-        for (x, ch) in line
-            .graphemes(true)
-            .scan(0, |column, ch| {
-                let start_column = *column;
-                *column = *column + UnicodeWidthStr::width(ch);
-                Some((start_column, ch))
-            })
-        {
+        for (x, _, ch) in columns(line, 0, result.tab_width) {
             if x < start_x || x >= new_start_x {
                 continue;
             }
-            if is_close_paren(ch) {
+            if is_close_paren(&result.paren_pairs, ch) {
                 remove_count += 1;
             }
         }
@@ -1375,17 +1702,17 @@ fn correct_paren_trail<'a>(result: &mut State<'a>, indent_x: usize) {
     let index = get_parent_opener_index(result, indent_x);
     for i in 0..index {
         let mut opener = result.paren_stack.pop().unwrap();
-        let close_ch = match_paren(opener.ch).unwrap();
+        let close_ch = match_paren(&result.paren_pairs, &opener.ch).unwrap();
+        parens.push_str(close_ch);
         if result.return_parens {
             opener.closer = Some(Closer {
                 line_no: result.paren_trail.line_no.unwrap(),
                 x: result.paren_trail.start_x.unwrap() + i,
-                ch: close_ch,
+                ch: Cow::from(close_ch.to_string()),
                 trail: None
             });
         }
         result.paren_trail.openers.push(opener);
-        parens.push_str(close_ch);
 
     }
 
@@ -1410,48 +1737,91 @@ fn clean_paren_trail<'a>(result: &mut State<'a>) {
     let end_x = end_x.unwrap();
 
     let mut new_trail = String::new();
-    let mut space_count = 0;
-    for (x, ch) in result.lines[result.line_no]
-                    .graphemes(true)
-                    .scan(0, |column, ch| {
-                        let start_column = *column;
-                        *column = *column + UnicodeWidthStr::width(ch);
-                        Some((start_column, ch))
-                    })
-    {
+    let mut space_width = 0;
+    for (x, _, ch) in columns(&result.lines[result.line_no], 0, result.tab_width) {
         if x < start_x || x >= end_x {
             continue;
         }
 
-        if is_close_paren(ch) {
+        if is_close_paren(&result.paren_pairs, ch) {
             new_trail.push_str(ch);
         } else {
-            space_count += 1;
+            space_width += grapheme_width(ch, x, result.tab_width);
         }
     }
 
-    if space_count > 0 {
+    if space_width > 0 {
         let line_no = result.line_no;
         replace_within_line(result, line_no, start_x, end_x, &new_trail[..]);
-        result.paren_trail.end_x = result.paren_trail.end_x.map(|x| x - space_count);
+        result.paren_trail.end_x = result.paren_trail.end_x.map(|x| x - space_width);
     }
 }
 
-fn set_closer<'a>(opener: &mut Paren<'a>, line_no: LineNumber, x: Column, ch: &'a str) {
+// RETURN PARENS: record the final state of openers which are leaving the paren trail
+fn remember_parens<'a>(result: &mut State<'a>, openers: Vec<Paren<'a>>) {
+    if !result.return_parens {
+        return;
+    }
+    for opener in openers {
+        if let Some(record) = result.parens.get_mut(&paren_key(&opener)) {
+            record.paren = opener;
+        }
+    }
+}
+
+fn build_paren_tree<'a>(mut result: State<'a>) -> (State<'a>, Vec<Paren<'a>>) {
+    if !result.return_parens {
+        return (result, vec![]);
+    }
+
+    let stack = std::mem::take(&mut result.paren_stack);
+    let paren_trail = std::mem::replace(&mut result.paren_trail, initial_paren_trail());
+    remember_parens(&mut result, stack);
+    remember_parens(&mut result, paren_trail.clamped.openers);
+    remember_parens(&mut result, paren_trail.openers);
+
+    // Children always open after their parents, so walking backwards lets us
+    // finish each paren's children before the paren itself is attached.
+    let records = std::mem::take(&mut result.parens);
+    let mut children: HashMap<ParenKey, Vec<Paren<'a>>> = HashMap::new();
+    let mut roots = vec![];
+    for (key, record) in records.into_iter().rev() {
+        let mut paren = record.paren;
+        if let Some(mut kids) = children.remove(&key) {
+            kids.reverse();
+            paren.children = kids;
+        }
+        match record.parent {
+            Some(parent) => children.entry(parent).or_default().push(paren),
+            None => roots.push(paren),
+        }
+    }
+    roots.reverse();
+
+    // Top-level parens reused from a previous run
+    let mut parens = std::mem::take(&mut result.incremental.prefix_parens);
+    parens.extend(roots);
+    parens.extend(std::mem::take(&mut result.incremental.suffix_parens));
+
+    (result, parens)
+}
+
+fn set_closer<'a>(opener: &mut Paren<'a>, line_no: LineNumber, x: Column, ch: Cow<'a, str>) {
     opener.closer = Some(Closer { line_no, x, ch, trail: None })
 }
 
 fn append_paren_trail<'a>(result: &mut State<'a>) {
     let mut opener = result.paren_stack.pop().unwrap().clone();
-    let close_ch = match_paren(opener.ch).unwrap();
+    let close_ch = match_paren(&result.paren_pairs, &opener.ch).unwrap().to_string();
     if result.return_parens {
-        set_closer(&mut opener, result.paren_trail.line_no.unwrap(), result.paren_trail.end_x.unwrap(), close_ch);
+        set_closer(&mut opener, result.paren_trail.line_no.unwrap(), result.paren_trail.end_x.unwrap(),
+                   Cow::from(close_ch.clone()));
     }
 
     set_max_indent(result, &opener);
     let line_no = result.paren_trail.line_no.unwrap();
     let end_x = result.paren_trail.end_x.unwrap();
-    insert_within_line(result, line_no, end_x, close_ch);
+    insert_within_line(result, line_no, end_x, &close_ch);
 
     result.paren_trail.end_x = result.paren_trail.end_x.map(|x| x + 1);
     result.paren_trail.openers.push(opener);
@@ -1459,7 +1829,9 @@ fn append_paren_trail<'a>(result: &mut State<'a>) {
 }
 
 fn invalidate_paren_trail<'a>(result: &mut State<'a>) {
-    result.paren_trail = initial_paren_trail();
+    let paren_trail = std::mem::replace(&mut result.paren_trail, initial_paren_trail());
+    remember_parens(result, paren_trail.openers);
+    remember_parens(result, paren_trail.clamped.openers);
 }
 
 fn check_unmatched_outside_paren_trail<'a>(result: &mut State<'a>) -> Result<()> {
@@ -1545,7 +1917,7 @@ fn finish_new_paren_trail<'a>(result: &mut State<'a>) {
         if let Some(paren) = peek(&result.paren_trail.openers, 0).map(Clone::clone) {
             set_max_indent(result, &paren);
         }
-        if Some(result.line_no) != result.cursor_line {
+        if !result.each_cursor().any(|(_, cursor_line)| cursor_line == Some(result.line_no)) {
             clean_paren_trail(result);
         }
         remember_paren_trail(result);
@@ -1556,9 +1928,20 @@ fn finish_new_paren_trail<'a>(result: &mut State<'a>) {
 
 fn add_indent<'a>(result: &mut State<'a>, delta: Delta) {
     let orig_indent = result.x;
-    let new_indent = (orig_indent as Delta + delta) as Column;
-    let indent_str = repeat_string(BLANK_SPACE, new_indent);
+    // Dropped leading close-parens count against the indent too, so the
+    // opener's delta can take more than the line has.
+    let new_indent = std::cmp::max(orig_indent as Delta + delta, 0) as Column;
     let line_no = result.line_no;
+    let indented_with_tabs = result.lines[line_no]
+        .chars()
+        .take_while(|&ch| ch == ' ' || ch == '\t')
+        .any(|ch| ch == '\t');
+    let indent_str = if result.preserve_tabs && indented_with_tabs {
+        repeat_string(TAB, new_indent / result.tab_width) +
+            &repeat_string(BLANK_SPACE, new_indent % result.tab_width)
+    } else {
+        repeat_string(BLANK_SPACE, new_indent)
+    };
     replace_within_line(result, line_no, 0, orig_indent, &indent_str);
     result.x = new_indent;
     result.indent_x = Some(new_indent);
@@ -1598,6 +1981,7 @@ fn on_indent<'a>(result: &mut State<'a>) -> Result<()> {
 
     if result.quote_danger {
         error(result, ErrorName::QuoteDanger)?;
+        result.quote_danger = false;
     }
 
     match result.mode {
@@ -1619,6 +2003,8 @@ fn on_indent<'a>(result: &mut State<'a>) -> Result<()> {
         Mode::Paren => correct_indent(result),
     }
 
+    check_top_level(result);
+
     Ok(())
 }
 
@@ -1646,23 +2032,28 @@ fn on_leading_close_paren<'a>(result: &mut State<'a>) -> Result<()> {
                     .contains_key(&ErrorName::LeadingCloseParen)
                 {
                     cache_error_pos(result, ErrorName::LeadingCloseParen);
+                    let extra = peek(&result.paren_stack, 0).map(|opener| opener_extra(result, opener));
+                    if let Some(err) = result
+                        .error_pos_cache
+                        .get_mut(&ErrorName::LeadingCloseParen)
+                    {
+                        err.extra = extra;
+                    }
                 }
             }
             result.skip_char = true;
         }
         Mode::Paren => {
-            if !is_valid_close_paren(&result.paren_stack, result.ch) {
+            if !is_valid_close_paren(result, result.ch) {
                 if result.smart {
                     result.skip_char = true;
                 } else {
                     error(result, ErrorName::UnmatchedCloseParen)?;
+                    result.skip_char = true;
                 }
-            } else if is_cursor_left_of(
-                result.cursor_x,
-                result.cursor_line,
-                Some(result.x),
-                result.line_no,
-            ) {
+            } else if result.each_cursor().any(|(cursor_x, cursor_line)| {
+                is_cursor_left_of(cursor_x, cursor_line, Some(result.x), result.line_no)
+            }) {
                 let line_no = result.line_no;
                 let x = result.x;
                 reset_paren_trail(result, line_no, x);
@@ -1712,9 +2103,9 @@ fn on_comment_line<'a>(result: &mut State<'a>) {
 }
 
 fn check_indent<'a>(result: &mut State<'a>) -> Result<()> {
-    if is_close_paren(result.ch) {
+    if is_close_paren(&result.paren_pairs, result.ch) {
         on_leading_close_paren(result)?;
-    } else if result.ch == result.comment_char {
+    } else if comment_string_at(result).is_some() {
         // comments don't count as indentation points
         on_comment_line(result);
         result.tracking_indent = false;
@@ -1727,7 +2118,7 @@ fn check_indent<'a>(result: &mut State<'a>) -> Result<()> {
 
 fn make_tab_stop<'a>(opener: &Paren<'a>) -> TabStop<'a> {
     TabStop {
-        ch: opener.ch,
+        ch: opener.ch.clone(),
         x: opener.x,
         line_no: opener.line_no,
         arg_x: opener.arg_x,
@@ -1767,6 +2158,306 @@ fn set_tab_stops<'a>(result: &mut State<'a>) {
     }
 }
 
+// {{{1 Incremental processing
+
+// A form which opens at column zero closes everything above it.  Once the
+// indentation of such a line has been processed, the only things carried on
+// to the lines below are the mode and `max_indent`.  We remember these points
+// as checkpoints so a later run over an edited copy of the text can start at
+// the last one above the edit, and can stop at the first one below it where
+// the input matches the previous run again.
+
+#[derive(Clone, Debug)]
+struct Checkpoint {
+    line_no: LineNumber,
+    max_indent: Option<Column>,
+}
+
+/// What a successful run leaves behind for the next run over the same buffer.
+pub struct Cache {
+    mode: types::Mode,
+    smart: bool,
+    final_mode: Mode,
+    settings: Options,
+    input: String,
+    input_line_count: usize,
+    output: String,
+    checkpoints: Vec<Checkpoint>,
+    affected: Option<(LineNumber, LineNumber)>,
+    paren_trails: Vec<ParenTrail>,
+    parens: Vec<Paren<'static>>,
+    edits: Vec<Change>,
+}
+
+struct Incremental<'a> {
+    previous: Option<&'a Cache>,
+    previous_lines: Vec<&'a str>,
+    affected: Option<(LineNumber, LineNumber)>,
+    checkpoints: Vec<Checkpoint>,
+    resynced: Option<(LineNumber, LineNumber)>,
+    prefix_parens: Vec<Paren<'a>>,
+    suffix_parens: Vec<Paren<'a>>,
+    prefix_edits: Vec<Change>,
+    suffix_edits: Vec<Change>,
+}
+
+// The first and last lines whose processing depends on the cursor, the
+// selection or the changes.
+fn affected_lines(options: &Options) -> Option<(LineNumber, LineNumber)> {
+    let mut lines: Vec<LineNumber> = vec![];
+    lines.extend(options.cursor_line);
+    lines.extend(options.prev_cursor_line);
+    lines.extend(options.selection_start_line);
+    for cursor in options.cursors.iter().chain(options.prev_cursors.iter()) {
+        lines.push(cursor.line_no);
+        lines.extend(cursor.selection_start_line);
+    }
+    for change in &options.changes {
+        lines.push(change.line_no);
+    }
+    for change in transform_changes(&options.changes, tab_width(options)) {
+        lines.push(change.lookup_line_no);
+    }
+    match (lines.iter().min(), lines.iter().max()) {
+        (Some(&first), Some(&last)) => Some((first, last)),
+        _ => None,
+    }
+}
+
+// Everything in the options which isn't about where the user is editing.
+fn cache_settings(options: &Options) -> Options {
+    let mut settings = options.clone();
+    settings.cursor_x = None;
+    settings.cursor_line = None;
+    settings.prev_cursor_x = None;
+    settings.prev_cursor_line = None;
+    settings.prev_text = None;
+    settings.selection_start_line = None;
+    settings.cursors = vec![];
+    settings.prev_cursors = vec![];
+    settings.track_positions = vec![];
+    settings.changes = vec![];
+    settings
+}
+
+fn line_offset(text: &str, line: &str) -> usize {
+    line.as_ptr() as usize - text.as_ptr() as usize
+}
+
+fn shift_paren_lines<'a>(paren: &Paren<'a>, from: LineNumber, to: LineNumber) -> Paren<'a> {
+    let shift = |line_no: LineNumber| line_no - from + to;
+    let mut paren = paren.clone();
+    paren.line_no = shift(paren.line_no);
+    paren.input_line_no = shift(paren.input_line_no);
+    if let Some(closer) = paren.closer.as_mut() {
+        closer.line_no = shift(closer.line_no);
+        if let Some(trail) = closer.trail.as_mut() {
+            trail.line_no = shift(trail.line_no);
+        }
+    }
+    paren.children = paren.children.iter().map(|child| shift_paren_lines(child, from, to)).collect();
+    paren
+}
+
+// Picks up the previous run's results above the last checkpoint at which
+// nothing has changed, returning the line to continue processing from.
+fn resume<'a>(result: &mut State<'a>, previous: &'a Cache) -> LineNumber {
+    result.incremental.previous = Some(previous);
+    result.incremental.previous_lines = split_lines(&previous.output);
+
+    if previous.final_mode != result.mode || previous.smart != result.smart {
+        return 0;
+    }
+
+    let first_affected = [result.incremental.affected, previous.affected]
+        .iter()
+        .filter_map(|affected| affected.map(|(first, _)| first))
+        .min()
+        .unwrap_or(usize::MAX);
+
+    let text = result.orig_text;
+    let checkpoint = previous.checkpoints.iter().rev()
+        .filter(|checkpoint| checkpoint.line_no > 0 && checkpoint.line_no < first_affected)
+        .filter(|checkpoint| checkpoint.line_no < result.input_lines.len())
+        .find(|checkpoint| {
+            let end = result.input_lines.get(checkpoint.line_no + 1)
+                .map(|next_line| line_offset(text, next_line))
+                .unwrap_or(text.len());
+            previous.input.as_bytes().get(..end) == Some(&text.as_bytes()[..end])
+                && (end < text.len() || previous.input.len() == end)
+        });
+    let line_no = match checkpoint {
+        Some(checkpoint) => {
+            result.max_indent = checkpoint.max_indent;
+            checkpoint.line_no
+        },
+        None => return 0,
+    };
+
+    result.lines = result.incremental.previous_lines[..line_no].iter().map(|line| Cow::from(*line)).collect();
+    result.line_no = line_no - 1;
+    result.paren_trails = previous.paren_trails.iter()
+        .filter(|trail| trail.line_no < line_no)
+        .cloned()
+        .collect();
+    result.incremental.checkpoints = previous.checkpoints.iter()
+        .filter(|checkpoint| checkpoint.line_no < line_no)
+        .cloned()
+        .collect();
+    if result.return_parens {
+        result.incremental.prefix_parens = previous.parens.iter()
+            .filter(|paren| paren.line_no < line_no)
+            .cloned()
+            .collect();
+    }
+    if result.return_edits {
+        result.incremental.prefix_edits = previous.edits.iter()
+            .filter(|edit| edit.line_no < line_no)
+            .cloned()
+            .collect();
+    }
+
+    line_no
+}
+
+// Called once the indentation of a line has been processed.
+fn check_top_level<'a>(result: &mut State<'a>) {
+    let at_top_level = result.x == 0
+        && result.paren_stack.is_empty()
+        && result.context == In::Code
+        && !result.quote_danger
+        && is_open_paren(&result.paren_pairs, result.ch);
+    if !at_top_level {
+        return;
+    }
+
+    result.incremental.checkpoints.push(Checkpoint {
+        line_no: result.line_no,
+        max_indent: result.max_indent,
+    });
+
+    if let Some(prev_line_no) = find_resync_line(result) {
+        result.incremental.resynced = Some((result.line_no, prev_line_no));
+    }
+}
+
+// Looks for the line of the previous run which this top-level line
+// corresponds to, if everything from here on is processed the same way.
+fn find_resync_line<'a>(result: &State<'a>) -> Option<LineNumber> {
+    let previous = result.incremental.previous?;
+    let line_no = result.line_no;
+
+    if !result.errors.is_empty() {
+        return None;
+    }
+
+    if previous.final_mode != result.mode || previous.smart != result.smart {
+        return None;
+    }
+    if let Some((_, last)) = result.incremental.affected {
+        if line_no <= last {
+            return None;
+        }
+    }
+    let prev_line_no = (line_no + previous.input_line_count).checked_sub(result.input_lines.len())?;
+    if let Some((_, last)) = previous.affected {
+        if prev_line_no <= last {
+            return None;
+        }
+    }
+
+    let checkpoint = previous.checkpoints
+        .binary_search_by_key(&prev_line_no, |checkpoint| checkpoint.line_no)
+        .ok()
+        .map(|i| &previous.checkpoints[i])?;
+    if checkpoint.max_indent != result.max_indent {
+        return None;
+    }
+
+    let rest = &result.orig_text.as_bytes()[line_offset(result.orig_text, result.input_lines[line_no])..];
+    let prev_start = previous.input.len().checked_sub(rest.len())?;
+    let prev_input = previous.input.as_bytes();
+    if prev_start > 0 && prev_input[prev_start - 1] != b'\n' {
+        return None;
+    }
+    if &prev_input[prev_start..] != rest {
+        return None;
+    }
+
+    Some(prev_line_no)
+}
+
+// Replaces everything from `line_no` on with the previous run's results.
+fn splice_previous<'a>(result: &mut State<'a>, line_no: LineNumber, prev_line_no: LineNumber) {
+    let previous = result.incremental.previous.unwrap();
+    let shift = |n: LineNumber| n - prev_line_no + line_no;
+
+    let previous_lines = &result.incremental.previous_lines[prev_line_no..];
+    result.lines.truncate(line_no);
+    result.lines.extend(previous_lines.iter().map(|line| Cow::from(*line)));
+
+    result.paren_trails.extend(previous.paren_trails.iter()
+        .filter(|trail| trail.line_no >= prev_line_no)
+        .map(|trail| ParenTrail { line_no: shift(trail.line_no), ..trail.clone() }));
+
+    result.incremental.checkpoints.extend(previous.checkpoints.iter()
+        .filter(|checkpoint| checkpoint.line_no > prev_line_no)
+        .map(|checkpoint| Checkpoint { line_no: shift(checkpoint.line_no), ..checkpoint.clone() }));
+
+    // The top-level opener on this line belongs to the previous run's parens.
+    result.paren_stack.clear();
+    result.paren_trail = initial_paren_trail();
+    if result.return_parens {
+        result.parens.split_off(&(line_no, 0));
+        result.incremental.suffix_parens = previous.parens.iter()
+            .filter(|paren| paren.line_no >= prev_line_no)
+            .map(|paren| shift_paren_lines(paren, prev_line_no, line_no))
+            .collect();
+    }
+    if result.return_edits {
+        result.edits.retain(|edit| edit.line_no < line_no);
+        result.incremental.suffix_edits = previous.edits.iter()
+            .filter(|edit| edit.line_no >= prev_line_no)
+            .map(|edit| Change { line_no: shift(edit.line_no), ..edit.clone() })
+            .collect();
+    }
+
+    result.success = true;
+}
+
+#[cfg(test)]
+#[test]
+fn cached_runs_skip_unchanged_forms() {
+    let options = Options::default();
+    let (_, previous) = run_mode_cached(types::Mode::Indent, "(a)\n(b\n  c)\n(d\n  e)", &options, None);
+    let previous = previous.unwrap();
+
+    let text = "(a)\n(b\n  cc)\n(d\n  e)";
+    let options = Options::default().with_cursor(4, 2).with_changes(vec![change(3, 2, "", "c")]);
+    let mut result = get_initial_result(text, &options, Mode::Indent, false);
+    assert_eq!(resume(&mut result, &previous), 1);
+
+    let result = process_text(text, &options, Mode::Indent, false, Some(&previous));
+    assert_eq!(result.incremental.resynced, Some((3, 3)));
+    assert_eq!(public_result(result).text, "(a)\n(b\n  cc)\n(d\n  e)");
+}
+
+#[cfg(test)]
+#[test]
+fn cached_runs_keep_the_edits_of_skipped_forms() {
+    let options = Options::default().with_return_edits(true);
+    let (_, previous) = run_mode_cached(types::Mode::Indent, "(a (b\n  c)\n(d\n  e)\n(f (g\n  h)", &options, None);
+    let previous = previous.unwrap();
+
+    let text = "(a (b\n  c)\n(d\n  ee)\n(f (g\n  h)";
+    let options = options.with_cursor(4, 3).with_changes(vec![change(3, 3, "", "e")]);
+    let result = process_text(text, &options, Mode::Indent, false, Some(&previous));
+    assert_eq!(result.incremental.resynced, Some((4, 4)));
+    let edits = public_result(result).edits;
+    assert_eq!(edits.iter().map(|edit| edit.line_no).collect::<Vec<_>>(), vec![4, 0]);
+    assert_eq!(edits, indent_mode(text, &options).edits);
+}
+
 // {{{1 High-level processing functions
 
 fn process_char<'a>(result: &mut State<'a>, ch: &'a str) -> Result<()> {
@@ -1798,17 +2489,16 @@ fn process_line<'a>(result: &mut State<'a>, line_no: usize) -> Result<()> {
 
     set_tab_stops(result);
 
-    for (x, ch) in result.input_lines[line_no]
-        .graphemes(true)
-        .scan(0, |column, ch| {
-            let start_column = *column;
-            *column = *column + UnicodeWidthStr::width(ch);
-            Some((start_column, ch))
-        })
-    {
+    let line = result.input_lines[line_no];
+    for (x, idx, ch) in columns(line, 0, result.tab_width) {
         result.input_x = x;
+        result.input_rest = &line[idx..];
         process_char(result, ch)?;
+        if result.incremental.resynced.is_some() {
+            return Ok(());
+        }
     }
+    result.input_rest = NEWLINE;
     process_char(result, NEWLINE)?;
 
     if !result.force_balance {
@@ -1826,6 +2516,7 @@ fn process_line<'a>(result: &mut State<'a>, line_no: usize) -> Result<()> {
 fn finalize_result<'a>(result: &mut State<'a>) -> Result<()> {
     if result.quote_danger {
         error(result, ErrorName::QuoteDanger)?;
+        result.quote_danger = false;
     }
     if result.is_in_stringish() {
         error(result, ErrorName::UnclosedQuote)?;
@@ -1847,19 +2538,34 @@ fn finalize_result<'a>(result: &mut State<'a>) -> Result<()> {
 
 fn process_error<'a>(result: &mut State<'a>, e: Error) {
     result.success = false;
-    result.error = Some(e);
+    // When collecting errors, the answer's error is the first of them.
+    result.error = Some(result.errors.first().cloned().unwrap_or(e));
 }
 
-fn process_text<'a>(text: &'a str, options: &Options, mode: Mode, smart: bool) -> State<'a> {
+fn process_text<'a>(
+    text: &'a str,
+    options: &Options,
+    mode: Mode,
+    smart: bool,
+    previous: Option<&'a Cache>,
+) -> State<'a> {
     let mut result = get_initial_result(text, &options, mode, smart);
+    let first_line_no = match previous {
+        Some(previous) => resume(&mut result, previous),
+        None => 0,
+    };
 
     let mut process_result: Result<()> = Ok(());
-    for i in 0..result.input_lines.len() {
+    for i in first_line_no..result.input_lines.len() {
         result.input_line_no = i;
         process_result = process_line(&mut result, i);
         if let Err(_) = process_result {
             break;
         }
+        if let Some((line_no, prev_line_no)) = result.incremental.resynced {
+            splice_previous(&mut result, line_no, prev_line_no);
+            return result;
+        }
     }
 
     if let Ok(_) = process_result {
@@ -1870,29 +2576,81 @@ fn process_text<'a>(text: &'a str, options: &Options, mode: Mode, smart: bool) -
         Err(Error {
             name: ErrorName::Restart,
             ..
-        }) => process_text(text, &options, Mode::Paren, smart),
+        }) => process_text(text, &options, Mode::Paren, smart, None),
         Err(e) => {
             process_error(&mut result, e);
             result
         }
-        _ => result,
+        _ => {
+            if let Some(e) = result.errors.first().cloned() {
+                process_error(&mut result, e);
+            }
+            result
+        }
     }
 }
 
 // {{{1 Public API
 
+// The recorded edits, positioned in the input and last first.  Those for
+// lines taken from a previous run come from its answer.
+fn text_edits<'a>(result: &State<'a>) -> Vec<Change> {
+    if !result.return_edits || !(result.success || result.partial_result) {
+        return vec![];
+    }
+
+    let mut edits: Vec<&LineEdit> = result.edits.iter().collect();
+    edits.sort_by_key(|edit| (edit.line_no, edit.at));
+    let mut changes = vec![];
+    let mut line_no = None;
+    let mut shift: Delta = 0;
+    for edit in edits {
+        if line_no != Some(edit.line_no) {
+            line_no = Some(edit.line_no);
+            shift = 0;
+        }
+        let at = (edit.at as Delta - shift) as usize;
+        shift += edit.new_text.len() as Delta - edit.old_text.len() as Delta;
+        if edit.old_text == edit.new_text {
+            continue;
+        }
+        let (prefix, suffix) = changes::common_affixes(&edit.old_text, &edit.new_text);
+        let input_line = result.input_lines[edit.line_no];
+        changes.push(Change {
+            x: text_width(&input_line[..at + prefix], 0, result.tab_width),
+            line_no: edit.line_no,
+            old_text: String::from(&edit.old_text[prefix..edit.old_text.len() - suffix]),
+            new_text: String::from(&edit.new_text[prefix..edit.new_text.len() - suffix]),
+        });
+    }
+    changes.reverse();
+
+    let incremental = &result.incremental;
+    incremental.suffix_edits.iter()
+        .chain(changes.iter())
+        .chain(incremental.prefix_edits.iter())
+        .cloned()
+        .collect()
+}
+
 fn public_result<'a>(result: State<'a>) -> Answer<'a> {
+    let (result, parens) = build_paren_tree(result);
     let line_ending = get_line_ending(result.orig_text);
+    let edits = text_edits(&result);
     if result.success {
         Answer {
             text: Cow::from(result.lines.join(line_ending)),
             cursor_x: result.cursor_x,
             cursor_line: result.cursor_line,
+            cursors: result.cursors,
+            tracked_positions: result.tracked_positions,
+            edits,
             success: true,
             tab_stops: result.tab_stops,
             paren_trails: result.paren_trails,
-            parens: result.parens,
+            parens,
             error: None,
+            errors: vec![],
         }
     } else {
         Answer {
@@ -1911,68 +2669,123 @@ fn public_result<'a>(result: State<'a>) -> Answer<'a> {
             } else {
                 result.orig_cursor_line
             },
+            cursors: if result.partial_result {
+                result.cursors
+            } else {
+                result.orig_cursors
+            },
+            tracked_positions: if result.partial_result {
+                result.tracked_positions
+            } else {
+                result.orig_tracked_positions
+            },
+            edits,
             paren_trails: result.paren_trails,
             success: false,
             tab_stops: result.tab_stops,
+            errors: if result.errors.is_empty() {
+                result.error.iter().cloned().collect()
+            } else {
+                result.errors
+            },
             error: result.error,
-            parens: result.parens,
+            parens: if result.partial_result { parens } else { vec![] },
         }
     }
 }
 
 pub fn indent_mode<'a>(text: &'a str, options: &Options) -> Answer<'a> {
-    public_result(process_text(text, options, Mode::Indent, false))
+    public_result(process_text(text, options, Mode::Indent, false, None))
 }
 
 pub fn paren_mode<'a>(text: &'a str, options: &Options) -> Answer<'a> {
-    public_result(process_text(text, options, Mode::Paren, false))
+    public_result(process_text(text, options, Mode::Paren, false, None))
+}
+
+// Smart mode falls back to indent mode while text is selected.
+fn has_selection(options: &Options) -> bool {
+    options.selection_start_line.is_some()
+        || options.cursors.iter().any(|cursor| cursor.selection_start_line.is_some())
 }
 
 pub fn smart_mode<'a>(text: &'a str, options: &Options) -> Answer<'a> {
-    let smart = options.selection_start_line == None;
-    public_result(process_text(text, options, Mode::Indent, smart))
+    let smart = !has_selection(options);
+    public_result(process_text(text, options, Mode::Indent, smart, None))
 }
 
-pub fn process(request: &Request) -> Answer {
-    let mut options = request.options.clone();
+// Turns the request's positions into columns and works out its changes.
+fn prepare_options(text: &str, options: &Options) -> Options {
+    let mut options = positions::decode_options(text, options);
 
-    if let Some(ref prev_text) = request.options.prev_text {
-        options.changes = changes::compute_text_changes(prev_text, &request.text);
+    if let Some(ref prev_text) = options.prev_text {
+        options.changes = changes::compute_text_changes(prev_text, text, tab_width(&options));
     }
 
-    if request.mode == "paren" {
-        Answer::from(paren_mode(&request.text, &options))
-    } else if request.mode == "indent" {
-        Answer::from(indent_mode(&request.text, &options))
-    } else if request.mode == "smart" {
-        Answer::from(smart_mode(&request.text, &options))
-    } else {
-        Answer::from(Error {
-            message: String::from("Bad value specified for `mode`"),
-            ..Error::default()
-        })
-    }
+    options
+}
+
+pub fn run_mode<'a>(mode: types::Mode, text: &'a str, options: &Options) -> Answer<'a> {
+    let decoded = prepare_options(text, options);
+
+    let answer = match mode {
+        types::Mode::Paren => paren_mode(text, &decoded),
+        types::Mode::Indent => indent_mode(text, &decoded),
+        types::Mode::Smart => smart_mode(text, &decoded),
+    };
+    positions::encode_answer(answer, text, options)
 }
 
-// This is like the process function above, but uses a reference counted version of Request
+/// Like `run_mode`, but reuses as much of the `previous` run over this
+/// buffer as it can.  Also returns what the next run will need, if this one
+/// succeeded.
 #[allow(dead_code)]
-pub fn rc_process<'a>(request: &'a SharedRequest) -> Answer<'a> {
-  let mut options = request.options.clone();
-
-  if let Some(ref prev_text) = request.options.prev_text {
-    options.changes = changes::compute_text_changes(prev_text, &request.text);
-  }
-
-  if request.mode == "paren" {
-    Answer::from(paren_mode(&request.text, &options))
-  } else if request.mode == "indent" {
-    Answer::from(indent_mode(&request.text, &options))
-  } else if request.mode == "smart" {
-    Answer::from(smart_mode(&request.text, &options))
-  } else {
-    Answer::from(Error {
-      message: String::from("Bad value specified for `mode`"),
-      ..Error::default()
-    })
-  }
+pub fn run_mode_cached<'a>(
+    mode: types::Mode,
+    text: &'a str,
+    options: &Options,
+    previous: Option<&'a Cache>,
+) -> (Answer<'a>, Option<Cache>) {
+    let decoded = prepare_options(text, options);
+    let settings = cache_settings(&decoded);
+    let previous = previous.filter(|previous| previous.mode == mode && previous.settings == settings);
+
+    let mut result = match mode {
+        types::Mode::Paren => process_text(text, &decoded, Mode::Paren, false, previous),
+        types::Mode::Indent => process_text(text, &decoded, Mode::Indent, false, previous),
+        types::Mode::Smart => {
+            let smart = !has_selection(&decoded);
+            process_text(text, &decoded, Mode::Indent, smart, previous)
+        },
+    };
+
+    let smart = result.smart;
+    let final_mode = result.mode;
+    let input_line_count = result.input_lines.len();
+    let affected = result.incremental.affected;
+    let checkpoints = std::mem::take(&mut result.incremental.checkpoints);
+    let answer = public_result(result);
+    if !answer.success {
+        return (positions::encode_answer(answer, text, options), None);
+    }
+
+    // The cache keeps the answer's positions in columns.
+    let cache = Cache {
+        mode,
+        smart,
+        final_mode,
+        settings,
+        input: String::from(text),
+        input_line_count,
+        output: answer.text.to_string(),
+        checkpoints,
+        affected,
+        paren_trails: answer.paren_trails.clone(),
+        parens: answer.parens.iter().cloned().map(Paren::into_owned).collect(),
+        edits: answer.edits.clone(),
+    };
+    (positions::encode_answer(answer, text, options), Some(cache))
+}
+
+pub fn process(request: &Request) -> Answer {
+    run_mode(request.mode, &request.text, &request.options)
 }
@@ -0,0 +1,244 @@
+//! Converts `x` positions between the display columns parinfer works in and
+//! the units an editor counts in (see `PositionEncoding`).
+//!
+//! Only the `x` of a position depends on the encoding, so each is converted
+//! along the line it is on: request positions along the request's text, and
+//! answer positions along the answer's text (or the request's, for `input_x`).
+
+use changes;
+use parinfer::{columns, split_lines, tab_width, text_width};
+use types::*;
+
+fn unit_count(ch: &str, width: usize, encoding: PositionEncoding) -> usize {
+    match encoding {
+        PositionEncoding::Graphemes => width,
+        PositionEncoding::Utf16 => ch.encode_utf16().count(),
+        PositionEncoding::Utf8Bytes => ch.len(),
+        PositionEncoding::Codepoints => ch.chars().count(),
+    }
+}
+
+/// Turns `x`, counted in `options.position_encoding` units along `line`, into
+/// a column.  A position inside a character moves to the start of it, and
+/// past the end of the line each unit counts as a column.
+pub fn to_column(line: &str, x: usize, options: &Options) -> Column {
+    let tab_width = tab_width(options);
+    let mut units = 0;
+    let mut end = 0;
+    for (start, _, ch) in columns(line, 0, tab_width) {
+        let width = text_width(ch, start, tab_width);
+        let count = unit_count(ch, width, options.position_encoding);
+        if units + count > x {
+            return start;
+        }
+        units += count;
+        end = start + width;
+    }
+    end + (x - units)
+}
+
+/// Turns a column along `line` into a count of `options.position_encoding`
+/// units, the opposite of `to_column`.
+pub fn from_column(line: &str, column: Column, options: &Options) -> usize {
+    let tab_width = tab_width(options);
+    let mut units = 0;
+    let mut end = 0;
+    for (start, _, ch) in columns(line, 0, tab_width) {
+        let width = text_width(ch, start, tab_width);
+        if start + width > column {
+            return units;
+        }
+        units += unit_count(ch, width, options.position_encoding);
+        end = start + width;
+    }
+    units + (column - end)
+}
+
+fn line<'t>(lines: &[&'t str], line_no: LineNumber) -> &'t str {
+    lines.get(line_no).cloned().unwrap_or("")
+}
+
+/// Converts the positions in `options` into columns along `text`.
+pub fn decode_options(text: &str, options: &Options) -> Options {
+    let mut decoded = options.clone();
+    if options.position_encoding == PositionEncoding::Graphemes {
+        return decoded;
+    }
+
+    let lines = split_lines(text);
+    let prev_lines = options.prev_text.as_ref().map(|prev_text| split_lines(prev_text));
+    let prev_lines = prev_lines.as_ref().unwrap_or(&lines);
+    let column = |lines: &[&str], line_no: Option<LineNumber>, x: Option<Column>| match (line_no, x) {
+        (Some(line_no), Some(x)) => Some(to_column(line(lines, line_no), x, options)),
+        _ => x,
+    };
+
+    decoded.cursor_x = column(&lines, options.cursor_line, options.cursor_x);
+    decoded.prev_cursor_x = column(prev_lines, options.prev_cursor_line, options.prev_cursor_x);
+    for cursor in &mut decoded.cursors {
+        cursor.x = to_column(line(&lines, cursor.line_no), cursor.x, options);
+    }
+    for cursor in &mut decoded.prev_cursors {
+        cursor.x = to_column(line(prev_lines, cursor.line_no), cursor.x, options);
+    }
+    for &mut (line_no, ref mut x) in &mut decoded.track_positions {
+        *x = to_column(line(&lines, line_no), *x, options);
+    }
+    // Each change is positioned in the text as it was when it was made, and
+    // its line is the same up to it just afterwards.  So working back from
+    // `text`, each change is decoded along the text after it, then undone.
+    let mut after = String::from(text);
+    for change in decoded.changes.iter_mut().rev() {
+        let undo = Change {
+            x: change.x,
+            line_no: change.line_no,
+            old_text: change.new_text.clone(),
+            new_text: change.old_text.clone(),
+        };
+        change.x = to_column(line(&split_lines(&after), change.line_no), change.x, options);
+        if let Ok(before) = changes::apply_change(&after, &undo, options) {
+            after = before;
+        }
+    }
+    decoded
+}
+
+struct Encoder<'e> {
+    input_lines: Vec<&'e str>,
+    output_lines: Vec<&'e str>,
+    options: &'e Options,
+}
+
+impl<'e> Encoder<'e> {
+    fn input(&self, line_no: LineNumber, x: Column) -> usize {
+        from_column(line(&self.input_lines, line_no), x, self.options)
+    }
+
+    fn output(&self, line_no: LineNumber, x: Column) -> usize {
+        from_column(line(&self.output_lines, line_no), x, self.options)
+    }
+
+    fn paren_trail(&self, trail: &mut ParenTrail) {
+        trail.start_x = self.output(trail.line_no, trail.start_x);
+        trail.end_x = self.output(trail.line_no, trail.end_x);
+    }
+
+    // `indent_delta` and `max_child_indent` measure indentation rather than
+    // locate anything, so they stay in columns.
+    fn paren(&self, paren: &mut Paren) {
+        paren.arg_x = paren.arg_x.map(|x| self.output(paren.line_no, x));
+        paren.x = self.output(paren.line_no, paren.x);
+        paren.input_x = self.input(paren.input_line_no, paren.input_x);
+        if let Some(ref mut closer) = paren.closer {
+            closer.x = self.output(closer.line_no, closer.x);
+            if let Some(ref mut trail) = closer.trail {
+                self.paren_trail(trail);
+            }
+        }
+        for child in &mut paren.children {
+            self.paren(child);
+        }
+    }
+
+    // Errors are located in the output text only for partial results.
+    fn error(&self, error: &mut Error) {
+        let lines = if self.options.partial_result { &self.output_lines } else { &self.input_lines };
+        error.x = from_column(line(lines, error.line_no), error.x, self.options);
+        error.input_x = self.input(error.input_line_no, error.input_x);
+        if let Some(ref mut extra) = error.extra {
+            extra.x = from_column(line(lines, extra.line_no), extra.x, self.options);
+        }
+    }
+}
+
+/// Converts the columns in `answer`, produced from `input`, into
+/// `options.position_encoding` units.
+pub fn encode_answer<'a>(mut answer: Answer<'a>, input: &str, options: &Options) -> Answer<'a> {
+    if options.position_encoding == PositionEncoding::Graphemes {
+        return answer;
+    }
+
+    let output = answer.text.clone();
+    let encoder = Encoder {
+        input_lines: split_lines(input),
+        output_lines: split_lines(&output),
+        options,
+    };
+
+    if let (Some(line_no), Some(x)) = (answer.cursor_line, answer.cursor_x) {
+        answer.cursor_x = Some(encoder.output(line_no, x));
+    }
+    for cursor in &mut answer.cursors {
+        cursor.x = encoder.output(cursor.line_no, cursor.x);
+    }
+    for &mut (line_no, ref mut x) in &mut answer.tracked_positions {
+        *x = encoder.output(line_no, *x);
+    }
+    for edit in &mut answer.edits {
+        edit.x = encoder.input(edit.line_no, edit.x);
+    }
+    for tab_stop in &mut answer.tab_stops {
+        tab_stop.arg_x = tab_stop.arg_x.map(|x| encoder.output(tab_stop.line_no, x));
+        tab_stop.x = encoder.output(tab_stop.line_no, tab_stop.x);
+    }
+    for trail in &mut answer.paren_trails {
+        encoder.paren_trail(trail);
+    }
+    for paren in &mut answer.parens {
+        encoder.paren(paren);
+    }
+    if let Some(ref mut error) = answer.error {
+        encoder.error(error);
+    }
+    for error in &mut answer.errors {
+        encoder.error(error);
+    }
+    answer
+}
+
+#[cfg(test)]
+#[test]
+fn columns_convert_to_each_encoding() {
+    let line = "(a 😀 ｗ\te\u{301})";
+    let with = |encoding| Options::default().with_tab_width(4).with_position_encoding(encoding);
+
+    // ( a _ 😀 _ ｗ \t é )
+    let starts = [0, 1, 2, 3, 5, 6, 8, 12, 13];
+    let utf16 = [0, 1, 2, 3, 5, 6, 7, 8, 10];
+    let bytes = [0, 1, 2, 3, 7, 8, 11, 12, 15];
+    let codepoints = [0, 1, 2, 3, 4, 5, 6, 7, 9];
+    for (encoding, units) in [(PositionEncoding::Utf16, utf16),
+                              (PositionEncoding::Utf8Bytes, bytes),
+                              (PositionEncoding::Codepoints, codepoints),
+                              (PositionEncoding::Graphemes, starts)].iter() {
+        let options = with(*encoding);
+        for (column, x) in starts.iter().zip(units.iter()) {
+            assert_eq!(from_column(line, *column, &options), *x, "{:?} from column {}", encoding, column);
+            assert_eq!(to_column(line, *x, &options), *column, "{:?} to column {}", encoding, x);
+        }
+    }
+
+    let options = with(PositionEncoding::Utf16);
+    // Past the end of the line.
+    assert_eq!(from_column(line, 16, &options), 13);
+    assert_eq!(to_column(line, 13, &options), 16);
+    // Inside a character.
+    assert_eq!(to_column(line, 4, &options), 3);
+    assert_eq!(from_column(line, 4, &options), 3);
+}
+
+#[cfg(test)]
+#[test]
+fn changes_are_decoded_along_the_text_they_were_made_in() {
+    let options = Options::default().with_position_encoding(PositionEncoding::Utf16);
+
+    // "(a\n ｗｗ b)" -> "(a\n ｗｗ c b)" -> "x\n(a\n ｗｗ c b)"
+    let changes = vec![changes::change(4, 1, "", "c "), changes::change(0, 0, "", "x\n")];
+    let decoded = decode_options("x\n(a\n ｗｗ c b)", &options.clone().with_changes(changes));
+    assert_eq!(decoded.changes.iter().map(|change| change.x).collect::<Vec<_>>(), vec![6, 0]);
+
+    // "\t😀 b" -> "\t😀 c b" -> "\tｗ😀 c b"
+    let changes = vec![changes::change(4, 0, "", "c "), changes::change(1, 0, "", "ｗ")];
+    let decoded = decode_options("\tｗ😀 c b", &options.with_changes(changes));
+    assert_eq!(decoded.changes.iter().map(|change| change.x).collect::<Vec<_>>(), vec![5, 2]);
+}
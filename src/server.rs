@@ -0,0 +1,360 @@
+//! A long-running mode which answers JSON-RPC requests on stdin, so editors
+//! don't need to start a process or load a library for every change.
+//!
+//! Each message is either a line of JSON or, as in the Language Server
+//! Protocol, a `Content-Length` header followed by that many bytes of JSON.
+//! Responses are framed the same way as the request they answer.
+//!
+//! Methods:
+//!
+//! * `process` takes a `Request` (`mode`, `text` and `options`) and returns
+//!   its `Answer`.  With a `buffer` name, the previous answer for that buffer
+//!   provides `prevText` and the previous cursor when they're missing.
+//! * `close` forgets the session for `buffer`.
+//! * `shutdown` answers `null` and stops the server.
+
+use parinfer;
+use serde_json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io;
+use std::io::{BufRead, Write};
+use types::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Framing {
+    Lines,
+    ContentLength,
+}
+
+fn parse_error(message: String) -> ResponseError {
+    ResponseError { code: PARSE_ERROR, message }
+}
+
+// A `Name: value` header line, as opposed to a line of anything else.
+fn header(line: &str) -> Option<(&str, &str)> {
+    let mut parts = line.splitn(2, ':');
+    let name = parts.next()?.trim();
+    let value = parts.next()?.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    Some((name, value))
+}
+
+/// Reads the next message, or `None` at the end of the input.  A message
+/// which can't be read is an error to respond with, after which reading
+/// carries on with the following line.
+pub fn read_message(input: &mut dyn BufRead) -> io::Result<Option<(Framing, Result<String, ResponseError>)>> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    if line.trim_start().starts_with('{') || line.trim_start().starts_with('[') {
+        return Ok(Some((Framing::Lines, Ok(String::from(line.trim_end())))));
+    }
+    if header(&line).is_none() {
+        let error = parse_error(format!("not JSON or a message header: {}", line.trim_end()));
+        return Ok(Some((Framing::Lines, Err(error))));
+    }
+
+    let mut length = Err(parse_error(String::from("message has no Content-Length")));
+    while !line.trim().is_empty() {
+        match header(&line) {
+            Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                length = value.parse::<usize>().map_err(|_| parse_error(format!("bad Content-Length: {}", value)));
+            },
+            Some(_) => {},
+            None => {
+                length = Err(parse_error(format!("bad message header: {}", line.trim_end())));
+            },
+        }
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "end of input in message headers"));
+        }
+    }
+    let length = match length {
+        Ok(length) => length,
+        Err(error) => return Ok(Some((Framing::ContentLength, Err(error)))),
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|e| parse_error(e.to_string()));
+    Ok(Some((Framing::ContentLength, body)))
+}
+
+pub fn write_message(output: &mut dyn Write, framing: Framing, message: &str) -> io::Result<()> {
+    match framing {
+        Framing::Lines => writeln!(output, "{}", message)?,
+        Framing::ContentLength => write!(output, "Content-Length: {}\r\n\r\n{}", message.len(), message)?,
+    }
+    output.flush()
+}
+
+#[derive(Deserialize)]
+struct Message {
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct ProcessParams {
+    #[serde(default)]
+    buffer: Option<String>,
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[derive(Deserialize)]
+struct CloseParams {
+    buffer: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResponseError {
+    pub code: i32,
+    pub message: String,
+}
+
+#[derive(Serialize)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ResponseError>,
+}
+
+// JSON-RPC error codes.
+pub const PARSE_ERROR: i32 = -32700;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INVALID_PARAMS: i32 = -32602;
+
+/// The response to the request `id`.
+pub fn response(id: Value, outcome: Result<Value, ResponseError>) -> String {
+    let (result, error) = match outcome {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    let response = Response { jsonrpc: "2.0", id, result, error };
+    serde_json::to_string(&response).expect("unable to produce JSON")
+}
+
+// What is remembered about a buffer between requests.
+#[derive(Default)]
+struct Session {
+    text: Option<String>,
+    cursor_x: Option<Column>,
+    cursor_line: Option<LineNumber>,
+    cache: Option<parinfer::Cache>,
+}
+
+#[derive(Default)]
+pub struct Server {
+    sessions: HashMap<String, Session>,
+    shut_down: bool,
+}
+
+fn process(session: &mut Session, mut request: Request) -> Value {
+    if request.options.prev_text.is_none() && request.options.changes.is_empty() {
+        request.options.prev_text = session.text.clone();
+        if request.options.prev_cursor_line.is_none() {
+            request.options.prev_cursor_x = session.cursor_x;
+            request.options.prev_cursor_line = session.cursor_line;
+        }
+    }
+
+    let (answer, cache) = parinfer::run_mode_cached(request.mode, &request.text, &request.options,
+                                                    session.cache.as_ref());
+    let result = serde_json::to_value(&answer).expect("unable to produce JSON");
+    let (text, cursor_x, cursor_line) = if answer.success {
+        (answer.text.into_owned(), answer.cursor_x, answer.cursor_line)
+    } else {
+        (request.text, request.options.cursor_x, request.options.cursor_line)
+    };
+    *session = Session {
+        text: Some(text),
+        cursor_x,
+        cursor_line,
+        cache,
+    };
+    result
+}
+
+impl Server {
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, ResponseError> {
+        let invalid_params = |e: serde_json::Error| ResponseError {
+            code: INVALID_PARAMS,
+            message: e.to_string(),
+        };
+        match method {
+            "process" => {
+                let params: ProcessParams = serde_json::from_value(params).map_err(invalid_params)?;
+                let mut scratch = Session::default();
+                let session = match params.buffer {
+                    Some(buffer) => self.sessions.entry(buffer).or_default(),
+                    None => &mut scratch,
+                };
+                Ok(process(session, params.request))
+            },
+            "close" => {
+                let params: CloseParams = serde_json::from_value(params).map_err(invalid_params)?;
+                self.sessions.remove(&params.buffer);
+                Ok(Value::Null)
+            },
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            },
+            _ => Err(ResponseError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method: {}", method),
+            }),
+        }
+    }
+
+    /// Handles one message, returning the response if it needs one.
+    /// Messages without an `id` are notifications and get none.
+    pub fn handle(&mut self, message: &str) -> Option<String> {
+        let (id, outcome) = match serde_json::from_str::<Message>(message) {
+            Ok(message) => {
+                let outcome = self.call(&message.method, message.params);
+                (message.id?, outcome)
+            },
+            Err(e) => (Value::Null, Err(parse_error(e.to_string()))),
+        };
+        Some(response(id, outcome))
+    }
+}
+
+/// Answers messages from `input` until it ends or a `shutdown` arrives.
+pub fn serve(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+    let mut server = Server::default();
+    while let Some((framing, message)) = read_message(input)? {
+        let response = match message {
+            Ok(message) => server.handle(&message),
+            Err(error) => Some(response(Value::Null, Err(error))),
+        };
+        if let Some(response) = response {
+            write_message(output, framing, &response)?;
+        }
+        if server.is_shut_down() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn serve_str(input: &str) -> String {
+        let mut output = vec![];
+        serve(&mut input.as_bytes(), &mut output).expect("unable to serve");
+        String::from_utf8(output).expect("output is not UTF-8")
+    }
+
+    fn responses(input: &str) -> Vec<Value> {
+        serve_str(input)
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("response is not JSON"))
+            .collect()
+    }
+
+    #[test]
+    pub fn answers_line_delimited_requests() {
+        let responses = responses(concat!(
+            r#"{"id": 1, "method": "process", "params": {"mode": "indent", "text": "(foo", "options": {}}}"#, "\n",
+            "\n",
+            r#"{"id": "two", "method": "nope"}"#, "\n",
+            "{\n",
+            r#"{"id": 3, "method": "shutdown"}"#, "\n",
+            r#"{"id": 4, "method": "shutdown"}"#, "\n",
+        ));
+        assert_eq!(responses.len(), 4);
+        assert_eq!(responses[0]["id"], 1);
+        assert_eq!(responses[0]["result"]["text"], "(foo)");
+        assert_eq!(responses[1]["id"], "two");
+        assert_eq!(responses[1]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[2]["id"], Value::Null);
+        assert_eq!(responses[2]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[3]["id"], 3);
+        assert_eq!(responses[3]["result"], Value::Null);
+    }
+
+    #[test]
+    pub fn answers_content_length_framed_requests() {
+        let body = r#"{"id": 1, "method": "process", "params": {"mode": "paren", "text": "(foo\nbar)", "options": {}}}"#;
+        let output = serve_str(&format!("Content-Length: {}\r\nContent-Type: application/json\r\n\r\n{}", body.len(), body));
+        let expected = r#"{"jsonrpc":"2.0","id":1,"result":"#;
+        assert!(output.starts_with("Content-Length: "));
+        let body = &output[output.find("\r\n\r\n").expect("no header end") + 4..];
+        assert_eq!(output[16..output.find('\r').unwrap()].parse::<usize>().unwrap(), body.len());
+        assert!(body.starts_with(expected));
+        let response: Value = serde_json::from_str(body).expect("response is not JSON");
+        assert_eq!(response["result"]["text"], "(foo\n bar)");
+    }
+
+    #[test]
+    pub fn keeps_serving_after_unreadable_messages() {
+        let request = r#"{"id": 1, "method": "shutdown"}"#;
+        let responses = responses(&format!("hello\n{}\n", request));
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[1]["id"], 1);
+
+        let output = serve_str(&format!("Content-Length: many\r\n\r\nContent-Length: {}\r\n\r\n{}", request.len(), request));
+        let mut output = output.as_bytes();
+        let mut bodies: Vec<Value> = vec![];
+        while let Some((framing, body)) = read_message(&mut output).expect("unable to read output") {
+            assert_eq!(framing, Framing::ContentLength);
+            bodies.push(serde_json::from_str(&body.expect("unreadable response")).expect("response is not JSON"));
+        }
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(bodies[1]["id"], 1);
+    }
+
+    #[test]
+    pub fn notifications_get_no_response() {
+        assert_eq!(serve_str(r#"{"method": "close", "params": {"buffer": "a.clj"}}"#), "");
+    }
+
+    #[test]
+    pub fn sessions_remember_the_previous_text_of_each_buffer() {
+        let request = |id: u32, buffer: &str, text: &str, line: u32, x: u32| format!(
+            r#"{{"id": {}, "method": "process", "params": {{"buffer": "{}", "mode": "smart", "text": "{}", "options": {{"cursorLine": {}, "cursorX": {}}}}}}}"#,
+            id, buffer, text, line, x
+        );
+        let responses = responses(&[
+            request(1, "a.clj", "(foo\\n  (bar))", 0, 0),
+            request(2, "b.clj", "(foo\\n  (bar))", 0, 0),
+            // Indenting the first line moves the rest with it, as the
+            // session knows what the buffer looked like before.
+            request(3, "a.clj", "  (foo\\n  (bar))", 0, 2),
+            request(4, "b.clj", "  (foo\\n  (bar))", 0, 2),
+            String::from(r#"{"id": 5, "method": "close", "params": {"buffer": "a.clj"}}"#),
+            request(6, "a.clj", "  (foo\\n  (bar))", 0, 2),
+        ].join("\n"));
+        assert_eq!(responses[2]["result"]["text"], "  (foo\n    (bar))");
+        assert_eq!(responses[3]["result"]["text"], "  (foo\n    (bar))");
+        assert_eq!(responses[4]["result"], Value::Null);
+        // Once closed, the buffer's indentation is taken as it is.
+        assert_eq!(responses[5]["result"]["text"], "  (foo)\n  (bar)");
+    }
+}
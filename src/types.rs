@@ -1,15 +1,15 @@
 use serde;
 use serde_json;
 use std;
-use std::{fmt,
-          mem,
-          rc::Rc};
+use std::{borrow::Cow,
+          fmt,
+          str::FromStr};
 
 pub type LineNumber = usize;
 pub type Column = usize;
 pub type Delta = i64;
 
-#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Change {
     pub x: Column,
@@ -18,7 +18,18 @@ pub struct Change {
     pub new_text: String,
 }
 
-#[derive(Clone, Deserialize, Debug)]
+/// One of several cursors, or the end of a selection the cursor is at.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Cursor {
+    pub x: Column,
+    pub line_no: LineNumber,
+    /// The line the other end of the selection is on, if there is one.
+    #[serde(default)]
+    pub selection_start_line: Option<LineNumber>,
+}
+
+#[derive(Clone, Deserialize, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Options {
     pub cursor_x: Option<Column>,
@@ -27,6 +38,17 @@ pub struct Options {
     pub prev_cursor_line: Option<LineNumber>,
     pub prev_text: Option<String>,
     pub selection_start_line: Option<LineNumber>,
+    /// Cursors besides the one at `cursor_x` and `cursor_line`, for editors
+    /// with several.  Parens are held in place by any of them.
+    #[serde(default)]
+    pub cursors: Vec<Cursor>,
+    /// Where each of `cursors` was before the changes.
+    #[serde(default)]
+    pub prev_cursors: Vec<Cursor>,
+    /// Other positions, as `(line, x)`, to move along with the text, such as
+    /// marks or other windows' cursors.
+    #[serde(default)]
+    pub track_positions: Vec<(LineNumber, Column)>,
     #[serde(default = "Options::default_changes")]
     pub changes: Vec<Change>,
     #[serde(default = "Options::default_false")]
@@ -35,10 +57,34 @@ pub struct Options {
     pub force_balance: bool,
     #[serde(default = "Options::default_false")]
     pub return_parens: bool,
+    #[serde(default = "Options::default_false")]
+    pub collect_errors: bool,
+    /// Returns the edits made to the text in `Answer::edits`.
+    #[serde(default = "Options::default_false")]
+    pub return_edits: bool,
     #[serde(default = "Options::default_comment")]
     pub comment_char: char,
+    /// Strings which start a line comment, used instead of `comment_char`
+    /// when not empty.  Where several match, the longest wins.
+    #[serde(default = "Options::default_comment_strings")]
+    pub comment_strings: Vec<String>,
     #[serde(default = "Options::default_string_delimiters")]
     pub string_delimiters: Vec<String>,
+    /// The open and close characters of each kind of paren, e.g. `"()"`.
+    /// Pairs which aren't two different characters are ignored.
+    #[serde(default = "Options::default_paren_pairs")]
+    pub paren_pairs: Vec<String>,
+    /// The number of columns between tab stops, from 1 to 16.  Columns
+    /// count a tab as reaching the next tab stop.
+    #[serde(default = "Options::default_tab_width")]
+    pub tab_width: usize,
+    /// Keeps tabs in code, and re-indents tab-indented lines with tabs,
+    /// instead of expanding them to spaces.
+    #[serde(default = "Options::default_false")]
+    pub preserve_tabs: bool,
+    /// What the `x` positions in the request and answer count.
+    #[serde(default)]
+    pub position_encoding: PositionEncoding,
     #[serde(default = "Options::default_false")]
     pub lisp_vline_symbols: bool,
     #[serde(default = "Options::default_false")]
@@ -51,6 +97,172 @@ pub struct Options {
     pub janet_long_strings: bool,
 }
 
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            cursor_x: None,
+            cursor_line: None,
+            prev_cursor_x: None,
+            prev_cursor_line: None,
+            prev_text: None,
+            selection_start_line: None,
+            cursors: vec![],
+            prev_cursors: vec![],
+            track_positions: vec![],
+            changes: Options::default_changes(),
+            partial_result: false,
+            force_balance: false,
+            return_parens: false,
+            collect_errors: false,
+            return_edits: false,
+            comment_char: Options::default_comment(),
+            comment_strings: Options::default_comment_strings(),
+            string_delimiters: Options::default_string_delimiters(),
+            paren_pairs: Options::default_paren_pairs(),
+            tab_width: Options::default_tab_width(),
+            preserve_tabs: false,
+            position_encoding: PositionEncoding::Graphemes,
+            lisp_vline_symbols: false,
+            lisp_block_comments: false,
+            guile_block_comments: false,
+            scheme_sexp_comments: false,
+            janet_long_strings: false,
+        }
+    }
+}
+
+// Builder-style setters, so that Rust callers can write
+// `Options::default().with_cursor(x, line).with_changes(changes)`.
+#[allow(dead_code)]
+impl Options {
+    pub fn with_cursor(mut self, x: Column, line_no: LineNumber) -> Options {
+        self.cursor_x = Some(x);
+        self.cursor_line = Some(line_no);
+        self
+    }
+
+    pub fn with_prev_cursor(mut self, x: Column, line_no: LineNumber) -> Options {
+        self.prev_cursor_x = Some(x);
+        self.prev_cursor_line = Some(line_no);
+        self
+    }
+
+    pub fn with_prev_text<S: Into<String>>(mut self, prev_text: S) -> Options {
+        self.prev_text = Some(prev_text.into());
+        self
+    }
+
+    pub fn with_selection_start_line(mut self, line_no: LineNumber) -> Options {
+        self.selection_start_line = Some(line_no);
+        self
+    }
+
+    pub fn with_cursors(mut self, cursors: Vec<Cursor>) -> Options {
+        self.cursors = cursors;
+        self
+    }
+
+    pub fn with_prev_cursors(mut self, prev_cursors: Vec<Cursor>) -> Options {
+        self.prev_cursors = prev_cursors;
+        self
+    }
+
+    pub fn with_track_positions(mut self, track_positions: Vec<(LineNumber, Column)>) -> Options {
+        self.track_positions = track_positions;
+        self
+    }
+
+    pub fn with_changes(mut self, changes: Vec<Change>) -> Options {
+        self.changes = changes;
+        self
+    }
+
+    pub fn with_partial_result(mut self, partial_result: bool) -> Options {
+        self.partial_result = partial_result;
+        self
+    }
+
+    pub fn with_force_balance(mut self, force_balance: bool) -> Options {
+        self.force_balance = force_balance;
+        self
+    }
+
+    pub fn with_return_parens(mut self, return_parens: bool) -> Options {
+        self.return_parens = return_parens;
+        self
+    }
+
+    pub fn with_collect_errors(mut self, collect_errors: bool) -> Options {
+        self.collect_errors = collect_errors;
+        self
+    }
+
+    pub fn with_return_edits(mut self, return_edits: bool) -> Options {
+        self.return_edits = return_edits;
+        self
+    }
+
+    pub fn with_comment_char(mut self, comment_char: char) -> Options {
+        self.comment_char = comment_char;
+        self
+    }
+
+    pub fn with_comment_strings(mut self, comment_strings: Vec<String>) -> Options {
+        self.comment_strings = comment_strings;
+        self
+    }
+
+    pub fn with_string_delimiters(mut self, string_delimiters: Vec<String>) -> Options {
+        self.string_delimiters = string_delimiters;
+        self
+    }
+
+    pub fn with_paren_pairs(mut self, paren_pairs: Vec<String>) -> Options {
+        self.paren_pairs = paren_pairs;
+        self
+    }
+
+    pub fn with_tab_width(mut self, tab_width: usize) -> Options {
+        self.tab_width = tab_width;
+        self
+    }
+
+    pub fn with_preserve_tabs(mut self, preserve_tabs: bool) -> Options {
+        self.preserve_tabs = preserve_tabs;
+        self
+    }
+
+    pub fn with_position_encoding(mut self, position_encoding: PositionEncoding) -> Options {
+        self.position_encoding = position_encoding;
+        self
+    }
+
+    pub fn with_lisp_vline_symbols(mut self, enabled: bool) -> Options {
+        self.lisp_vline_symbols = enabled;
+        self
+    }
+
+    pub fn with_lisp_block_comments(mut self, enabled: bool) -> Options {
+        self.lisp_block_comments = enabled;
+        self
+    }
+
+    pub fn with_guile_block_comments(mut self, enabled: bool) -> Options {
+        self.guile_block_comments = enabled;
+        self
+    }
+
+    pub fn with_scheme_sexp_comments(mut self, enabled: bool) -> Options {
+        self.scheme_sexp_comments = enabled;
+        self
+    }
+
+    pub fn with_janet_long_strings(mut self, enabled: bool) -> Options {
+        self.janet_long_strings = enabled;
+        self
+    }
+}
+
 impl Options {
     fn default_changes() -> Vec<Change> {
         vec![]
@@ -63,15 +275,64 @@ impl Options {
     fn default_comment() -> char {
         ';'
     }
+    fn default_comment_strings() -> Vec<String> {
+        vec![]
+    }
     fn default_string_delimiters() -> Vec<String> {
         vec!["\"".to_string()]
     }
+    fn default_paren_pairs() -> Vec<String> {
+        vec!["()".to_string(), "[]".to_string(), "{}".to_string()]
+    }
+    fn default_tab_width() -> usize {
+        2
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Indent,
+    Paren,
+    Smart,
+}
+
+/// The units of the `x` positions exchanged with the editor.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PositionEncoding {
+    /// Display columns: wide characters count as two, and tabs reach the
+    /// next tab stop.  This is what parinfer uses internally.
+    #[default]
+    Graphemes,
+    /// UTF-16 code units, as in JavaScript strings and LSP.
+    Utf16,
+    /// Bytes of UTF-8.
+    Utf8Bytes,
+    /// Unicode scalar values.
+    Codepoints,
+}
+
+impl FromStr for Mode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Mode, Error> {
+        match s {
+            "indent" => Ok(Mode::Indent),
+            "paren" => Ok(Mode::Paren),
+            "smart" => Ok(Mode::Smart),
+            _ => Err(Error {
+                message: format!("Bad value specified for `mode`: {}", s),
+                ..Error::default()
+            }),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Request {
-    pub mode: String,
+    pub mode: Mode,
     pub text: String,
     pub options: Options,
 }
@@ -79,12 +340,24 @@ pub struct Request {
 #[derive(Clone,Serialize,Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct TabStop<'a> {
-    pub ch: &'a str,
+    pub ch: Cow<'a, str>,
     pub x: Column,
     pub line_no: LineNumber,
     pub arg_x: Option<Column>,
 }
 
+#[allow(dead_code)]
+impl<'a> TabStop<'a> {
+    pub fn into_owned(self) -> TabStop<'static> {
+        TabStop {
+            ch: Cow::Owned(self.ch.into_owned()),
+            x: self.x,
+            line_no: self.line_no,
+            arg_x: self.arg_x,
+        }
+    }
+}
+
 #[derive(Clone,Debug,Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ParenTrail {
@@ -93,19 +366,32 @@ pub struct ParenTrail {
     pub end_x: Column,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Closer<'a> {
     pub line_no: LineNumber,
     pub x: Column,
-    pub ch: &'a str,
+    pub ch: Cow<'a, str>,
     pub trail: Option<ParenTrail>
 }
 
+#[allow(dead_code)]
+impl<'a> Closer<'a> {
+    pub fn into_owned(self) -> Closer<'static> {
+        Closer {
+            line_no: self.line_no,
+            x: self.x,
+            ch: Cow::Owned(self.ch.into_owned()),
+            trail: self.trail,
+        }
+    }
+}
+
 #[derive(Clone,Debug,Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Paren<'a> {
     pub line_no: LineNumber,
-    pub ch: &'a str,
+    pub ch: Cow<'a, str>,
     pub x: Column,
     pub indent_delta: Delta,
     pub max_child_indent: Option<Column>,
@@ -113,33 +399,88 @@ pub struct Paren<'a> {
     pub input_line_no: LineNumber,
     pub input_x: Column,
 
-    #[serde(skip)]
     pub closer: Option<Closer<'a>>,
-    #[serde(skip)]
     pub children: Vec<Paren<'a>>
 }
 
+#[allow(dead_code)]
+impl<'a> Paren<'a> {
+    pub fn into_owned(self) -> Paren<'static> {
+        Paren {
+            line_no: self.line_no,
+            ch: Cow::Owned(self.ch.into_owned()),
+            x: self.x,
+            indent_delta: self.indent_delta,
+            max_child_indent: self.max_child_indent,
+            arg_x: self.arg_x,
+            input_line_no: self.input_line_no,
+            input_x: self.input_x,
+            closer: self.closer.map(Closer::into_owned),
+            children: self.children.into_iter().map(Paren::into_owned).collect(),
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Answer<'a> {
-    pub text: std::borrow::Cow<'a, str>,
+    pub text: Cow<'a, str>,
     pub success: bool,
     pub error: Option<Error>,
+    pub errors: Vec<Error>,
     pub cursor_x: Option<Column>,
     pub cursor_line: Option<LineNumber>,
+    /// Where the request's `cursors` moved to, in the same order.
+    pub cursors: Vec<Cursor>,
+    /// Where the request's `track_positions` moved to, in the same order.
+    pub tracked_positions: Vec<(LineNumber, Column)>,
+    /// With `return_edits`, the smallest edits turning the request's text
+    /// into `text`.  They come last first, so each is positioned in the
+    /// request's text and they can also be applied one after another.
+    pub edits: Vec<Change>,
     pub tab_stops: Vec<TabStop<'a>>,
     pub paren_trails: Vec<ParenTrail>,
     pub parens: Vec<Paren<'a>>,
 }
 
+#[allow(dead_code)]
+impl<'a> Answer<'a> {
+    /// Copies any text borrowed from the request, so the answer can outlive it.
+    pub fn into_owned(self) -> Answer<'static> {
+        Answer {
+            text: Cow::Owned(self.text.into_owned()),
+            success: self.success,
+            error: self.error,
+            errors: self.errors,
+            cursor_x: self.cursor_x,
+            cursor_line: self.cursor_line,
+            cursors: self.cursors,
+            tracked_positions: self.tracked_positions,
+            edits: self.edits,
+            tab_stops: self.tab_stops.into_iter().map(TabStop::into_owned).collect(),
+            paren_trails: self.paren_trails,
+            parens: self.parens.into_iter().map(Paren::into_owned).collect(),
+        }
+    }
+}
+
+/// An `Answer` which doesn't borrow from the request, for callers that need
+/// to hold on to results (e.g. as an Emacs user-ptr).
+#[allow(dead_code)]
+pub type OwnedAnswer = Answer<'static>;
+
 impl<'a> From<Error> for Answer<'a> {
     fn from(error: Error) -> Answer<'a> {
         Answer {
-            text: std::borrow::Cow::from(""),
+            text: Cow::from(""),
             success: false,
+            errors: vec![error.clone()],
             error: Some(error),
             cursor_x: None,
             cursor_line: None,
+            cursors: vec![],
+            tracked_positions: vec![],
+            edits: vec![],
             tab_stops: vec![],
             paren_trails: vec![],
             parens: vec![],
@@ -159,6 +500,8 @@ pub enum ErrorName {
 
     Utf8EncodingError,
     JsonEncodingError,
+    InvalidChange,
+    UnknownDocument,
     Panic,
 
     Restart,
@@ -182,6 +525,8 @@ impl ToString for ErrorName {
             &ErrorName::LeadingCloseParen => "leading-close-paren",
             &ErrorName::Utf8EncodingError => "utf8-error",
             &ErrorName::JsonEncodingError => "json-error",
+            &ErrorName::InvalidChange => "invalid-change",
+            &ErrorName::UnknownDocument => "unknown-document",
             &ErrorName::Panic => "panic",
             _ => "??",
         })
@@ -222,6 +567,8 @@ impl<'a> serde::Deserialize<'a> for ErrorName {
                      "leading-close-paren" => Ok(ErrorName::LeadingCloseParen),
                      "utf8-error" => Ok(ErrorName::Utf8EncodingError),
                      "json-error" => Ok(ErrorName::JsonEncodingError),
+                     "invalid-change" => Ok(ErrorName::InvalidChange),
+                     "unknown-document" => Ok(ErrorName::UnknownDocument),
                      "panic" => Ok(ErrorName::Panic),
                     _ => Err(E::custom(format!("unknown error name: {}", value)))
                 }
@@ -241,8 +588,27 @@ pub struct Error {
     pub line_no: LineNumber,
     pub input_x: Column,
     pub input_line_no: LineNumber,
+    /// Where the open-paren related to an unmatched-close-paren or
+    /// leading-close-paren error is, if there is one.
+    pub extra: Option<ErrorExtra>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorExtra {
+    pub name: ErrorName,
+    pub line_no: LineNumber,
+    pub x: Column,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
 
+impl std::error::Error for Error {}
+
 impl From<std::str::Utf8Error> for Error {
     fn from(error: std::str::Utf8Error) -> Error {
         Error {
@@ -272,45 +638,3 @@ impl From<serde_json::Error> for Error {
         }
     }
 }
-
-// Introduce the concept of Reference Counting of requests to work with emacs memory module
-#[allow(dead_code)]
-pub type SharedRequest = Rc<Request>;
-
-// Info needed to store a pointer to answer
-const ANSWER_LEN: usize = mem::size_of::<Answer>() / 8;
-pub type RawAnswer = [u64; ANSWER_LEN];
-
-#[allow(dead_code)]
-pub struct WrappedAnswer{
-  request: SharedRequest,
-  raw: RawAnswer
-}
-
-
-impl WrappedAnswer {
-    #[inline]
-    #[allow(dead_code)]
-    pub unsafe fn new(request: SharedRequest, inner: Answer) -> Self {
-        let ptr = (&inner as *const Answer) as *const RawAnswer;
-        // Delay inner cursor's cleanup (until wrapper is dropped).
-        mem::forget(inner);
-        let raw = ptr.read();
-        Self { request, raw }
-    }
-
-    #[inline]
-    #[allow(dead_code)]
-    pub fn inner(&self) -> &Answer {
-        let ptr = (&self.raw as *const RawAnswer) as *const Answer;
-        unsafe { &*ptr }
-    }
-}
-
-impl Drop for WrappedAnswer {
-    #[inline]
-    fn drop(&mut self) {
-        let ptr = (&mut self.raw as *mut RawAnswer) as *mut Answer;
-        unsafe { ptr.read() };
-    }
-}
@@ -1,7 +1,143 @@
 use types::*;
+use parinfer;
+use serde::Serialize;
 use serde_json;
-use std::panic;
+use serde_wasm_bindgen;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
 use super::common_wrapper;
+use document::Document;
+use wasm_bindgen;
+use wasm_bindgen::prelude::*;
+
+// The shapes of the objects passed to and returned from `indentMode` and
+// friends, for the generated `parinfer_rust.d.ts`.  They follow the JSON
+// requests and answers, field for field.
+#[wasm_bindgen(typescript_custom_section)]
+const TYPESCRIPT_TYPES: &'static str = r#"
+export type Mode = "indent" | "paren" | "smart";
+
+export type PositionEncoding = "graphemes" | "utf16" | "utf8-bytes" | "codepoints";
+
+export interface Change {
+    x: number;
+    lineNo: number;
+    oldText: string;
+    newText: string;
+}
+
+export interface Cursor {
+    x: number;
+    lineNo: number;
+    selectionStartLine?: number | null;
+}
+
+export interface Options {
+    cursorX?: number | null;
+    cursorLine?: number | null;
+    prevCursorX?: number | null;
+    prevCursorLine?: number | null;
+    prevText?: string | null;
+    selectionStartLine?: number | null;
+    cursors?: Cursor[];
+    prevCursors?: Cursor[];
+    trackPositions?: [number, number][];
+    changes?: Change[];
+    partialResult?: boolean;
+    forceBalance?: boolean;
+    returnParens?: boolean;
+    collectErrors?: boolean;
+    returnEdits?: boolean;
+    commentChar?: string;
+    commentStrings?: string[];
+    stringDelimiters?: string[];
+    parenPairs?: string[];
+    tabWidth?: number;
+    preserveTabs?: boolean;
+    positionEncoding?: PositionEncoding;
+    lispVlineSymbols?: boolean;
+    lispBlockComments?: boolean;
+    guileBlockComments?: boolean;
+    schemeSexpComments?: boolean;
+    janetLongStrings?: boolean;
+}
+
+export interface ErrorExtra {
+    name: string;
+    lineNo: number;
+    x: number;
+}
+
+export interface ParinferError {
+    name: string;
+    message: string;
+    x: number;
+    lineNo: number;
+    inputX: number;
+    inputLineNo: number;
+    extra: ErrorExtra | null;
+}
+
+export interface TabStop {
+    ch: string;
+    x: number;
+    lineNo: number;
+    argX: number | null;
+}
+
+export interface ParenTrail {
+    lineNo: number;
+    startX: number;
+    endX: number;
+}
+
+export interface Closer {
+    lineNo: number;
+    x: number;
+    ch: string;
+    trail: ParenTrail | null;
+}
+
+export interface Paren {
+    lineNo: number;
+    ch: string;
+    x: number;
+    indentDelta: number;
+    maxChildIndent: number | null;
+    argX: number | null;
+    inputLineNo: number;
+    inputX: number;
+    closer: Closer | null;
+    children: Paren[];
+}
+
+export interface Answer {
+    text: string;
+    success: boolean;
+    error: ParinferError | null;
+    errors: ParinferError[];
+    cursorX: number | null;
+    cursorLine: number | null;
+    cursors: Cursor[];
+    trackedPositions: [number, number][];
+    edits: Change[];
+    tabStops: TabStop[];
+    parenTrails: ParenTrail[];
+    parens: Paren[];
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// A JavaScript object shaped like `Options`.
+    #[wasm_bindgen(typescript_type = "Options")]
+    pub type JsOptions;
+
+    /// A JavaScript object shaped like `Answer`.
+    #[wasm_bindgen(typescript_type = "Answer")]
+    pub type JsAnswer;
+}
 
 pub fn run_parinfer(input: String) -> String {
     match panic::catch_unwind(|| common_wrapper::internal_run(&input)) {
@@ -11,13 +147,118 @@ pub fn run_parinfer(input: String) -> String {
     }
 }
 
+fn options_from_js(options: Option<JsOptions>) -> Result<Options, Error> {
+    let options: JsValue = match options {
+        Some(options) => options.into(),
+        None => return Ok(Options::default()),
+    };
+    if options.is_null() || options.is_undefined() {
+        return Ok(Options::default());
+    }
+    serde_wasm_bindgen::from_value(options).map_err(|error| Error {
+        name: ErrorName::JsonEncodingError,
+        message: format!("Error reading options: {}", error),
+        ..Error::default()
+    })
+}
+
+fn answer_to_js(answer: &Answer) -> JsAnswer {
+    // json_compatible() makes `None` null and maps plain objects, so the
+    // answer looks the same as one parsed from `run_parinfer`'s JSON.
+    let serializer = serde_wasm_bindgen::Serializer::json_compatible();
+    answer.serialize(&serializer).unwrap().into()
+}
+
+/// Runs `mode` over `text` with options given as a JavaScript object,
+/// returning the answer as one.
+pub fn run_mode(mode: Mode, text: String, options: Option<JsOptions>) -> JsAnswer {
+    let result = panic::catch_unwind(|| -> Result<Answer<'static>, Error> {
+        let options = options_from_js(options)?;
+        Ok(parinfer::run_mode(mode, &text, &options).into_owned())
+    });
+    match result {
+        Ok(Ok(answer)) => answer_to_js(&answer),
+        Ok(Err(e)) => answer_to_js(&Answer::from(e)),
+        Err(_) => answer_to_js(&Answer::from(Error {
+            name: ErrorName::Panic,
+            message: String::from("plugin panicked!"),
+            ..Error::default()
+        })),
+    }
+}
+
+// JavaScript can't hold on to a Rust value, so documents are kept here and
+// handed out by number.  Zero is never a valid handle.
+thread_local! {
+    static DOCUMENTS: RefCell<HashMap<u32, Document>> = RefCell::new(HashMap::new());
+    static NEXT_HANDLE: Cell<u32> = Cell::new(1);
+}
+
+/// Returns a handle to a new document, or zero if the request is invalid.
+pub fn new_document(input: String) -> u32 {
+    match panic::catch_unwind(|| common_wrapper::new_document(&input)) {
+        Ok(Ok(document)) => {
+            let handle = NEXT_HANDLE.with(|next| {
+                let handle = next.get();
+                next.set(handle.wrapping_add(1).max(1));
+                handle
+            });
+            DOCUMENTS.with(|documents| documents.borrow_mut().insert(handle, document));
+            handle
+        },
+        _ => 0
+    }
+}
+
+pub fn update_document(handle: u32, input: String) -> String {
+    DOCUMENTS.with(|documents| {
+        let mut documents = documents.borrow_mut();
+        let document = match documents.get_mut(&handle) {
+            Some(document) => AssertUnwindSafe(document),
+            None => {
+                let error = Error {
+                    name: ErrorName::UnknownDocument,
+                    message: format!("No document with handle {}.", handle),
+                    ..Error::default()
+                };
+                return serde_json::to_string(&Answer::from(error)).unwrap();
+            }
+        };
+        match panic::catch_unwind(move || {
+            let AssertUnwindSafe(document) = document;
+            common_wrapper::update_document(document, &input)
+        }) {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => serde_json::to_string(&Answer::from(e)).unwrap(),
+            Err(_) => common_wrapper::panic_result()
+        }
+    })
+}
+
+pub fn free_document(handle: u32) {
+    DOCUMENTS.with(|documents| documents.borrow_mut().remove(&handle));
+}
+
 #[cfg(test)]
 mod tests {
-    use super::run_parinfer;
+    use super::{free_document, new_document, run_mode, run_parinfer, update_document};
+    use serde::Serialize;
     use serde_json;
-    use serde_json::Value;
+    use serde_json::{json, Value};
+    use serde_wasm_bindgen;
+    use types::Mode;
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::*;
+
+    fn to_js(value: Value) -> JsValue {
+        value.serialize(&serde_wasm_bindgen::Serializer::json_compatible()).unwrap()
+    }
 
-    #[test]
+    fn from_js(value: JsValue) -> Value {
+        serde_wasm_bindgen::from_value(value).unwrap()
+    }
+
+    #[wasm_bindgen_test]
     fn it_works() {
         let out = run_parinfer(String::from(r#"{
             "mode": "indent",
@@ -39,4 +280,45 @@ mod tests {
             "returns correct text"
         );
     }
+
+    #[wasm_bindgen_test]
+    fn modes_take_and_return_objects() {
+        let options = to_js(json!({"cursorX": 3, "cursorLine": 0, "returnParens": true}));
+        let answer = from_js(run_mode(Mode::Indent, String::from("(def x"), Some(options.into())).into());
+        assert_eq!(Value::Bool(true), answer["success"]);
+        assert_eq!(Value::String(String::from("(def x)")), answer["text"]);
+        assert_eq!(json!(3), answer["cursorX"]);
+        assert_eq!(Value::Null, answer["error"]);
+        assert_eq!(json!("("), answer["parens"][0]["ch"]);
+
+        let answer = from_js(run_mode(Mode::Paren, String::from("(def\nx)"), None).into());
+        assert_eq!(Value::String(String::from("(def\n x)")), answer["text"]);
+
+        let options = to_js(json!({"tabWidth": "wide"}));
+        let answer = from_js(run_mode(Mode::Smart, String::from("(def x"), Some(options.into())).into());
+        assert_eq!(Value::Bool(false), answer["success"]);
+        assert_eq!(json!("json-error"), answer["error"]["name"]);
+    }
+
+    #[wasm_bindgen_test]
+    fn documents_work() {
+        let handle = new_document(String::from(r#"{
+            "mode": "indent",
+            "text": "(def x",
+            "options": {}
+        }"#));
+        assert!(handle != 0);
+        let out = update_document(handle, String::from(r#"{
+            "changes": [{"lineNo": 0, "x": 6, "oldText": "", "newText": " 1"}],
+            "cursorX": 8,
+            "cursorLine": 0
+        }"#));
+        let answer : Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(Value::String(String::from("(def x 1)")), answer["text"]);
+        free_document(handle);
+        let out = update_document(handle, String::from("{}"));
+        let answer : Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(Value::Bool(false), answer["success"]);
+        assert_eq!(json!("unknown-document"), answer["error"]["name"]);
+    }
 }
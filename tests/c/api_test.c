@@ -0,0 +1,198 @@
+/* Exercises parinfer_rust.h from C.  Run by tests/c_api.rs. */
+
+#include <assert.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "parinfer_rust.h"
+
+static const char *request = "{\"mode\": \"indent\", \"text\": \"(def x\", \"options\": {}}";
+
+static void test_version(void)
+{
+    assert(parinfer_api_version() == PARINFER_API_VERSION);
+    assert(strlen(parinfer_version()) > 0);
+}
+
+static void test_run(void)
+{
+    char *first = parinfer_run(request);
+    char *second = parinfer_run("{\"mode\": \"paren\", \"text\": \"(a\\nb)\", \"options\": {}}");
+    /* Each answer lasts until it is freed. */
+    assert(strstr(first, "\"text\":\"(def x)\"") != NULL);
+    assert(strstr(second, "\"text\":\"(a\\n b)\"") != NULL);
+    parinfer_free(first);
+    parinfer_free(second);
+    parinfer_free(NULL);
+
+    char *error = parinfer_run("{");
+    assert(strstr(error, "\"success\":false") != NULL);
+    parinfer_free(error);
+
+    error = parinfer_run(NULL);
+    assert(strstr(error, "\"success\":false") != NULL);
+    parinfer_free(error);
+}
+
+static void test_run_into(void)
+{
+    char small[8] = "unused";
+    size_t needed = parinfer_run_into(request, small, sizeof(small));
+    assert(needed > sizeof(small));
+    assert(small[0] == '\0');
+
+    char *buffer = malloc(needed);
+    assert(parinfer_run_into(request, buffer, needed) == needed);
+    assert(strlen(buffer) == needed - 1);
+    assert(strstr(buffer, "\"text\":\"(def x)\"") != NULL);
+    free(buffer);
+
+    assert(parinfer_run_into(request, NULL, 0) == needed);
+}
+
+static void test_document(void)
+{
+    ParinferDocument *document = parinfer_document_new(request);
+    assert(document != NULL);
+    const char *answer = parinfer_document_update(document,
+        "{\"changes\": [{\"lineNo\": 0, \"x\": 6, \"oldText\": \"\", \"newText\": \" 1\"}]}");
+    assert(strstr(answer, "\"text\":\"(def x 1)\"") != NULL);
+    parinfer_document_free(document);
+    parinfer_document_free(NULL);
+}
+
+static void test_process(void)
+{
+    /* The text needn't be NUL-terminated. */
+    const char text[] = {'(', 'f', 'o', 'o', '\n', '(', 'b', 'a', 'r'};
+    ParinferOptions options;
+    parinfer_options_init(&options);
+    options.cursor_x = 4;
+    options.cursor_line = 1;
+
+    ParinferAnswer *answer = parinfer_process(PARINFER_MODE_INDENT, text, sizeof(text), &options);
+    assert(parinfer_answer_success(answer));
+    size_t length;
+    const char *result = parinfer_answer_text(answer, &length);
+    assert(length == 11 && memcmp(result, "(foo)\n(bar)", length) == 0);
+    assert(parinfer_answer_cursor_x(answer) == 4);
+    assert(parinfer_answer_cursor_line(answer) == 1);
+    ParinferError error;
+    assert(!parinfer_answer_error(answer, &error));
+
+    assert(parinfer_answer_tab_stop_count(answer) == 1);
+    ParinferTabStop stop;
+    assert(parinfer_answer_tab_stop(answer, 0, &stop));
+    assert(stop.ch == '(' && stop.x == 0 && stop.line_no == 0 && stop.arg_x == -1);
+    assert(!parinfer_answer_tab_stop(answer, 1, &stop));
+
+    assert(parinfer_answer_paren_trail_count(answer) == 2);
+    ParinferParenTrail trail;
+    assert(parinfer_answer_paren_trail(answer, 0, &trail));
+    assert(trail.line_no == 0 && trail.start_x == 4 && trail.end_x == 5);
+    assert(!parinfer_answer_paren_trail(answer, 2, &trail));
+    parinfer_answer_free(answer);
+
+    answer = parinfer_process(PARINFER_MODE_PAREN, "(foo \"bar", 9, NULL);
+    assert(!parinfer_answer_success(answer));
+    assert(parinfer_answer_error(answer, &error));
+    assert(strcmp(error.name, "unclosed-quote") == 0);
+    assert(error.line_no == 0 && error.x == 5);
+    assert(parinfer_answer_cursor_x(answer) == -1);
+    parinfer_answer_free(answer);
+
+    answer = parinfer_process(7, "", 0, NULL);
+    assert(!parinfer_answer_success(answer));
+    parinfer_answer_free(answer);
+    parinfer_answer_free(NULL);
+}
+
+static void test_process_changes(void)
+{
+    /* "foo " was typed before "[a", so smart mode moves "b 2" with it. */
+    const char *text = "(let foo [a 1\n      b 2])";
+    ParinferChange change = {
+        .x = 5, .line_no = 0,
+        .old_text = "", .old_text_length = 0,
+        .new_text = "foo ", .new_text_length = 4,
+    };
+    ParinferOptions options;
+    parinfer_options_init(&options);
+    options.cursor_x = 9;
+    options.cursor_line = 0;
+    options.changes = &change;
+    options.change_count = 1;
+    options.return_edits = true;
+    options.return_parens = true;
+
+    ParinferAnswer *answer = parinfer_process(PARINFER_MODE_SMART, text, strlen(text), &options);
+    assert(parinfer_answer_success(answer));
+    size_t length;
+    const char *result = parinfer_answer_text(answer, &length);
+    assert(length == 29 && memcmp(result, "(let foo [a 1\n          b 2])", length) == 0);
+
+    assert(parinfer_answer_edit_count(answer) == 1);
+    ParinferChange edit;
+    assert(parinfer_answer_edit(answer, 0, &edit));
+    assert(edit.line_no == 1 && edit.x == 6 && edit.old_text_length == 0);
+    assert(edit.new_text_length == 4 && memcmp(edit.new_text, "    ", 4) == 0);
+    assert(!parinfer_answer_edit(answer, 1, &edit));
+
+    assert(parinfer_answer_paren_count(answer) == 2);
+    ParinferParen paren;
+    assert(parinfer_answer_paren(answer, 0, &paren));
+    assert(paren.ch == '(' && paren.parent == -1 && paren.closer_ch == ')' && paren.closer_line_no == 1);
+    assert(parinfer_answer_paren(answer, 1, &paren));
+    assert(paren.ch == '[' && paren.x == 9 && paren.parent == 0 && paren.closer_x == 13);
+    assert(!parinfer_answer_paren(answer, 2, &paren));
+    parinfer_answer_free(answer);
+
+    /* Without the change, the same text is read as "[a 1" closing early. */
+    options.changes = NULL;
+    options.change_count = 0;
+    answer = parinfer_process(PARINFER_MODE_SMART, text, strlen(text), &options);
+    result = parinfer_answer_text(answer, &length);
+    assert(length == 25 && memcmp(result, "(let foo [a 1]\n      b 2)", length) == 0);
+    parinfer_answer_free(answer);
+}
+
+static void test_process_lists(void)
+{
+    const char *pairs[] = {"()", "<>"};
+    const char *comments[] = {"--"};
+    ParinferOptions options;
+    parinfer_options_init(&options);
+    options.paren_pairs = pairs;
+    options.paren_pair_count = 2;
+    options.comment_strings = comments;
+    options.comment_string_count = 1;
+    options.collect_errors = true;
+
+    const char *text = "(a <b> [c)) -- )\n(d))";
+    ParinferAnswer *answer = parinfer_process(PARINFER_MODE_PAREN, text, strlen(text), &options);
+    assert(!parinfer_answer_success(answer));
+    assert(parinfer_answer_collected_error_count(answer) == 2);
+    /* "[" isn't a paren, so the first error is the second ")". */
+    ParinferError error;
+    assert(parinfer_answer_collected_error(answer, 0, &error));
+    assert(error.line_no == 0 && error.x == 10);
+    assert(parinfer_answer_collected_error(answer, 1, &error));
+    assert(strcmp(error.name, "unmatched-close-paren") == 0);
+    assert(error.line_no == 1 && error.x == 3);
+    assert(!parinfer_answer_collected_error(answer, 2, &error));
+    parinfer_answer_free(answer);
+}
+
+int main(void)
+{
+    test_version();
+    test_run();
+    test_run_into();
+    test_document();
+    test_process();
+    test_process_changes();
+    test_process_lists();
+    printf("ok\n");
+    return 0;
+}
@@ -0,0 +1,90 @@
+//! Builds `tests/c/api_test.c` against `parinfer_rust.h` and the shared
+//! library, then runs it.  Skipped where there is no C compiler.  Also
+//! checks the header matches what cbindgen generates from the source.
+
+#![cfg(all(unix, not(target_arch = "wasm32")))]
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// The directory Cargo builds the shared library into, next to the
+// directory holding this test.
+fn library_dir() -> PathBuf {
+    let mut dir = env::current_exe().expect("no test executable path");
+    dir.pop();
+    if dir.ends_with("deps") {
+        dir.pop();
+    }
+    dir
+}
+
+// `cargo test` only builds the rlib, so build the cdylib for the same
+// profile before linking against it.
+fn build_library(library_dir: &Path) -> bool {
+    let mut cargo = Command::new(env!("CARGO"));
+    cargo.arg("build").arg("--lib")
+        .arg("--manifest-path").arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+    if library_dir.ends_with("release") {
+        cargo.arg("--release");
+    }
+    if !cargo.status().map(|status| status.success()).unwrap_or(false) {
+        return false;
+    }
+    let library = format!("{}parinfer_rust{}", env::consts::DLL_PREFIX, env::consts::DLL_SUFFIX);
+    library_dir.join(library).exists()
+}
+
+#[test]
+pub fn c_api() {
+    let compiler = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let library_dir = library_dir();
+    let executable = library_dir.join("parinfer_c_api_test");
+
+    if !build_library(&library_dir) {
+        eprintln!("skipping C API test: no shared library in {}", library_dir.display());
+        return;
+    }
+
+    let compiled = match Command::new(&compiler)
+        .arg(root.join("tests/c/api_test.c"))
+        .arg("-I").arg(&root)
+        .arg("-L").arg(&library_dir)
+        .arg("-lparinfer_rust")
+        .arg("-o").arg(&executable)
+        .status()
+    {
+        Ok(status) => status,
+        Err(_) => {
+            eprintln!("skipping C API test: no C compiler ({})", compiler);
+            return;
+        }
+    };
+    assert!(compiled.success(), "unable to compile tests/c/api_test.c");
+
+    let output = Command::new(&executable)
+        .env("LD_LIBRARY_PATH", &library_dir)
+        .env("DYLD_LIBRARY_PATH", &library_dir)
+        .output()
+        .expect("unable to run the C API test");
+    assert!(output.status.success(), "C API test failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
+
+#[test]
+pub fn header_is_up_to_date() {
+    let cbindgen = env::var("CBINDGEN").unwrap_or_else(|_| String::from("cbindgen"));
+    let verified = match Command::new(&cbindgen)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(&["--quiet", "--config", "cbindgen.toml", "--output", "parinfer_rust.h", "--verify"])
+        .status()
+    {
+        Ok(status) => status,
+        Err(_) => {
+            eprintln!("skipping header check: no cbindgen ({})", cbindgen);
+            return;
+        }
+    };
+    assert!(verified.success(), "parinfer_rust.h is out of date; run `make header`");
+}
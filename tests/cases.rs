@@ -104,6 +104,10 @@ impl Case {
             }
         }
 
+        if let Some(ref parens) = self.result.parens {
+            check_parens(self.source.line_no, parens, &answer["parens"]);
+        }
+
         if let Some(ref trails) = self.result.paren_trails {
             assert_eq!(
                 trails.len(),
@@ -132,6 +136,48 @@ impl Case {
     }
 }
 
+fn check_parens(line_no: LineNumber, expected: &[Paren], actual: &serde_json::Value) {
+    assert_eq!(
+        expected.len(),
+        actual.as_array().unwrap().len(),
+        "case {}: paren count",
+        line_no
+    );
+    for (expected, actual) in expected.iter().zip(actual.as_array().unwrap().iter()) {
+        assert_eq!(
+            json!(expected.ch), actual["ch"],
+            "case {}: paren ch",
+            line_no
+        );
+        assert_eq!(
+            json!(expected.x), actual["x"],
+            "case {}: paren x",
+            line_no
+        );
+        assert_eq!(
+            json!(expected.line_no), actual["lineNo"],
+            "case {}: paren line",
+            line_no
+        );
+        assert_eq!(
+            json!(expected.closer.ch), actual["closer"]["ch"],
+            "case {}: closer ch",
+            line_no
+        );
+        assert_eq!(
+            json!(expected.closer.x), actual["closer"]["x"],
+            "case {}: closer x",
+            line_no
+        );
+        assert_eq!(
+            json!(expected.closer.line_no), actual["closer"]["lineNo"],
+            "case {}: closer line",
+            line_no
+        );
+        check_parens(line_no, &expected.children, &actual["children"]);
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct Options {
@@ -153,6 +199,14 @@ struct Options {
     scheme_sexp_comments: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     janet_long_strings: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    selection_start_line: Option<LineNumber>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partial_result: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    force_balance: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    return_parens: Option<bool>,
 }
 
 
@@ -184,6 +238,7 @@ struct CaseResult {
     cursor_line: Option<LineNumber>,
     tab_stops: Option<Vec<TabStop>>,
     paren_trails: Option<Vec<ParenTrail>>,
+    parens: Option<Vec<Paren>>,
 }
 
 #[derive(Deserialize)]
@@ -194,6 +249,25 @@ struct ParenTrail {
     end_x: Column,
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Paren {
+    ch: String,
+    x: Column,
+    line_no: LineNumber,
+    closer: Closer,
+    #[serde(default)]
+    children: Vec<Paren>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Closer {
+    ch: String,
+    x: Column,
+    line_no: LineNumber,
+}
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct Error {
@@ -275,7 +349,8 @@ pub fn composed_unicode_graphemes_count_as_a_single_character() {
             cursor_x: None,
             cursor_line: None,
             tab_stops: None,
-            paren_trails: None
+            paren_trails: None,
+            parens: None
         },
         source: Source {
             line_no: 0
@@ -290,7 +365,11 @@ pub fn composed_unicode_graphemes_count_as_a_single_character() {
             scheme_sexp_comments: None,
             janet_long_strings: None,
             prev_cursor_x: None,
-            prev_cursor_line: None
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
         }
     };
     let input = json!({
@@ -313,7 +392,8 @@ pub fn graphemes_in_changes_are_counted_correctly() {
             cursor_x: Some(10),
             cursor_line: Some(1),
             tab_stops: None,
-            paren_trails: None
+            paren_trails: None,
+            parens: None
         },
         source: Source {
             line_no: 0
@@ -335,7 +415,11 @@ pub fn graphemes_in_changes_are_counted_correctly() {
             scheme_sexp_comments: None,
             janet_long_strings: None,
             prev_cursor_x: None,
-            prev_cursor_line: None
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
         }
     };
     let input = json!({
@@ -345,6 +429,23 @@ pub fn graphemes_in_changes_are_counted_correctly() {
     }).to_string();
     let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
     case.check2(answer);
+
+    // The change starts after "(é", which is two UTF-8 bytes wide.
+    for &(encoding, x) in [("utf16", 2), ("utf8-bytes", 3), ("codepoints", 2)].iter() {
+        let input = json!({
+            "mode": "smart",
+            "text": &case.text,
+            "options": {
+                "cursorX": 7,
+                "cursorLine": 1,
+                "changes": [{"lineNo": 0, "x": x, "oldText": "éé", "newText": "xyååå"}],
+                "positionEncoding": encoding
+            }
+        }).to_string();
+        let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+        assert_eq!(answer["text"], json!(case.result.text), "{}", encoding);
+        assert_eq!(answer["cursorX"], json!(10), "{}", encoding);
+    }
 }
 
 #[test]
@@ -358,7 +459,8 @@ pub fn wide_characters() {
             cursor_x: Some(17),
             cursor_line: Some(1),
             tab_stops: None,
-            paren_trails: None
+            paren_trails: None,
+            parens: None
         },
         source: Source {
             line_no: 0
@@ -380,7 +482,11 @@ pub fn wide_characters() {
             scheme_sexp_comments: None,
             janet_long_strings: None,
             prev_cursor_x: None,
-            prev_cursor_line: None
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
         }
     };
     let input = json!({
@@ -390,6 +496,31 @@ pub fn wide_characters() {
     }).to_string();
     let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
     case.check2(answer);
+
+    // The "{" is at column 16, after five double-width characters.
+    for &(encoding, x) in [("graphemes", 16), ("utf16", 11), ("utf8-bytes", 21), ("codepoints", 11)].iter() {
+        let input = json!({
+            "mode": "smart",
+            "text": &case.result.text,
+            "options": {
+                "cursorX": 17,
+                "cursorLine": 1,
+                "positionEncoding": encoding
+            }
+        }).to_string();
+        let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+        assert_eq!(answer["tabStops"][1]["x"], json!(x), "{}", encoding);
+        assert_eq!(answer["tabStops"][1]["argX"], json!(x + 6), "{}", encoding);
+        assert_eq!(answer["cursorX"], json!(17), "{}", encoding);
+    }
+
+    let input = json!({
+        "mode": "paren",
+        "text": "(ｗｏｒｌｄ foo))",
+        "options": {"positionEncoding": "utf16"}
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["error"]["x"], json!(11));
 }
 
 #[test]
@@ -403,7 +534,8 @@ pub fn lisp_vline_symbols() {
             cursor_x: None,
             cursor_line: None,
             tab_stops: None,
-            paren_trails: None
+            paren_trails: None,
+            parens: None
         },
         source: Source {
             line_no: 0
@@ -418,7 +550,11 @@ pub fn lisp_vline_symbols() {
             scheme_sexp_comments: None,
             janet_long_strings: None,
             prev_cursor_x: None,
-            prev_cursor_line: None
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
         }
     };
     let input = json!({
@@ -441,7 +577,8 @@ pub fn lisp_sharp_syntax_backtrack() {
             cursor_x: None,
             cursor_line: None,
             tab_stops: None,
-            paren_trails: None
+            paren_trails: None,
+            parens: None
         },
         source: Source {
             line_no: 0
@@ -456,7 +593,11 @@ pub fn lisp_sharp_syntax_backtrack() {
             scheme_sexp_comments: None,
             janet_long_strings: None,
             prev_cursor_x: None,
-            prev_cursor_line: None
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
         }
     };
     let input = json!({
@@ -479,7 +620,8 @@ pub fn lisp_block_comments() {
             cursor_x: None,
             cursor_line: None,
             tab_stops: None,
-            paren_trails: None
+            paren_trails: None,
+            parens: None
         },
         source: Source {
             line_no: 0
@@ -494,7 +636,11 @@ pub fn lisp_block_comments() {
             scheme_sexp_comments: None,
             janet_long_strings: None,
             prev_cursor_x: None,
-            prev_cursor_line: None
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
         }
     };
     let input = json!({
@@ -517,7 +663,8 @@ pub fn guile_block_comments() {
             cursor_x: None,
             cursor_line: None,
             tab_stops: None,
-            paren_trails: None
+            paren_trails: None,
+            parens: None
         },
         source: Source {
             line_no: 0
@@ -532,7 +679,11 @@ pub fn guile_block_comments() {
             scheme_sexp_comments: None,
             janet_long_strings: None,
             prev_cursor_x: None,
-            prev_cursor_line: None
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
         }
     };
     let input = json!({
@@ -555,7 +706,8 @@ pub fn scheme_sexp_comments() {
             cursor_x: None,
             cursor_line: None,
             tab_stops: None,
-            paren_trails: None
+            paren_trails: None,
+            parens: None
         },
         source: Source {
             line_no: 0
@@ -570,7 +722,11 @@ pub fn scheme_sexp_comments() {
             scheme_sexp_comments: Some(true),
             janet_long_strings: None,
             prev_cursor_x: None,
-            prev_cursor_line: None
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
         }
     };
     let input = json!({
@@ -593,7 +749,8 @@ pub fn janet_long_strings() {
             cursor_x: None,
             cursor_line: None,
             tab_stops: None,
-            paren_trails: None
+            paren_trails: None,
+            parens: None
         },
         source: Source {
             line_no: 0
@@ -608,7 +765,11 @@ pub fn janet_long_strings() {
             scheme_sexp_comments: None,
             janet_long_strings: Some(true),
             prev_cursor_x: None,
-            prev_cursor_line: None
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
         }
     };
     let input = json!({
@@ -619,3 +780,853 @@ pub fn janet_long_strings() {
     let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
     case.check2(answer);
 }
+
+#[test]
+pub fn return_parens() {
+    let case = Case {
+        text: String::from("(defn foo [a]\n  (let [b (inc a\n    b"),
+        result: CaseResult {
+            text: String::from("(defn foo [a]\n  (let [b (inc a)]\n    b))"),
+            success: true,
+            error: None,
+            cursor_x: None,
+            cursor_line: None,
+            tab_stops: None,
+            paren_trails: None,
+            parens: Some(vec![
+                Paren {
+                    ch: String::from("("),
+                    x: 0,
+                    line_no: 0,
+                    closer: Closer { ch: String::from(")"), x: 6, line_no: 2 },
+                    children: vec![
+                        Paren {
+                            ch: String::from("["),
+                            x: 10,
+                            line_no: 0,
+                            closer: Closer { ch: String::from("]"), x: 12, line_no: 0 },
+                            children: vec![]
+                        },
+                        Paren {
+                            ch: String::from("("),
+                            x: 2,
+                            line_no: 1,
+                            closer: Closer { ch: String::from(")"), x: 5, line_no: 2 },
+                            children: vec![
+                                Paren {
+                                    ch: String::from("["),
+                                    x: 7,
+                                    line_no: 1,
+                                    closer: Closer { ch: String::from("]"), x: 17, line_no: 1 },
+                                    children: vec![
+                                        Paren {
+                                            ch: String::from("("),
+                                            x: 10,
+                                            line_no: 1,
+                                            closer: Closer { ch: String::from(")"), x: 16, line_no: 1 },
+                                            children: vec![]
+                                        }
+                                    ]
+                                }
+                            ]
+                        }
+                    ]
+                }
+            ])
+        },
+        source: Source {
+            line_no: 0
+        },
+        options: Options {
+            cursor_x: None,
+            cursor_line: None,
+            changes: None,
+            lisp_vline_symbols: None,
+            lisp_block_comments: None,
+            guile_block_comments: None,
+            scheme_sexp_comments: None,
+            janet_long_strings: None,
+            prev_cursor_x: None,
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: None,
+            return_parens: Some(true)
+        }
+    };
+    let input = json!({
+        "mode": "smart",
+        "text": &case.text,
+        "options": &case.options
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    case.check2(answer);
+}
+
+#[test]
+pub fn partial_result() {
+    let case = Case {
+        text: String::from("(foo\nbar"),
+        result: CaseResult {
+            text: String::from("(foo\n bar"),
+            success: false,
+            error: Some(Error {
+                name: String::from("unclosed-paren"),
+                line_no: 0,
+                x: 0
+            }),
+            cursor_x: None,
+            cursor_line: None,
+            tab_stops: None,
+            paren_trails: None,
+            parens: None
+        },
+        source: Source {
+            line_no: 0
+        },
+        options: Options {
+            cursor_x: None,
+            cursor_line: None,
+            changes: None,
+            lisp_vline_symbols: None,
+            lisp_block_comments: None,
+            guile_block_comments: None,
+            scheme_sexp_comments: None,
+            janet_long_strings: None,
+            prev_cursor_x: None,
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: Some(true),
+            force_balance: None,
+            return_parens: None
+        }
+    };
+    let input = json!({
+        "mode": "paren",
+        "text": &case.text,
+        "options": &case.options
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    case.check2(answer);
+}
+
+#[test]
+pub fn force_balance() {
+    let case = Case {
+        text: String::from("(foo\n  }bar)"),
+        result: CaseResult {
+            text: String::from("(foo\n  bar)"),
+            success: true,
+            error: None,
+            cursor_x: None,
+            cursor_line: None,
+            tab_stops: None,
+            paren_trails: None,
+            parens: None
+        },
+        source: Source {
+            line_no: 0
+        },
+        options: Options {
+            cursor_x: None,
+            cursor_line: None,
+            changes: None,
+            lisp_vline_symbols: None,
+            lisp_block_comments: None,
+            guile_block_comments: None,
+            scheme_sexp_comments: None,
+            janet_long_strings: None,
+            prev_cursor_x: None,
+            prev_cursor_line: None,
+            selection_start_line: None,
+            partial_result: None,
+            force_balance: Some(true),
+            return_parens: None
+        }
+    };
+    let input = json!({
+        "mode": "indent",
+        "text": &case.text,
+        "options": &case.options
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    case.check2(answer);
+}
+
+#[test]
+pub fn selection_start_line() {
+    let case = Case {
+        text: String::from("(let [a 1\n      b 2]\n  (+ a b))"),
+        result: CaseResult {
+            text: String::from("(let [a 1\n      b 2]\n  (+ a b))"),
+            success: true,
+            error: None,
+            cursor_x: Some(0),
+            cursor_line: Some(0),
+            tab_stops: Some(vec![
+                TabStop {
+                    ch: String::from("("),
+                    x: 0,
+                    line_no: 0,
+                    arg_x: None
+                },
+                TabStop {
+                    ch: String::from("["),
+                    x: 5,
+                    line_no: 0,
+                    arg_x: Some(8)
+                }
+            ]),
+            paren_trails: None,
+            parens: None
+        },
+        source: Source {
+            line_no: 0
+        },
+        options: Options {
+            cursor_x: Some(0),
+            cursor_line: Some(0),
+            changes: None,
+            lisp_vline_symbols: None,
+            lisp_block_comments: None,
+            guile_block_comments: None,
+            scheme_sexp_comments: None,
+            janet_long_strings: None,
+            prev_cursor_x: None,
+            prev_cursor_line: None,
+            selection_start_line: Some(1),
+            partial_result: None,
+            force_balance: None,
+            return_parens: None
+        }
+    };
+    let input = json!({
+        "mode": "indent",
+        "text": &case.text,
+        "options": &case.options
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    case.check2(answer);
+}
+
+#[test]
+pub fn typed_api() {
+    use parinfer_rust::api;
+
+    let answer = {
+        let text = String::from("(defn foo\n  [a b\n  ret");
+        let options = api::Options::default().with_cursor(0, 2);
+        api::indent_mode(&text, &options)
+    };
+    assert!(answer.success);
+    assert_eq!(answer.text, "(defn foo\n  [a b]\n  ret)");
+    assert_eq!(answer.cursor_line, Some(2));
+
+    let options = api::Options::default()
+        .with_cursor(2, 1)
+        .with_prev_cursor(4, 1)
+        .with_prev_text("(foo\n    bar)");
+    let answer = api::smart_mode("(foo\n  bar)", &options);
+    assert!(answer.success);
+    assert_eq!(answer.text, "(foo\n  bar)");
+
+    let request: api::Request = serde_json::from_str(r#"{
+        "mode": "paren",
+        "text": "(foo\nbar)",
+        "options": {}
+    }"#).unwrap();
+    assert_eq!(request.mode, api::Mode::Paren);
+    assert_eq!(api::process(&request).text, "(foo\n bar)");
+}
+
+#[test]
+pub fn owned_answer_outlives_request() {
+    use parinfer_rust::api;
+
+    let answer: api::OwnedAnswer = {
+        let request = api::Request {
+            mode: api::Mode::Indent,
+            text: String::from("(foo [bar\n  baz"),
+            options: api::Options::default().with_cursor(0, 0).with_return_parens(true),
+        };
+        api::process(&request)
+    };
+    assert_eq!(answer.text, "(foo [bar]\n  baz)");
+    assert_eq!(answer.tab_stops.len(), 0);
+    assert_eq!(answer.parens.len(), 1);
+    assert_eq!(answer.parens[0].ch, "(");
+    assert_eq!(answer.parens[0].children[0].ch, "[");
+    assert_eq!(answer.parens[0].closer.as_ref().unwrap().ch, ")");
+}
+
+#[test]
+pub fn document_tracks_edits() {
+    use parinfer_rust::api;
+
+    let mut document = api::Document::new(api::Mode::Smart,
+                                          String::from("(foo\n  bar)"),
+                                          api::Options::default().with_cursor(0, 0));
+    let change = api::Change {
+        x: 0,
+        line_no: 0,
+        old_text: String::from(""),
+        new_text: String::from("("),
+    };
+    document.edit(change.clone()).unwrap();
+    document.move_cursor(1, 0);
+    let expected = api::smart_mode("((foo\n  bar)", &api::Options::default()
+                                   .with_cursor(1, 0)
+                                   .with_prev_cursor(0, 0)
+                                   .with_changes(vec![change]));
+    {
+        let answer = document.process();
+        assert!(answer.success);
+        assert_eq!(answer.text, expected.text);
+        assert_eq!(answer.text, "((foo\n   bar))");
+    }
+    assert_eq!(document.text(), "((foo\n   bar))");
+    assert_eq!(document.cursor(), (Some(1), Some(0)));
+
+    let update: api::DocumentUpdate = serde_json::from_str(r#"{
+        "changes": [{"lineNo": 1, "x": 3, "oldText": "bar", "newText": "baz"}],
+        "cursorX": 6,
+        "cursorLine": 1
+    }"#).unwrap();
+    assert_eq!(document.update(update).unwrap().text, "((foo\n   baz))");
+
+    let error = document.edit(api::Change {
+        x: 3,
+        line_no: 1,
+        old_text: String::from("bar"),
+        new_text: String::from(""),
+    }).unwrap_err();
+    assert_eq!(error.name, api::ErrorName::InvalidChange);
+    assert_eq!(document.text(), "((foo\n   baz))");
+
+    // The first change applies, but the second doesn't, so neither is kept.
+    let update: api::DocumentUpdate = serde_json::from_str(r#"{
+        "changes": [{"lineNo": 1, "x": 3, "oldText": "baz", "newText": "qux"},
+                    {"lineNo": 1, "x": 3, "oldText": "baz", "newText": ""}]
+    }"#).unwrap();
+    assert_eq!(document.update(update).unwrap_err().name, api::ErrorName::InvalidChange);
+    assert_eq!(document.text(), "((foo\n   baz))");
+    assert_eq!(document.process().text, "((foo\n   baz))");
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+pub fn c_document_api() {
+    unsafe {
+        parinfer_rust::INITIALIZED = true;
+        let request = CString::new(r#"{"mode": "indent", "text": "(def x", "options": {}}"#).unwrap();
+        let document = parinfer_rust::parinfer_document_new(request.as_ptr());
+        assert!(!document.is_null());
+
+        let update = CString::new(r#"{
+            "changes": [{"lineNo": 0, "x": 6, "oldText": "", "newText": " 1"}],
+            "cursorX": 8,
+            "cursorLine": 0
+        }"#).unwrap();
+        let out = CStr::from_ptr(parinfer_rust::parinfer_document_update(document, update.as_ptr()));
+        let answer: serde_json::Value = serde_json::from_str(out.to_str().unwrap()).unwrap();
+        assert_eq!(answer["success"], json!(true));
+        assert_eq!(answer["text"], json!("(def x 1)"));
+
+        let update = CString::new(r#"{"changes": [{"lineNo": 3, "x": 0, "oldText": "", "newText": "("}]}"#).unwrap();
+        let out = CStr::from_ptr(parinfer_rust::parinfer_document_update(document, update.as_ptr()));
+        let answer: serde_json::Value = serde_json::from_str(out.to_str().unwrap()).unwrap();
+        assert_eq!(answer["success"], json!(false));
+        assert_eq!(answer["error"]["name"], json!("invalid-change"));
+
+        parinfer_rust::parinfer_document_free(document);
+
+        let bad_request = CString::new("{").unwrap();
+        assert!(parinfer_rust::parinfer_document_new(bad_request.as_ptr()).is_null());
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+pub fn c_owned_answers() {
+    unsafe {
+        parinfer_rust::INITIALIZED = true;
+        let request = CString::new(r#"{"mode": "indent", "text": "(def x", "options": {}}"#).unwrap();
+        let answer = parinfer_rust::parinfer_run(request.as_ptr());
+        let text = CStr::from_ptr(answer).to_str().unwrap().to_string();
+        parinfer_rust::parinfer_free(answer);
+        let json: serde_json::Value = serde_json::from_str(&text).unwrap();
+        assert_eq!(json["text"], json!("(def x)"));
+
+        let mut buffer = vec![1 as std::os::raw::c_char; text.len() + 1];
+        assert_eq!(parinfer_rust::parinfer_run_into(request.as_ptr(), buffer.as_mut_ptr(), 4), text.len() + 1);
+        assert_eq!(buffer[0], 0);
+        assert_eq!(parinfer_rust::parinfer_run_into(request.as_ptr(), buffer.as_mut_ptr(), buffer.len()), text.len() + 1);
+        assert_eq!(CStr::from_ptr(buffer.as_ptr()).to_str().unwrap(), text);
+
+        assert_eq!(parinfer_rust::parinfer_api_version(), parinfer_rust::PARINFER_API_VERSION);
+        assert_eq!(CStr::from_ptr(parinfer_rust::parinfer_version()).to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+pub fn c_structured_api() {
+    unsafe {
+        parinfer_rust::INITIALIZED = true;
+        let mut options = std::mem::MaybeUninit::<parinfer_rust::ParinferOptions>::uninit();
+        parinfer_rust::parinfer_options_init(options.as_mut_ptr());
+        let mut options = options.assume_init();
+        options.cursor_x = 5;
+        options.cursor_line = 0;
+        options.position_encoding = parinfer_rust::PARINFER_POSITION_UTF8_BYTES;
+
+        let text = "(λ x";
+        let answer = parinfer_rust::parinfer_process(parinfer_rust::PARINFER_MODE_SMART,
+                                                     text.as_ptr() as *const std::os::raw::c_char,
+                                                     text.len(), &options);
+        assert!(parinfer_rust::parinfer_answer_success(answer));
+        let mut length = 0;
+        let out = parinfer_rust::parinfer_answer_text(answer, &mut length);
+        let out = std::slice::from_raw_parts(out as *const u8, length);
+        assert_eq!(std::str::from_utf8(out).unwrap(), "(λ x)");
+        assert_eq!(parinfer_rust::parinfer_answer_cursor_x(answer), 5);
+        parinfer_rust::parinfer_answer_free(answer);
+
+        options.comment_char = 0xD800;
+        let answer = parinfer_rust::parinfer_process(parinfer_rust::PARINFER_MODE_SMART,
+                                                     text.as_ptr() as *const std::os::raw::c_char,
+                                                     text.len(), &options);
+        assert!(!parinfer_rust::parinfer_answer_success(answer));
+        let mut error = std::mem::MaybeUninit::uninit();
+        assert!(parinfer_rust::parinfer_answer_error(answer, error.as_mut_ptr()));
+        let error = error.assume_init();
+        assert_eq!(CStr::from_ptr(error.message).to_str().unwrap(),
+                   "The comment character is not a Unicode code point.");
+        parinfer_rust::parinfer_answer_free(answer);
+    }
+}
+
+#[test]
+pub fn document_matches_full_runs() {
+    use parinfer_rust::api;
+
+    let mut text = String::new();
+    for i in 0..12 {
+        text.push_str(&format!("(defn f{} [x]\n  (let [y [x \"s)\"]]\n    {{:a y}}))\n\n; c{}\n", i, i));
+    }
+    let inserts = ["(", ")", "]", " ", "\n", "x", "\"\"", "[", "  "];
+
+    for &mode in &[api::Mode::Indent, api::Mode::Paren, api::Mode::Smart] {
+        for &return_parens in &[false, true] {
+            let options = api::Options::default().with_return_parens(return_parens);
+            let mut document = api::Document::new(mode, text.clone(), options.clone());
+            document.process();
+            let mut last_good = String::from(document.text());
+
+            let mut seed: usize = 7;
+            for _ in 0..150 {
+                seed = (seed * 1103515245 + 12345) % 2147483648;
+                let lines: Vec<&str> = document.text().split('\n').collect();
+                let line_no = seed % lines.len();
+                let x = (seed / 7) % (lines[line_no].chars().count() + 1);
+                let new_text = inserts[(seed / 13) % inserts.len()];
+                let old_text: String = if seed % 3 == 1 {
+                    lines[line_no].chars().skip(x).take(1).collect()
+                } else {
+                    String::new()
+                };
+                let cursor_x = if new_text == "\n" { 0 } else { x + new_text.len() };
+                let cursor_line = if new_text == "\n" { line_no + 1 } else { line_no };
+
+                document.edit(api::Change {
+                    x,
+                    line_no,
+                    old_text,
+                    new_text: String::from(new_text),
+                }).unwrap();
+                document.move_cursor(cursor_x, cursor_line);
+
+                let expected = api::run(mode, document.text(), document.options());
+                let success = {
+                    let actual = document.process();
+                    assert_eq!(serde_json::to_value(actual).unwrap(),
+                               serde_json::to_value(&expected).unwrap());
+                    actual.success
+                };
+
+                // Start over from the last good text rather than piling edits onto a broken one.
+                if success {
+                    last_good = String::from(document.text());
+                } else {
+                    document = api::Document::new(mode, last_good.clone(), options.clone());
+                    document.process();
+                }
+            }
+        }
+    }
+}
+
+#[test]
+pub fn collect_errors() {
+    let input = json!({
+        "mode": "paren",
+        "text": "(foo))\n(bar]\n(baz",
+        "options": {
+            "collectErrors": true
+        }
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["success"], json!(false));
+    assert_eq!(answer["text"], json!("(foo))\n(bar]\n(baz"));
+    assert_eq!(answer["error"]["name"], json!("unmatched-close-paren"));
+    let errors: Vec<(String, LineNumber, Column)> = answer["errors"].as_array().unwrap().iter()
+        .map(|e| (String::from(e["name"].as_str().unwrap()),
+                  e["lineNo"].as_u64().unwrap() as LineNumber,
+                  e["x"].as_u64().unwrap() as Column))
+        .collect();
+    assert_eq!(errors, vec![
+        (String::from("unmatched-close-paren"), 0, 5),
+        (String::from("unmatched-close-paren"), 1, 4),
+        (String::from("unclosed-paren"), 2, 0),
+    ]);
+
+    let input = json!({
+        "mode": "indent",
+        "text": "(foo ; \"\n bar)\n(baz ; \"\n qux)",
+        "options": {}
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["errors"].as_array().unwrap().len(), 1);
+    assert_eq!(answer["errors"][0], answer["error"]);
+}
+
+#[test]
+pub fn collect_errors_stop_at_invalid_state() {
+    let cases = [
+        ("(foo))\n(a \"b\n(c)", "unclosed-quote"),
+        ("(foo))\n(a \\\n(c)", "eol-backslash"),
+    ];
+    for mode in ["indent", "paren", "smart"].iter() {
+        for &(text, name) in cases.iter() {
+            let input = json!({
+                "mode": mode,
+                "text": text,
+                "options": {
+                    "collectErrors": true,
+                    "returnParens": true
+                }
+            }).to_string();
+            let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+            let errors: Vec<&str> = answer["errors"].as_array().unwrap().iter()
+                .map(|e| e["name"].as_str().unwrap())
+                .collect();
+            assert_eq!(answer["success"], json!(false), "{} mode: {:?}", mode, text);
+            assert_eq!(errors.last(), Some(&name), "{} mode: {:?}", mode, text);
+            assert_eq!(answer["errors"][0], answer["error"], "{} mode: {:?}", mode, text);
+        }
+    }
+}
+
+#[test]
+pub fn collect_errors_scan_past_quote_danger() {
+    for mode in ["indent", "paren", "smart"].iter() {
+        let input = json!({
+            "mode": mode,
+            "text": "(foo ; \"\n bar)\n(baz ; \"\n qux)\n(a ; \"\n b))",
+            "options": {
+                "collectErrors": true
+            }
+        }).to_string();
+        let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+        let errors: Vec<(String, LineNumber, Column)> = answer["errors"].as_array().unwrap().iter()
+            .map(|e| (String::from(e["name"].as_str().unwrap()),
+                      e["lineNo"].as_u64().unwrap() as LineNumber,
+                      e["x"].as_u64().unwrap() as Column))
+            .collect();
+        let mut expected = vec![
+            (String::from("quote-danger"), 0, 7),
+            (String::from("quote-danger"), 2, 7),
+            (String::from("quote-danger"), 4, 5),
+        ];
+        // Only paren mode keeps the extra close-paren on the last line.
+        if *mode == "paren" {
+            expected.push((String::from("unmatched-close-paren"), 5, 3));
+        }
+        assert_eq!(answer["success"], json!(false), "{} mode", mode);
+        assert_eq!(errors, expected, "{} mode", mode);
+        assert_eq!(answer["errors"][0], answer["error"], "{} mode", mode);
+    }
+}
+
+#[test]
+pub fn errors_locate_the_open_paren() {
+    let cases = [
+        ("paren", "(foo [a) b)", "unmatched-close-paren", json!({"name": "unmatched-open-paren", "lineNo": 0, "x": 5})),
+        ("indent", "(foo [a) b)", "unmatched-close-paren", json!({"name": "unmatched-open-paren", "lineNo": 0, "x": 5})),
+        ("indent", "(foo\n  (bar\n  ) baz)", "leading-close-paren", json!({"name": "unmatched-open-paren", "lineNo": 1, "x": 2})),
+        ("paren", "(foo))", "unmatched-close-paren", json!(null)),
+    ];
+    for &(mode, text, name, ref extra) in cases.iter() {
+        let input = json!({
+            "mode": mode,
+            "text": text,
+            "options": {}
+        }).to_string();
+        let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+        assert_eq!(answer["error"]["name"], json!(name), "{} mode: {:?}", mode, text);
+        assert_eq!(&answer["error"]["extra"], extra, "{} mode: {:?}", mode, text);
+    }
+}
+
+#[test]
+pub fn paren_pairs_are_configurable() {
+    let cases = [
+        ("indent", json!(["()"]), "(foo [bar\n  baz", "(foo [bar\n  baz)"),
+        ("indent", json!(["()", "<>"]), "<div (foo\n  bar", "<div (foo)\n  bar>"),
+        ("paren", json!(["<>"]), "<a\nb>", "<a\n b>"),
+    ];
+    for &(mode, ref pairs, text, expected) in cases.iter() {
+        let input = json!({
+            "mode": mode,
+            "text": text,
+            "options": {
+                "parenPairs": pairs
+            }
+        }).to_string();
+        let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+        assert_eq!(answer["text"], json!(expected), "{} mode with {}: {:?}", mode, pairs, text);
+    }
+
+    let input = json!({
+        "mode": "paren",
+        "text": "<a (b>\nc)",
+        "options": {
+            "parenPairs": ["()", "<>"]
+        }
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["error"]["name"], json!("unmatched-close-paren"));
+    assert_eq!(answer["error"]["x"], json!(5));
+}
+
+#[test]
+pub fn comment_strings() {
+    let cases = [
+        (json!(["--"]), "(foo -- )\n  bar", "(foo -- )\n  bar)"),
+        (json!(["--"]), "(foo ; )\n  bar", "(foo ;\n  bar)"),
+        (json!([";", "//"]), "(foo // )\n  bar ; )", "(foo // )\n  bar) ; )"),
+        (json!(["#", "#!"]), "#!/usr/bin/env (\n(foo", "#!/usr/bin/env (\n(foo)"),
+    ];
+    for &(ref comments, text, expected) in cases.iter() {
+        let input = json!({
+            "mode": "indent",
+            "text": text,
+            "options": {
+                "commentStrings": comments
+            }
+        }).to_string();
+        let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+        assert_eq!(answer["text"], json!(expected), "comments {}: {:?}", comments, text);
+    }
+}
+
+#[test]
+pub fn tabs() {
+    let cases = [
+        ("indent", json!({"tabWidth": 4}), "(defn foo\n\t(bar\n\t\tbaz", "(defn foo\n    (bar\n        baz))"),
+        ("indent", json!({"tabWidth": 4}), "(a\tb\n  c", "(a  b\n  c)"),
+        ("indent", json!({"tabWidth": 4, "preserveTabs": true}), "(defn foo\n\t(bar\n\t\tbaz", "(defn foo\n\t(bar\n\t\tbaz))"),
+        ("paren", json!({"tabWidth": 4, "preserveTabs": true}), "(defn foo [a\n\t\t  b]\n\t(bar))", "(defn foo [a\n\t\t   b]\n\t(bar))"),
+        ("paren", json!({"tabWidth": 4, "preserveTabs": true}), "(foo\n bar)\n\t\t(baz)", "(foo\n bar)\n(baz)"),
+        ("paren", json!({"tabWidth": 4}), "(foo\n\tbar)", "(foo\n    bar)"),
+    ];
+    for &(mode, ref options, text, expected) in cases.iter() {
+        let input = json!({
+            "mode": mode,
+            "text": text,
+            "options": options
+        }).to_string();
+        let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+        assert_eq!(answer["text"], json!(expected), "{} mode with {}: {:?}", mode, options, text);
+    }
+
+    // Columns count a tab up to the next tab stop.
+    let input = json!({
+        "mode": "indent",
+        "text": "(let [a 1\n\t  b 2)",
+        "options": {"tabWidth": 4, "preserveTabs": true, "cursorX": 10, "cursorLine": 1}
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["text"], json!("(let [a 1\n\t  b 2])"));
+    assert_eq!(answer["parenTrails"][0], json!({"lineNo": 1, "startX": 9, "endX": 11}));
+    assert_eq!(answer["cursorX"], json!(9));
+}
+
+#[test]
+pub fn prev_text_with_several_edits() {
+    // Each edit shifts the lines below it, rather than one change spanning
+    // from the first edit to the last.
+    let input = json!({
+        "mode": "smart",
+        "text": "(foooo (a\n      b))\n(b (c\n      d))",
+        "options": {
+            "prevText": "(foo (a\n      b))\n(bar (c\n      d))",
+            "cursorX": 2,
+            "cursorLine": 2
+        }
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["text"], json!("(foooo (a\n        b))\n(b (c\n    d))"));
+
+    // Two edits on the same line.
+    let input = json!({
+        "mode": "smart",
+        "text": "(foooo (barrr (a\n           b)))",
+        "options": {
+            "prevText": "(foo (bar (a\n           b)))",
+            "cursorX": 2,
+            "cursorLine": 0
+        }
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["text"], json!("(foooo (barrr (a\n               b)))"));
+
+    // The same edits, made right to left.
+    let input = json!({
+        "mode": "smart",
+        "text": "(foooo (barrr (a\n           b)))",
+        "options": {
+            "changes": [
+                {"lineNo": 0, "x": 6, "oldText": "bar", "newText": "barrr"},
+                {"lineNo": 0, "x": 1, "oldText": "foo", "newText": "foooo"}
+            ],
+            "cursorX": 2,
+            "cursorLine": 0
+        }
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["text"], json!("(foooo (barrr (a\n               b)))"));
+}
+
+#[test]
+pub fn multiple_cursors() {
+    // Each cursor holds the parens before it in place.
+    let input = json!({
+        "mode": "indent",
+        "text": "(foo [a])\n  b\n(bar [c])\n  d",
+        "options": {
+            "cursorX": 9,
+            "cursorLine": 0,
+            "cursors": [{"x": 9, "lineNo": 2}]
+        }
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["text"], json!("(foo [a])\n  b\n(bar [c])\n  d"));
+
+    let input = json!({
+        "mode": "indent",
+        "text": "(foo [a])\n  b\n(bar [c])\n  d",
+        "options": {
+            "cursorX": 9,
+            "cursorLine": 0
+        }
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["text"], json!("(foo [a])\n  b\n(bar [c]\n  d)"));
+    assert_eq!(answer["cursors"], json!([]));
+
+    // Every cursor moves with its line.
+    let input = json!({
+        "mode": "paren",
+        "text": "(foo\nbar)\n(baz\nqux)",
+        "options": {
+            "cursorX": 3,
+            "cursorLine": 1,
+            "cursors": [{"x": 3, "lineNo": 3}, {"x": 0, "lineNo": 2}]
+        }
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["text"], json!("(foo\n bar)\n(baz\n qux)"));
+    assert_eq!(answer["cursorX"], json!(4));
+    assert_eq!(answer["cursors"], json!([
+        {"x": 4, "lineNo": 3, "selectionStartLine": null},
+        {"x": 0, "lineNo": 2, "selectionStartLine": null},
+    ]));
+}
+
+#[test]
+pub fn track_positions() {
+    let cases = [
+        ("paren", "(foo\nbar)\n(baz)", json!([[1, 0], [1, 3], [0, 2], [2, 5]]), json!([[1, 1], [1, 4], [0, 2], [2, 5]])),
+        ("indent", "(a)\n b", json!([[0, 3], [0, 1], [1, 2]]), json!([[0, 2], [0, 1], [1, 2]])),
+        ("indent", "(a \t b\n c", json!([[0, 6]]), json!([[0, 6]])),
+    ];
+    for &(mode, text, ref positions, ref expected) in cases.iter() {
+        let input = json!({
+            "mode": mode,
+            "text": text,
+            "options": {
+                "trackPositions": positions
+            }
+        }).to_string();
+        let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+        assert_eq!(&answer["trackedPositions"], expected, "{} mode: {:?}", mode, text);
+    }
+
+    // Positions are left alone when there is no answer.
+    let input = json!({
+        "mode": "paren",
+        "text": "(foo\nbar",
+        "options": {
+            "trackPositions": [[1, 0]]
+        }
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["success"], json!(false));
+    assert_eq!(answer["trackedPositions"], json!([[1, 0]]));
+}
+
+#[test]
+pub fn return_edits() {
+    let cases = [
+        ("indent", "(defn foo\n  [a\n  (bar a", vec![(2, 8, "", "))"), (1, 4, "", "]")]),
+        ("paren", "(foo (bar)\nbaz)", vec![(1, 0, "", " ")]),
+        ("paren", "(let [a 1\n  b 2])", vec![(1, 2, "", "    ")]),
+        ("indent", "(a (b))\n  c", vec![(1, 3, "", ")"), (0, 6, ")", "")]),
+        ("indent", "(foo)", vec![]),
+    ];
+    for &(mode, text, ref expected) in cases.iter() {
+        let input = json!({
+            "mode": mode,
+            "text": text,
+            "options": {"returnEdits": true}
+        }).to_string();
+        let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+        let edits: Vec<(LineNumber, Column, String, String)> = answer["edits"].as_array().unwrap().iter()
+            .map(|e| (e["lineNo"].as_u64().unwrap() as LineNumber,
+                      e["x"].as_u64().unwrap() as Column,
+                      String::from(e["oldText"].as_str().unwrap()),
+                      String::from(e["newText"].as_str().unwrap())))
+            .collect();
+        let expected: Vec<(LineNumber, Column, String, String)> = expected.iter()
+            .map(|&(line_no, x, old_text, new_text)| (line_no, x, String::from(old_text), String::from(new_text)))
+            .collect();
+        assert_eq!(edits, expected, "{} mode: {:?}", mode, text);
+
+        // Each edit is positioned in the original text.
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        for (line_no, x, old_text, new_text) in edits {
+            assert_eq!(&lines[line_no][x..x + old_text.len()], old_text);
+            lines[line_no].replace_range(x..x + old_text.len(), &new_text);
+        }
+        assert_eq!(json!(lines.join("\n")), answer["text"]);
+    }
+
+    let input = json!({
+        "mode": "indent",
+        "text": "(foo)",
+        "options": {}
+    }).to_string();
+    let answer: serde_json::Value = serde_json::from_str(&run(&input)).unwrap();
+    assert_eq!(answer["edits"], json!([]));
+}